@@ -0,0 +1,124 @@
+// derive macros for rusty_deque's IntoValue and FromValue conversion traits
+// a struct is converted to and from a list with one element per field, in declaration order
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Index};
+
+#[proc_macro_derive(IntoValue)]
+pub fn derive_into_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match struct_fields(&input) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let name = &input.ident;
+    let generics = bound_params(
+        &input.generics,
+        parse_quote!(::rusty_deque::evaluator::eval_convert::IntoValue),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let values: Vec<TokenStream2> = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|field| {
+                let ident = &field.ident;
+                quote! { ::rusty_deque::evaluator::eval_convert::IntoValue::into_value(self.#ident) }
+            })
+            .collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                quote! { ::rusty_deque::evaluator::eval_convert::IntoValue::into_value(self.#index) }
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    quote! {
+        impl #impl_generics ::rusty_deque::evaluator::eval_convert::IntoValue for #name #ty_generics #where_clause {
+            fn into_value(self) -> ::rusty_deque::evaluator::eval_value::Value {
+                ::rusty_deque::evaluator::eval_value::Value::List(vec![#(#values),*])
+            }
+        }
+    }
+    .into()
+}
+
+#[proc_macro_derive(FromValue)]
+pub fn derive_from_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match struct_fields(&input) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let name = &input.ident;
+    let generics = bound_params(
+        &input.generics,
+        parse_quote!(::rusty_deque::evaluator::eval_convert::FromValue),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let len = fields.len();
+    let vars: Vec<_> = (0..len).map(|i| format_ident!("field_{}", i)).collect();
+    let construct = match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|field| &field.ident);
+            quote! { #name { #(#idents: #vars),* } }
+        }
+        Fields::Unnamed(_) => quote! { #name(#(#vars),*) },
+        Fields::Unit => quote! { #name },
+    };
+    let error = format!("expected List of {} elements for {}", len, name);
+
+    quote! {
+        impl #impl_generics ::rusty_deque::evaluator::eval_convert::FromValue for #name #ty_generics #where_clause {
+            fn from_value(
+                val: ::rusty_deque::evaluator::eval_value::Value,
+            ) -> ::std::result::Result<Self, ::std::string::String> {
+                match val {
+                    ::rusty_deque::evaluator::eval_value::Value::List(list) if list.len() == #len => {
+                        #[allow(unused_mut, unused_variables)]
+                        let mut iter = list.into_iter();
+                        #(
+                            let #vars = ::rusty_deque::evaluator::eval_convert::FromValue::from_value(
+                                iter.next().unwrap(),
+                            )?;
+                        )*
+                        Ok(#construct)
+                    }
+                    _ => Err(#error.to_string()),
+                }
+            }
+        }
+    }
+    .into()
+}
+
+// only structs can be derived; there is no obvious list encoding for enums
+fn struct_fields(input: &DeriveInput) -> syn::Result<&Fields> {
+    match &input.data {
+        Data::Struct(data) => Ok(&data.fields),
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            "IntoValue and FromValue can only be derived for structs",
+        )),
+    }
+}
+
+// requires every type parameter to implement the derived trait, since the fields hold them
+fn bound_params(generics: &Generics, bound: syn::Path) -> Generics {
+    let mut generics = generics.clone();
+    let params: Vec<_> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #bound));
+    }
+    generics
+}
@@ -0,0 +1,104 @@
+use rusty_deque::evaluator::eval::*;
+use rusty_deque::evaluator::eval_convert::*;
+use rusty_deque::evaluator::eval_value::Value;
+use rusty_deque::parser;
+use rusty_deque_derive::{FromValue, IntoValue};
+
+#[derive(Debug, PartialEq, IntoValue, FromValue)]
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+#[derive(Debug, PartialEq, IntoValue, FromValue)]
+struct Named(String, Option<f64>);
+
+#[derive(Debug, PartialEq, IntoValue, FromValue)]
+struct Unit;
+
+#[derive(Debug, PartialEq, IntoValue, FromValue)]
+struct Pair<T> {
+    first: T,
+    rest: Vec<T>,
+}
+
+#[test]
+fn test_derive_named_struct() {
+    let point = Point { x: 1, y: -2 };
+    let val = point.into_value();
+    assert_eq!(val, Value::List(vec![Value::Int(1), Value::Int(-2)]));
+    assert_eq!(Point::from_value(val), Ok(Point { x: 1, y: -2 }));
+}
+
+#[test]
+fn test_derive_tuple_struct() {
+    let named = Named("hi".to_string(), Some(1.5));
+    let val = named.into_value();
+    assert_eq!(
+        val,
+        Value::List(vec![
+            Value::List(vec![Value::Char('h'), Value::Char('i')]),
+            Value::List(vec![Value::Float(1.5)]),
+        ])
+    );
+    assert_eq!(
+        Named::from_value(val),
+        Ok(Named("hi".to_string(), Some(1.5)))
+    );
+}
+
+#[test]
+fn test_derive_unit_struct() {
+    assert_eq!(Unit.into_value(), Value::List(vec![]));
+    assert_eq!(Unit::from_value(Value::List(vec![])), Ok(Unit));
+}
+
+#[test]
+fn test_derive_generic_struct() {
+    let pair = Pair {
+        first: 'a',
+        rest: vec!['b'],
+    };
+    let val = pair.into_value();
+    assert_eq!(
+        val,
+        Value::List(vec![Value::Char('a'), Value::List(vec![Value::Char('b')])])
+    );
+    assert_eq!(
+        Pair::<char>::from_value(val),
+        Ok(Pair {
+            first: 'a',
+            rest: vec!['b'],
+        })
+    );
+    assert!(
+        Pair::<i64>::from_value(Value::List(vec![Value::Char('a'), Value::List(vec![])])).is_err()
+    );
+}
+
+#[test]
+fn test_derive_errors() {
+    assert!(Point::from_value(Value::Int(1)).is_err());
+    assert!(Point::from_value(Value::List(vec![Value::Int(1)])).is_err());
+    assert!(Point::from_value(Value::List(vec![Value::Int(1), Value::Bool(true)])).is_err());
+}
+
+#[test]
+fn test_derive_host_fn() {
+    let code = "[1, 2]~ [10, 20]~ add~";
+    let code = parser::par::parse_source(code).unwrap();
+    let mut interp = Interpreter::new().with_host_fn(
+        "add",
+        HostFn::typed_binary(|a: Point, b: Point| {
+            Ok(Point {
+                x: a.x + b.x,
+                y: a.y + b.y,
+            })
+        }),
+    );
+    interp.run(code).unwrap();
+    assert_eq!(
+        interp.deque.into_iter().collect::<Vec<_>>(),
+        vec![Point { x: 11, y: 22 }.into_value()]
+    );
+}
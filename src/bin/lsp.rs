@@ -0,0 +1,464 @@
+// a language server for .deque files, speaking the Language Server Protocol over stdio
+// it provides diagnostics, including the checker's warnings, hover documentation and completion for instructions,
+// bracket highlighting, folding and formatting
+// go-to-definition and completion of user definitions are not provided: the language has no
+// way to name a value or a block, so there are no definitions to find. definitionProvider
+// isn't advertised, so editors don't ask. they need a binding form in the language first
+// the server can't see the host functions a program embedding the interpreter registers, so a
+// client lists their names in initializationOptions, as {"instructions": ["name", ...]};
+// those names aren't reported as unknown and are completed like the builtins
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value as Json};
+
+use rusty_deque::checker::check::check_code;
+use rusty_deque::evaluator::eval_docs::{instr_doc, INSTR_DOCS};
+use rusty_deque::formatter::fmt::{format_code, FormatOptions};
+use rusty_deque::lexer::lex::tokenize_with_trivia;
+use rusty_deque::lexer::lex_token::{Token, TokenType};
+use rusty_deque::parser::par_cst::parse_cst;
+
+// JSON-RPC error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP enums
+const SYNC_FULL: i64 = 1;
+const SEVERITY_ERROR: i64 = 1;
+const SEVERITY_WARNING: i64 = 2;
+const COMPLETION_FUNCTION: i64 = 3;
+
+fn main() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    let mut server = Server::default();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("lsp: {}", e);
+                std::process::exit(1);
+            }
+        };
+        for reply in server.handle(&message) {
+            if let Err(e) = write_message(&mut output, &reply) {
+                eprintln!("lsp: {}", e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(status) = server.exit {
+            std::process::exit(status);
+        }
+    }
+}
+
+// reads one message, or None at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| invalid_data(&e.to_string()))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, String>, // the text of each open document, by uri
+    host_fns: Vec<String>,              // instruction names from initializationOptions
+    shut_down: bool,
+    exit: Option<i32>, // set when the client asks the server to exit
+}
+
+impl Server {
+    // handles a request or notification, returning the messages to send back
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = &message["id"];
+        let result = match method {
+            "initialize" => {
+                self.host_fns = params["initializationOptions"]["instructions"]
+                    .as_array()
+                    .map(|names| {
+                        names
+                            .iter()
+                            .filter_map(|name| name.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Ok(initialize_result())
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "exit" => {
+                self.exit = Some(if self.shut_down { 0 } else { 1 });
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                return self.update(uri, text.to_string());
+            }
+            "textDocument/didChange" => {
+                // the server asks for full sync, so the last change is the whole document
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                match text {
+                    Some(text) => return self.update(uri, text.to_string()),
+                    None => return vec![],
+                }
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, vec![])];
+            }
+            "textDocument/hover" => self.with_document(params, |text| hover(text, params)),
+            "textDocument/completion" => Ok(completion(&self.host_fns)),
+            "textDocument/formatting" => {
+                self.with_document(params, |text| formatting(text, params))
+            }
+            "textDocument/documentHighlight" => {
+                self.with_document(params, |text| highlight(text, params))
+            }
+            "textDocument/foldingRange" => self.with_document(params, folding_ranges),
+            _ if id.is_null() => return vec![], // notifications the server doesn't use
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method: {}", method))),
+        };
+        if id.is_null() {
+            return vec![];
+        }
+        match result {
+            Ok(result) => vec![json!({"jsonrpc": "2.0", "id": id, "result": result})],
+            Err((code, message)) => vec![json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": message},
+            })],
+        }
+    }
+
+    // stores a document's new text and returns its diagnostics
+    fn update(&mut self, uri: &str, text: String) -> Vec<Json> {
+        let diagnostics = diagnostics(&text, &self.host_fns);
+        self.documents.insert(uri.to_string(), text);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    fn with_document(
+        &self,
+        params: &Json,
+        f: impl FnOnce(&str) -> Json,
+    ) -> Result<Json, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match self.documents.get(uri) {
+            Some(text) => Ok(f(text)),
+            None => Err((INVALID_PARAMS, format!("unknown document: {}", uri))),
+        }
+    }
+}
+
+fn initialize_result() -> Json {
+    json!({
+        "capabilities": {
+            "textDocumentSync": SYNC_FULL,
+            "hoverProvider": true,
+            "completionProvider": {},
+            "documentFormattingProvider": true,
+            "documentHighlightProvider": true,
+            "foldingRangeProvider": true,
+        },
+        "serverInfo": {"name": "rusty_deque", "version": env!("CARGO_PKG_VERSION")},
+    })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+// every lexer error, or else the parser error,
+// or else warnings for unknown instructions and from the checker
+fn diagnostics(text: &str, host_fns: &[String]) -> Vec<Json> {
+    let lines = LineIndex::new(text);
+    let tokens = tokenize_with_trivia(text);
+    let lex_errors: Vec<Json> = tokens
+        .iter()
+        .filter(|t| t.token.token_type == TokenType::Error)
+        .map(|t| diagnostic(&lines, &t.token, SEVERITY_ERROR, &t.token.error_msg))
+        .collect();
+    if !lex_errors.is_empty() {
+        return lex_errors;
+    }
+    let cst = match parse_cst(tokens.clone()) {
+        Ok(cst) => cst,
+        Err(e) => return vec![diagnostic(&lines, &e.token, SEVERITY_ERROR, &e.message)],
+    };
+    let ast = match cst.to_ast() {
+        Ok(ast) => ast,
+        Err(e) => return vec![diagnostic(&lines, &e.token, SEVERITY_ERROR, &e.message)],
+    };
+    let mut warnings: Vec<Json> = tokens
+        .iter()
+        .filter(|t| t.token.token_type == TokenType::Instr && instr_doc(&t.token.lexeme).is_none())
+        .filter(|t| !host_fns.contains(&t.token.lexeme))
+        .map(|t| {
+            let message = format!("Unknown instruction: {}", t.token.lexeme);
+            diagnostic(&lines, &t.token, SEVERITY_WARNING, &message)
+        })
+        .collect();
+    // a checker warning covers its whole exec, from the op to the sigil
+    for warning in check_code(&ast).warnings {
+        if let Some(exec) = cst.exec_at(&warning.path) {
+            warnings.push(json!({
+                "range": lines.range(exec.op.first_token().token.start, exec.sigil.token.end),
+                "severity": SEVERITY_WARNING,
+                "source": "rusty_deque",
+                "message": warning.message,
+            }));
+        }
+    }
+    warnings
+}
+
+fn diagnostic(lines: &LineIndex, token: &Token, severity: i64, message: &str) -> Json {
+    json!({
+        "range": lines.range(token.start, token.end),
+        "severity": severity,
+        "source": "rusty_deque",
+        "message": message,
+    })
+}
+
+fn hover(text: &str, params: &Json) -> Json {
+    let lines = LineIndex::new(text);
+    let offset = lines.offset(&params["position"]);
+    let tokens = tokenize_with_trivia(text);
+    let token = match token_at(tokens.iter().map(|t| &t.token), offset) {
+        Some(token) if token.token_type == TokenType::Instr => token,
+        _ => return Json::Null,
+    };
+    match instr_doc(&token.lexeme) {
+        Some(doc) => json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("`{}` `{}`\n\n{}", doc.name, doc.effect, doc.summary),
+            },
+            "range": lines.range(token.start, token.end),
+        }),
+        None => Json::Null,
+    }
+}
+
+fn completion(host_fns: &[String]) -> Json {
+    let mut items: Vec<Json> = INSTR_DOCS
+        .iter()
+        .map(|doc| {
+            json!({
+                "label": doc.name,
+                "kind": COMPLETION_FUNCTION,
+                "detail": doc.effect,
+                "documentation": doc.summary,
+            })
+        })
+        .collect();
+    items.extend(host_fns.iter().map(|name| {
+        json!({
+            "label": name,
+            "kind": COMPLETION_FUNCTION,
+            "detail": "host function",
+        })
+    }));
+    json!(items)
+}
+
+fn formatting(text: &str, params: &Json) -> Json {
+    let mut options = FormatOptions::default();
+    if let Some(tab_size) = params["options"]["tabSize"].as_u64() {
+        options.indent = tab_size as usize;
+    }
+    // code that doesn't parse is left alone; the diagnostics already say why
+    let formatted = match format_code(text, &options) {
+        Ok(formatted) => formatted,
+        Err(_) => return Json::Null,
+    };
+    if formatted == text {
+        return json!([]);
+    }
+    let lines = LineIndex::new(text);
+    json!([{
+        "range": lines.range(0, lines.chars.len()),
+        "newText": formatted,
+    }])
+}
+
+// highlights a bracket and the bracket matching it
+fn highlight(text: &str, params: &Json) -> Json {
+    let lines = LineIndex::new(text);
+    let offset = lines.offset(&params["position"]);
+    let tokens: Vec<Token> = tokenize_with_trivia(text)
+        .into_iter()
+        .map(|t| t.token)
+        .collect();
+    for (open, close) in bracket_pairs(&tokens) {
+        let (open, close) = (&tokens[open], &tokens[close]);
+        if [open, close]
+            .iter()
+            .any(|t| t.start <= offset && offset <= t.end)
+        {
+            return json!([
+                {"range": lines.range(open.start, open.end)},
+                {"range": lines.range(close.start, close.end)},
+            ]);
+        }
+    }
+    Json::Null
+}
+
+// lets blocks and lists that span lines be folded
+fn folding_ranges(text: &str) -> Json {
+    let lines = LineIndex::new(text);
+    let tokens: Vec<Token> = tokenize_with_trivia(text)
+        .into_iter()
+        .map(|t| t.token)
+        .collect();
+    let ranges: Vec<Json> = bracket_pairs(&tokens)
+        .into_iter()
+        .map(|(open, close)| {
+            (
+                lines.line_of(tokens[open].start),
+                lines.line_of(tokens[close].start),
+            )
+        })
+        .filter(|(start, end)| start < end)
+        .map(|(start, end)| json!({"startLine": start, "endLine": end}))
+        .collect();
+    json!(ranges)
+}
+
+// the indices of each matching pair of brackets; unmatched brackets are skipped
+fn bracket_pairs(tokens: &[Token]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut open = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token.token_type {
+            TokenType::LeftCurly | TokenType::LeftSquare => open.push(i),
+            TokenType::RightCurly | TokenType::RightSquare => {
+                let expected = if token.token_type == TokenType::RightCurly {
+                    TokenType::LeftCurly
+                } else {
+                    TokenType::LeftSquare
+                };
+                if let Some(&start) = open.last() {
+                    if tokens[start].token_type == expected {
+                        open.pop();
+                        pairs.push((start, i));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+// the token under a cursor, including a cursor just after the token
+fn token_at<'a>(tokens: impl Iterator<Item = &'a Token>, offset: usize) -> Option<&'a Token> {
+    tokens
+        .filter(|t| t.token_type != TokenType::End)
+        .find(|t| t.start <= offset && offset <= t.end)
+}
+
+// converts between char offsets and LSP positions, which count UTF-16 code units
+struct LineIndex {
+    chars: Vec<char>,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> LineIndex {
+        let chars: Vec<char> = text.chars().collect();
+        let mut line_starts = vec![0];
+        for (i, c) in chars.iter().enumerate() {
+            if *c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        LineIndex { chars, line_starts }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        }
+    }
+
+    fn position(&self, offset: usize) -> Json {
+        let offset = offset.min(self.chars.len());
+        let line = self.line_of(offset);
+        let character: usize = self.chars[self.line_starts[line]..offset]
+            .iter()
+            .map(|c| c.len_utf16())
+            .sum();
+        json!({"line": line, "character": character})
+    }
+
+    fn range(&self, start: usize, end: usize) -> Json {
+        json!({"start": self.position(start), "end": self.position(end)})
+    }
+
+    fn offset(&self, position: &Json) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let character = position["character"].as_u64().unwrap_or(0) as usize;
+        let mut offset = match self.line_starts.get(line) {
+            Some(&start) => start,
+            None => return self.chars.len(),
+        };
+        let mut units = 0;
+        while offset < self.chars.len() && self.chars[offset] != '\n' && units < character {
+            units += self.chars[offset].len_utf16();
+            offset += 1;
+        }
+        offset
+    }
+}
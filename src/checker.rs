@@ -0,0 +1,1032 @@
+pub mod check {
+    // a static pass over the AST that follows the deque through the program without running it
+    // it knows the effect of every builtin instruction, and warns about code that is sure to fail:
+    // popping from an empty deque, or passing a value of the wrong type
+    // whatever can't be followed, like the values a loop leaves, is forgotten instead of guessed
+
+    use crate::evaluator::eval_value::Place;
+    use crate::parser::par_ast::{Block, Code, Exec, Literal, Op, StackEffect};
+    use std::fmt::{Display, Formatter, Result as FmtResult};
+
+    // the type of a value, as far as the checker can tell
+    // Num is an Int or a Float, Any is a value of unknown type
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Ty {
+        Int,
+        Float,
+        Num,
+        Bool,
+        Char,
+        List,
+        Block,
+        Any,
+    }
+
+    impl Display for Ty {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    // code that will fail when it runs
+    // the path is the index of the exec in the code, then its index in each nested block
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Warning {
+        pub path: Vec<usize>,
+        pub message: String,
+    }
+
+    // what a block takes from and leaves on each side of the deque
+    // inputs are in the order they are popped, outputs in the order they are pushed
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Effect {
+        pub left_in: Vec<Ty>,
+        pub right_in: Vec<Ty>,
+        pub left_out: Vec<Ty>,
+        pub right_out: Vec<Ty>,
+    }
+
+    // a block that only uses the back is written like the effects in the instruction docs, `( List -- Char List )`
+    // one that uses the front has each side written front first, `( a | b -- c | d )`; binary results
+    // go to the front, so `{+~}` is `( | Num Num -- Num | )`
+    impl Display for Effect {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            let names = |tys: &[Ty]| tys.iter().map(Ty::to_string).collect::<Vec<_>>();
+            let mut words = vec!["(".to_string()];
+            if self.left_in.is_empty() && self.left_out.is_empty() {
+                words.extend(names(&self.right_in));
+                words.push("--".to_string());
+                words.extend(names(&self.right_out));
+            } else {
+                words.extend(names(&self.left_in));
+                words.push("|".to_string());
+                words.extend(names(&self.right_in));
+                words.push("--".to_string());
+                words.extend(names(&self.left_out));
+                words.push("|".to_string());
+                words.extend(names(&self.right_out));
+            }
+            words.push(")".to_string());
+            write!(f, "{}", words.join(" "))
+        }
+    }
+
+    // the effect of a block literal at a path, or None if it couldn't be inferred
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BlockEffect {
+        pub path: Vec<usize>,
+        pub effect: Option<Effect>,
+    }
+
+    // a literal whose value is never used: dropped by pop or clear,
+    // or left on the deque when the program ends
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Unused {
+        pub path: Vec<usize>,
+        pub dropped_by: Option<Vec<usize>>, // the path of the pop or clear, if one drops it
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    pub struct Report {
+        pub warnings: Vec<Warning>, // sorted by path
+        pub effects: Vec<BlockEffect>,
+        pub unused: Vec<Unused>, // sorted by path
+    }
+
+    // checks a program, which starts on an empty deque, and infers the effect of each of its blocks
+    pub fn check_code(code: &Code) -> Report {
+        let mut checker = Checker::default();
+        checker.infer_blocks(code, &[]);
+        let mut state = State::default();
+        checker.run(code, &[], &mut state);
+        if !state.lost {
+            for val in state.left.iter().chain(state.right.iter()) {
+                checker.drop_val(val, None);
+            }
+        }
+
+        let mut warnings = checker.warnings;
+        warnings.sort_by(|a, b| a.path.cmp(&b.path));
+        warnings.dedup();
+        // a literal dropped in one place may be used in another, by a block that runs twice
+        let used = checker.used;
+        let mut unused: Vec<Unused> = checker
+            .unused
+            .into_iter()
+            .filter(|unused| !used.contains(&unused.path))
+            .collect();
+        unused.sort_by(|a, b| a.path.cmp(&b.path));
+        unused.dedup_by(|a, b| a.path == b.path);
+        Report {
+            warnings,
+            effects: checker.effects,
+            unused,
+        }
+    }
+
+    // the effect of code run on a deque of unknown values
+    pub fn infer_effect(code: &Code) -> Option<Effect> {
+        Checker::default().infer(code, &[])
+    }
+
+    // blocks run by exec are checked in place, up to this depth of nesting
+    const MAX_INLINE: usize = 32;
+
+    // a value on the abstract deque
+    #[derive(Debug, Clone)]
+    struct Val<'a> {
+        ty: Ty,
+        literal: Option<(&'a Literal, Vec<usize>)>, // the literal it was pushed as, and where
+        input: Option<usize>,                       // which of the block's inputs it is
+    }
+
+    impl Val<'_> {
+        fn of(ty: Ty) -> Self {
+            Val {
+                ty,
+                literal: None,
+                input: None,
+            }
+        }
+    }
+
+    fn type_of(literal: &Literal) -> Ty {
+        match literal {
+            Literal::Int(_) => Ty::Int,
+            Literal::Float(_) => Ty::Float,
+            Literal::Bool(_) => Ty::Bool,
+            Literal::Char(_) => Ty::Char,
+            Literal::List(_) => Ty::List,
+            Literal::Block(_) => Ty::Block,
+            Literal::None => Ty::Any,
+        }
+    }
+
+    // the type of a value that is one of two types
+    fn join(a: Ty, b: Ty) -> Ty {
+        let numeric = |ty| matches!(ty, Ty::Int | Ty::Float | Ty::Num);
+        if a == b {
+            a
+        } else if numeric(a) && numeric(b) {
+            Ty::Num
+        } else {
+            Ty::Any
+        }
+    }
+
+    // the deque as the checker sees it: known values at both ends, and maybe unknown ones between
+    #[derive(Debug, Clone, Default)]
+    struct State<'a> {
+        left: Vec<Val<'a>>,  // the front last
+        right: Vec<Val<'a>>, // the back last
+        // a block's deque has unknown values in the middle; popping one makes it an input
+        open: bool,
+        inputs: Vec<(Place, Ty)>,
+        // after code the checker can't follow, the middle holds any number of unknown values
+        lost: bool,
+        // whether the block cleared its deque, which no effect can describe
+        cleared: bool,
+    }
+
+    impl<'a> State<'a> {
+        fn open() -> Self {
+            State {
+                open: true,
+                ..State::default()
+            }
+        }
+
+        // the number of values, if it is known
+        fn len(&self) -> Option<usize> {
+            if self.open || self.lost {
+                None
+            } else {
+                Some(self.left.len() + self.right.len())
+            }
+        }
+
+        // None if the deque is known to be empty
+        fn pop(&mut self, place: Place) -> Option<Val<'a>> {
+            let (near, far) = match place {
+                Place::Left => (&mut self.left, &mut self.right),
+                Place::Right => (&mut self.right, &mut self.left),
+            };
+            if let Some(val) = near.pop() {
+                return Some(val);
+            }
+            if self.lost {
+                return Some(Val::of(Ty::Any));
+            }
+            if self.open {
+                self.inputs.push((place, Ty::Any));
+                return Some(Val {
+                    input: Some(self.inputs.len() - 1),
+                    ..Val::of(Ty::Any)
+                });
+            }
+            if far.is_empty() {
+                None
+            } else {
+                Some(far.remove(0))
+            }
+        }
+
+        fn push(&mut self, place: Place, val: Val<'a>) {
+            match place {
+                Place::Left => self.left.push(val),
+                Place::Right => self.right.push(val),
+            }
+        }
+
+        // stop following the deque, after code whose effect isn't known
+        fn forget(&mut self) {
+            self.left.clear();
+            self.right.clear();
+            self.lost = true;
+        }
+
+        // a deque that is known all the way through has no middle, so which end its values were
+        // pushed to doesn't matter; they are all kept at the back, so states can be compared
+        fn settle(&mut self) {
+            if !self.open && !self.lost {
+                let mut vals: Vec<Val<'a>> = self.left.drain(..).rev().collect();
+                vals.append(&mut self.right);
+                self.right = vals;
+            }
+        }
+
+        // whether both states hold the same number of known values and inputs
+        fn same_shape(&self, other: &State) -> bool {
+            self.left.len() == other.left.len()
+                && self.right.len() == other.right.len()
+                && self.inputs.len() == other.inputs.len()
+                && self.open == other.open
+                && self.lost == other.lost
+                && self.cleared == other.cleared
+        }
+
+        // a state that describes both states, which have the same shape
+        fn merge(&self, other: &State<'a>) -> State<'a> {
+            let merge_vals = |a: &[Val<'a>], b: &[Val<'a>]| {
+                a.iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| Val {
+                        ty: join(a.ty, b.ty),
+                        literal: match (&a.literal, &b.literal) {
+                            (Some((x, x_path)), Some((y, y_path)))
+                                if std::ptr::eq(*x, *y) && x_path == y_path =>
+                            {
+                                a.literal.clone()
+                            }
+                            _ => None,
+                        },
+                        input: if a.input == b.input { a.input } else { None },
+                    })
+                    .collect()
+            };
+            State {
+                left: merge_vals(&self.left, &other.left),
+                right: merge_vals(&self.right, &other.right),
+                inputs: self
+                    .inputs
+                    .iter()
+                    .zip(other.inputs.iter())
+                    .map(|(a, b)| (a.0, join(a.1, b.1)))
+                    .collect(),
+                ..self.clone()
+            }
+        }
+
+        // the effect of a block, once it has run on an open state
+        fn effect(&self) -> Option<Effect> {
+            if self.lost || self.cleared {
+                return None;
+            }
+            let tys = |vals: &[Val]| vals.iter().map(|val| val.ty).collect();
+            let inputs = |side: Place| {
+                self.inputs
+                    .iter()
+                    .filter(|(place, _)| *place == side)
+                    .map(|(_, ty)| *ty)
+                    .collect()
+            };
+            Some(Effect {
+                left_in: inputs(Place::Left),
+                right_in: inputs(Place::Right),
+                left_out: tys(&self.left),
+                right_out: tys(&self.right),
+            })
+        }
+    }
+
+    // what an instruction accepts for one of its arguments
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Want {
+        Any,
+        Num,
+        Int,
+        List,
+        Block,
+        Char,
+        Ord, // something comparable: a number or a char
+    }
+
+    impl Want {
+        fn accepts(self, ty: Ty) -> bool {
+            match (self, ty) {
+                (Want::Any, _) | (_, Ty::Any) => true,
+                (Want::Num, ty) => matches!(ty, Ty::Int | Ty::Float | Ty::Num),
+                (Want::Int, ty) => matches!(ty, Ty::Int | Ty::Num),
+                (Want::List, ty) => ty == Ty::List,
+                (Want::Block, ty) => ty == Ty::Block,
+                (Want::Char, ty) => ty == Ty::Char,
+                (Want::Ord, ty) => matches!(ty, Ty::Int | Ty::Float | Ty::Num | Ty::Char),
+            }
+        }
+
+        // what an unknown value must be, once it has been accepted
+        fn narrowed(self) -> Ty {
+            match self {
+                Want::Num => Ty::Num,
+                Want::Int => Ty::Int,
+                Want::List => Ty::List,
+                Want::Block => Ty::Block,
+                Want::Char => Ty::Char,
+                Want::Any | Want::Ord => Ty::Any,
+            }
+        }
+    }
+
+    impl Display for Want {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            match self {
+                Want::Any => write!(f, "a value"),
+                Want::Num => write!(f, "a number"),
+                Want::Int => write!(f, "an Int"),
+                Want::List => write!(f, "a List"),
+                Want::Block => write!(f, "a Block"),
+                Want::Char => write!(f, "a Char"),
+                Want::Ord => write!(f, "a number or Char"),
+            }
+        }
+    }
+
+    // what an instruction with a fixed effect pushes
+    enum Push {
+        Nothing,
+        Value(Ty),
+        Arith, // an Int for Ints, a Float if either is a Float
+        Same,  // the type of its argument
+    }
+
+    // the instructions that pop a fixed number of values and push at most one
+    fn fixed_effect(instr: &str) -> Option<(&'static [Want], Push)> {
+        use Want::*;
+        let effect: (&'static [Want], Push) = match instr {
+            "pop" | "ol" | "ow" | "el" | "ew" | "od" => (&[Any], Push::Nothing),
+            "len" => (&[], Push::Value(Ty::Int)),
+            // CASTS
+            "toInt" => (&[Any], Push::Value(Ty::Int)),
+            "toFloat" => (&[Any], Push::Value(Ty::Float)),
+            "toChar" => (&[Any], Push::Value(Ty::Char)),
+            "toBool" => (&[Any], Push::Value(Ty::Bool)),
+            "toStr" | "type" => (&[Any], Push::Value(Ty::List)),
+            "isInt" | "isFloat" | "isBool" | "isChar" | "isList" | "isStr" | "isBlock" => {
+                (&[Any], Push::Value(Ty::Bool))
+            }
+            // NUMBERS
+            "+" | "-" | "*" | "/" | "%" => (&[Num, Num], Push::Arith),
+            "//" | "exp" | "log" => (&[Num, Num], Push::Value(Ty::Float)),
+            "--" => (&[Num], Push::Same),
+            "&" | "|" | "^" => (&[Num, Num], Push::Value(Ty::Int)),
+            "n" => (&[Num], Push::Value(Ty::Int)),
+            // COMPARISONS
+            "=" | "ne" | "<" | ">" | "<=" | ">=" => (&[Ord, Ord], Push::Value(Ty::Bool)),
+            "nn" => (&[Any], Push::Value(Ty::Bool)),
+            "&&" | "||" => (&[Any, Any], Push::Value(Ty::Bool)),
+            // LISTS
+            // l/ and li take their list and index in either order
+            "l+" => (&[Any, Any], Push::Value(Ty::List)),
+            "lj" => (&[List, Any], Push::Value(Ty::List)),
+            "l/" => (&[Any, Int, Any], Push::Value(Ty::List)),
+            "li" => (&[Any, Any], Push::Value(Ty::Any)),
+            "ll" => (&[List], Push::Value(Ty::Int)),
+            // IO
+            "il" => (&[], Push::Value(Ty::Any)),
+            "ia" => (&[], Push::Value(Ty::List)),
+            "eof" => (&[], Push::Value(Ty::Bool)),
+            // FILES
+            "fr" | "frl" | "fls" | "frb" => (&[List], Push::Value(Ty::List)),
+            "fw" | "fa" => (&[List, Any], Push::Nothing),
+            "fe" => (&[List], Push::Value(Ty::Bool)),
+            "frm" => (&[List], Push::Nothing),
+            // BYTES
+            "ib" => (&[Int], Push::Value(Ty::List)),
+            "iab" => (&[], Push::Value(Ty::List)),
+            "ob" => (&[List], Push::Nothing),
+            "fwb" => (&[List, List], Push::Nothing),
+            "enc" | "dec" => (&[List, List], Push::Value(Ty::List)),
+            // PROCESS
+            "args" | "envl" => (&[], Push::Value(Ty::List)),
+            "env" => (&[List], Push::Value(Ty::Any)),
+            _ => return None,
+        };
+        Some(effect)
+    }
+
+    // an instruction being checked, and where it is
+    struct Site<'s> {
+        instr: &'s str,
+        place: Place,
+        path: &'s [usize],
+    }
+
+    impl Display for Site<'_> {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            let sigil = match self.place {
+                Place::Left => '!',
+                Place::Right => '~',
+            };
+            write!(f, "`{}{}`", self.instr, sigil)
+        }
+    }
+
+    #[derive(Default)]
+    struct Checker {
+        warnings: Vec<Warning>,
+        effects: Vec<BlockEffect>,
+        depth: usize, // of blocks being checked in place
+        // the literals an instruction has taken, and those dropped without being taken
+        used: Vec<Vec<usize>>,
+        unused: Vec<Unused>,
+    }
+
+    impl Checker {
+        fn warn(&mut self, path: &[usize], message: String) {
+            self.warnings.push(Warning {
+                path: path.to_vec(),
+                message,
+            });
+        }
+
+        fn drop_val(&mut self, val: &Val, dropped_by: Option<&[usize]>) {
+            if let Some((_, path)) = &val.literal {
+                self.unused.push(Unused {
+                    path: path.clone(),
+                    dropped_by: dropped_by.map(<[usize]>::to_vec),
+                });
+            }
+        }
+
+        // infers the effect of every block literal in the code, in the order they appear
+        fn infer_blocks(&mut self, code: &Code, path: &[usize]) {
+            for (i, exec) in code.iter().enumerate() {
+                if let Exec::Left(Op::Literal(Literal::Block(block)))
+                | Exec::Right(Op::Literal(Literal::Block(block))) = exec
+                {
+                    let path = [path, &[i]].concat();
+                    let mut effect = self.infer(&block.execs, &path);
+                    if let Some(declared) = &block.effect {
+                        match &effect {
+                            Some(inferred) if !declared_matches(declared, inferred) => self.warn(
+                                &path,
+                                format!(
+                                    "block is annotated {}, but its effect is {}",
+                                    declared, inferred
+                                ),
+                            ),
+                            Some(_) => {}
+                            // the annotation stands in for an effect that can't be inferred
+                            None => effect = Some(declared_effect(declared)),
+                        }
+                    }
+                    self.effects.push(BlockEffect {
+                        path: path.clone(),
+                        effect,
+                    });
+                    self.infer_blocks(&block.execs, &path);
+                }
+            }
+        }
+
+        fn infer(&mut self, code: &Code, path: &[usize]) -> Option<Effect> {
+            let mut state = State::open();
+            self.run(code, path, &mut state);
+            state.effect()
+        }
+
+        fn run<'a>(&mut self, code: &'a Code, path: &[usize], state: &mut State<'a>) {
+            for (i, exec) in code.iter().enumerate() {
+                let path = [path, &[i]].concat();
+                let (op, place) = match exec {
+                    Exec::Left(op) => (op, Place::Left),
+                    Exec::Right(op) => (op, Place::Right),
+                };
+                match op {
+                    Op::Literal(literal) => state.push(
+                        place,
+                        Val {
+                            literal: Some((literal, path)),
+                            ..Val::of(type_of(literal))
+                        },
+                    ),
+                    Op::Instruction(instr) => {
+                        let site = Site {
+                            instr,
+                            place,
+                            path: &path,
+                        };
+                        self.instr(&site, state);
+                    }
+                }
+            }
+        }
+
+        // warns if the deque is known to hold fewer than n values
+        fn expect<'a>(&mut self, site: &Site, state: &mut State<'a>, n: usize) {
+            if let Some(len) = state.len() {
+                if len < n {
+                    let values = if n == 1 { "value" } else { "values" };
+                    self.warn(
+                        site.path,
+                        format!("{} needs {} {}, but the deque has {}", site, n, values, len),
+                    );
+                    // the deque is whatever it was before the error, so stop following it
+                    state.forget();
+                }
+            }
+        }
+
+        fn take<'a>(&mut self, site: &Site, state: &mut State<'a>, want: Want) -> Val<'a> {
+            let val = match state.pop(site.place) {
+                Some(val) => val,
+                None => {
+                    self.warn(site.path, format!("{} pops from an empty deque", site));
+                    state.forget();
+                    return Val::of(Ty::Any);
+                }
+            };
+            // pop takes a value only to drop it
+            if let (Some((_, path)), false) = (&val.literal, site.instr == "pop") {
+                self.used.push(path.clone());
+            }
+            self.require(site, state, val, want)
+        }
+
+        fn require<'a>(
+            &mut self,
+            site: &Site,
+            state: &mut State<'a>,
+            mut val: Val<'a>,
+            want: Want,
+        ) -> Val<'a> {
+            if !want.accepts(val.ty) {
+                self.warn(
+                    site.path,
+                    format!("{} expects {}, got {}", site, want, val.ty),
+                );
+            } else if val.ty == Ty::Any && want.narrowed() != Ty::Any {
+                val.ty = want.narrowed();
+                // copies of the same input, made by dup or over, are narrowed with it
+                if let Some(input) = val.input {
+                    state.inputs[input].1 = val.ty;
+                    for copy in state.left.iter_mut().chain(state.right.iter_mut()) {
+                        if copy.input == Some(input) {
+                            copy.ty = val.ty;
+                        }
+                    }
+                }
+            }
+            val
+        }
+
+        // a block literal, if the value is one and can be checked in place
+        fn inline<'a>(&self, val: &Val<'a>) -> Option<(&'a Block, Vec<usize>)> {
+            match val.literal {
+                Some((Literal::Block(block), ref path)) if self.depth < MAX_INLINE => {
+                    Some((block, path.clone()))
+                }
+                _ => None,
+            }
+        }
+
+        fn run_block<'a>(&mut self, block: &(&'a Block, Vec<usize>), state: &mut State<'a>) {
+            let (block, path) = block;
+            self.depth += 1;
+            let mut after = state.clone();
+            let warnings = self.warnings.len();
+            self.run(&block.execs, path, &mut after);
+            self.depth -= 1;
+            match &block.effect {
+                // a block the checker can't follow does what its annotation says,
+                // unless it was lost after a warning
+                Some(declared) if after.lost && !state.lost && self.warnings.len() == warnings => {
+                    self.apply_declared(declared, path, state)
+                }
+                _ => *state = after,
+            }
+        }
+
+        fn apply_declared<'a>(
+            &mut self,
+            declared: &StackEffect,
+            path: &[usize],
+            state: &mut State<'a>,
+        ) {
+            if let Some(len) = state.len() {
+                if len < declared.inputs() {
+                    self.warn(
+                        path,
+                        format!(
+                            "block is annotated {}, but the deque has {} values",
+                            declared, len
+                        ),
+                    );
+                    state.forget();
+                    return;
+                }
+            }
+            let effect = declared_effect(declared);
+            for _ in effect.left_in.iter() {
+                state.pop(Place::Left);
+            }
+            for _ in effect.right_in.iter() {
+                state.pop(Place::Right);
+            }
+            for ty in effect.left_out {
+                state.push(Place::Left, Val::of(ty));
+            }
+            for ty in effect.right_out {
+                state.push(Place::Right, Val::of(ty));
+            }
+        }
+
+        // the state after a loop, which ran its body zero or more times
+        // a body that leaves the deque as it found it is followed; any other body is not
+        fn after_loop<'a>(state: &mut State<'a>, iteration: &State<'a>) {
+            let mut iteration = iteration.clone();
+            state.settle();
+            iteration.settle();
+            if state.same_shape(&iteration) {
+                *state = state.merge(&iteration);
+            } else {
+                state.forget();
+            }
+        }
+
+        fn instr<'a>(&mut self, site: &Site, state: &mut State<'a>) {
+            if let Some((wants, push)) = fixed_effect(site.instr) {
+                self.expect(site, state, wants.len());
+                let args: Vec<Val> = wants
+                    .iter()
+                    .map(|want| self.take(site, state, *want))
+                    .collect();
+                if site.instr == "pop" {
+                    self.drop_val(&args[0], Some(site.path));
+                }
+                if let [a, b] = &args[..] {
+                    if matches!(site.instr, "=" | "ne" | "<" | ">" | "<=" | ">=")
+                        && (a.ty == Ty::Char) != (b.ty == Ty::Char)
+                        && ![a.ty, b.ty].contains(&Ty::Any)
+                        && args.iter().all(|arg| Want::Ord.accepts(arg.ty))
+                    {
+                        self.warn(
+                            site.path,
+                            format!("{} can't compare {} with {}", site, a.ty, b.ty),
+                        );
+                    }
+                }
+                let ty = match push {
+                    Push::Nothing => return,
+                    Push::Value(ty) => ty,
+                    Push::Arith => {
+                        if args.iter().all(|arg| arg.ty == Ty::Int) {
+                            Ty::Int
+                        } else if args.iter().any(|arg| arg.ty == Ty::Float) {
+                            Ty::Float
+                        } else {
+                            Ty::Num
+                        }
+                    }
+                    Push::Same => match args[0].ty {
+                        Ty::Int => Ty::Int,
+                        Ty::Float => Ty::Float,
+                        _ => Ty::Num,
+                    },
+                };
+                // results of two or three values go to the front, like the evaluator's binary and ternary
+                let place = if wants.len() >= 2 {
+                    Place::Left
+                } else {
+                    site.place
+                };
+                state.push(place, Val::of(ty));
+                return;
+            }
+
+            let place = site.place;
+            let other = match place {
+                Place::Left => Place::Right,
+                Place::Right => Place::Left,
+            };
+            match site.instr {
+                // DEQUE OPS
+                "clear" => {
+                    for val in state.left.iter().chain(state.right.iter()) {
+                        self.drop_val(val, Some(site.path));
+                    }
+                    state.cleared = state.cleared || state.open || state.lost;
+                    state.left.clear();
+                    state.right.clear();
+                    state.open = false;
+                    state.lost = false;
+                }
+                "dup" => {
+                    self.expect(site, state, 1);
+                    let val = self.take(site, state, Want::Any);
+                    state.push(place, val.clone());
+                    state.push(place, val);
+                }
+                "swap" => {
+                    // swapping an empty deque does nothing
+                    if state.len() == Some(0) {
+                        return;
+                    }
+                    self.expect(site, state, 2);
+                    let a = self.take(site, state, Want::Any);
+                    let b = self.take(site, state, Want::Any);
+                    state.push(place, a);
+                    state.push(place, b);
+                }
+                "rot" => {
+                    if state.len() == Some(0) {
+                        return;
+                    }
+                    // rot~ moves the back to the front, rot! the front to the back
+                    let val = self.take(site, state, Want::Any);
+                    state.push(other, val);
+                }
+                "over" => {
+                    // the copy goes on the other end of the deque
+                    self.expect(site, state, 2);
+                    let a = self.take(site, state, Want::Any);
+                    let b = self.take(site, state, Want::Any);
+                    state.push(place, b.clone());
+                    state.push(place, a);
+                    state.push(other, b);
+                }
+                // LISTS
+                "lb" => {
+                    self.expect(site, state, 1);
+                    let count = self.take(site, state, Want::Int);
+                    match count.literal {
+                        Some((Literal::Int(count), _)) => {
+                            for _ in 0..*count {
+                                self.take(site, state, Want::Any);
+                            }
+                        }
+                        _ => state.forget(),
+                    }
+                    state.push(place, Val::of(Ty::List));
+                }
+                "ld" => {
+                    self.expect(site, state, 1);
+                    let list = self.take(site, state, Want::List);
+                    match list.literal {
+                        Some((Literal::List(items), ref path)) => {
+                            for item in items {
+                                let val = Val {
+                                    literal: Some((item, path.clone())),
+                                    ..Val::of(type_of(item))
+                                };
+                                state.push(place, val);
+                            }
+                        }
+                        _ => state.forget(),
+                    }
+                }
+                // map, filter and reduce run their block on a deque of their own
+                "map" | "filter" | "reduce" => {
+                    let reduce = site.instr == "reduce";
+                    self.expect(site, state, if reduce { 3 } else { 2 });
+                    self.take(site, state, Want::List);
+                    let accumulator = if reduce {
+                        Some(self.take(site, state, Want::Any))
+                    } else {
+                        None
+                    };
+                    let block = self.take(site, state, Want::Block);
+                    if let Some(block) = self.inline(&block) {
+                        let mut temp = State::default();
+                        temp.push(Place::Left, Val::of(Ty::Any));
+                        if let Some(accumulator) = accumulator.clone() {
+                            temp.push(Place::Right, accumulator);
+                        }
+                        self.run_block(&block, &mut temp);
+                        if temp.len() == Some(0) {
+                            self.warn(
+                                site.path,
+                                format!("{} block leaves no value for the result", site),
+                            );
+                        }
+                    }
+                    let ty = match accumulator {
+                        Some(_) => Ty::Any,
+                        None => Ty::List,
+                    };
+                    // like the other instructions of two or three values, the result goes to the front
+                    state.push(Place::Left, Val::of(ty));
+                }
+                // CONTROL FLOW
+                "exec" => {
+                    self.expect(site, state, 1);
+                    let val = self.take(site, state, Want::Any);
+                    if let Some(block) = self.inline(&val) {
+                        self.run_block(&block, state);
+                    } else if matches!(val.ty, Ty::Block | Ty::Any) {
+                        state.forget();
+                    }
+                    // exec does nothing with any other value
+                }
+                "loop" => {
+                    self.expect(site, state, 1);
+                    let body = self.take(site, state, Want::Block);
+                    if let Some(body) = self.inline(&body) {
+                        self.run_block(&body, &mut state.clone());
+                    }
+                    // nothing after a loop runs, unless the loop fails or exits
+                    state.forget();
+                }
+                "range" => {
+                    self.expect(site, state, 4);
+                    for _ in 0..3 {
+                        self.take(site, state, Want::Int);
+                    }
+                    let body = self.take(site, state, Want::Block);
+                    match self.inline(&body) {
+                        Some(body) => {
+                            let mut iteration = state.clone();
+                            iteration.push(place, Val::of(Ty::Int));
+                            self.run_block(&body, &mut iteration);
+                            Checker::after_loop(state, &iteration);
+                        }
+                        None => state.forget(),
+                    }
+                }
+                "lines" => {
+                    self.expect(site, state, 1);
+                    let body = self.take(site, state, Want::Block);
+                    match self.inline(&body) {
+                        Some(body) => {
+                            let mut iteration = state.clone();
+                            iteration.push(place, Val::of(Ty::List));
+                            self.run_block(&body, &mut iteration);
+                            Checker::after_loop(state, &iteration);
+                        }
+                        None => state.forget(),
+                    }
+                }
+                "while" => {
+                    self.expect(site, state, 2);
+                    let condition = self.take(site, state, Want::Block);
+                    let body = self.take(site, state, Want::Block);
+                    match (self.inline(&condition), self.inline(&body)) {
+                        (Some(condition), Some(body)) => {
+                            // the condition runs once more than the body
+                            let mut iteration = state.clone();
+                            self.run_block(&condition, &mut iteration);
+                            self.take(site, &mut iteration, Want::Any);
+                            self.run_block(&body, &mut iteration);
+                            Checker::after_loop(state, &iteration);
+                            self.run_block(&condition, state);
+                            self.take(site, state, Want::Any);
+                        }
+                        _ => state.forget(),
+                    }
+                }
+                "ite" => {
+                    self.expect(site, state, 3);
+                    let condition = self.take(site, state, Want::Block);
+                    let then = self.take(site, state, Want::Block);
+                    let otherwise = self.take(site, state, Want::Block);
+                    let blocks = (
+                        self.inline(&condition),
+                        self.inline(&then),
+                        self.inline(&otherwise),
+                    );
+                    match blocks {
+                        (Some(condition), Some(then), Some(otherwise)) => {
+                            self.run_block(&condition, state);
+                            self.take(site, state, Want::Any);
+                            let mut other_branch = state.clone();
+                            self.run_block(&then, state);
+                            self.run_block(&otherwise, &mut other_branch);
+                            state.settle();
+                            other_branch.settle();
+                            if state.same_shape(&other_branch) {
+                                *state = state.merge(&other_branch);
+                            } else {
+                                state.forget();
+                            }
+                        }
+                        _ => state.forget(),
+                    }
+                }
+                // IO
+                "printf" | "format" => {
+                    self.expect(site, state, 1);
+                    let fmt = self.take(site, state, Want::List);
+                    match fmt.literal.as_ref().and_then(|(fmt, _)| directives(fmt)) {
+                        Some(wants) => {
+                            for want in wants {
+                                self.take(site, state, want);
+                            }
+                        }
+                        None => state.forget(),
+                    }
+                    if site.instr == "format" {
+                        state.push(place, Val::of(Ty::List));
+                    }
+                }
+                "exit" => {
+                    self.expect(site, state, 1);
+                    self.take(site, state, Want::Int);
+                    state.forget();
+                }
+                // an instruction the checker doesn't know, which fails when it runs
+                _ => state.forget(),
+            }
+        }
+    }
+
+    // an annotation as an effect, reading names like Int as types and any other name as Any
+    fn declared_effect(declared: &StackEffect) -> Effect {
+        let tys = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| match name.as_str() {
+                    "Int" => Ty::Int,
+                    "Float" => Ty::Float,
+                    "Num" => Ty::Num,
+                    "Bool" => Ty::Bool,
+                    "Char" => Ty::Char,
+                    "List" | "Str" => Ty::List,
+                    "Block" => Ty::Block,
+                    _ => Ty::Any,
+                })
+                .collect()
+        };
+        Effect {
+            left_in: tys(&declared.left_in),
+            right_in: tys(&declared.right_in),
+            left_out: tys(&declared.left_out),
+            right_out: tys(&declared.right_out),
+        }
+    }
+
+    // whether an inferred effect takes as many values from each side as declared, and leaves as many
+    // the side of the values it leaves isn't compared: binary instructions push to the front
+    // whichever side they are called on, so most blocks leave their results there
+    fn declared_matches(declared: &StackEffect, inferred: &Effect) -> bool {
+        declared.left_in.len() == inferred.left_in.len()
+            && declared.right_in.len() == inferred.right_in.len()
+            && declared.left_out.len() + declared.right_out.len()
+                == inferred.left_out.len() + inferred.right_out.len()
+    }
+
+    // what each directive in a format string pops, or None if it isn't a valid format string
+    fn directives(fmt: &Literal) -> Option<Vec<Want>> {
+        let chars = match fmt {
+            Literal::List(items) => items
+                .iter()
+                .map(|item| match item {
+                    Literal::Char(c) => Some(*c),
+                    _ => None,
+                })
+                .collect::<Option<Vec<char>>>()?,
+            _ => return None,
+        };
+        let mut wants = Vec::new();
+        let mut chars = chars.into_iter().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                continue;
+            }
+            if chars.peek() == Some(&'.') {
+                chars.next();
+                while chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+                    chars.next();
+                }
+            }
+            match chars.next()? {
+                '%' => {}
+                's' => wants.push(Want::Any),
+                'd' => wants.push(Want::Int),
+                'f' => wants.push(Want::Num),
+                'c' => wants.push(Want::Char),
+                _ => return None,
+            }
+        }
+        Some(wants)
+    }
+}
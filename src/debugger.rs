@@ -0,0 +1,334 @@
+pub mod debug {
+    // an interactive debugger, which runs code one exec at a time
+    // at each stop it shows the exec that runs next, the deque, and the watch expressions
+    // the terminal is driven from main.rs; this takes commands and returns what to print
+
+    use crate::evaluator::eval::{Capabilities, Interpreter, Limits};
+    use crate::evaluator::eval_error::{EvalError, EvalResult};
+    use crate::evaluator::eval_instr::repr;
+    use crate::evaluator::eval_value::Value;
+    use crate::lexer::lex::tokenize_with_trivia;
+    use crate::parser::par::parse_source;
+    use crate::parser::par_ast::{Code, Exec, Op};
+    use crate::parser::par_cst::{parse_cst, CstCode};
+    use std::collections::VecDeque;
+    use std::fmt::{Display, Formatter, Result as FmtResult};
+    use std::io::Write;
+
+    pub const HELP: &str = "\
+commands:
+  step, s               run the next exec, stopping inside any block it runs
+  next, n               run the next exec, and any blocks it runs
+  continue, c           run until a breakpoint, or the end of the program
+  break, b <line>       stop before the execs on a line
+  break, b <instr>      stop before an instruction, on either side
+  delete, d <n>         remove breakpoint n
+  watch, w <code>       at each stop, run code on a copy of the deque and show what it leaves
+  unwatch <n>           remove watch n
+  info, i               list the breakpoints and watches
+  print, p              show where the program is, and the deque
+  help, h               show this message
+  quit, q               stop debugging
+an empty line repeats step, next or continue";
+
+    // watch expressions run with no capabilities, and stop after this many steps
+    const WATCH_STEPS: u64 = 10_000;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Breakpoint {
+        Line(usize), // counting from 1
+        Instr(String),
+    }
+
+    impl Display for Breakpoint {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            match self {
+                Breakpoint::Line(line) => write!(f, "line {}", line),
+                Breakpoint::Instr(instr) => write!(f, "instruction {}", instr),
+            }
+        }
+    }
+
+    pub struct Debugger {
+        pub interp: Interpreter,
+        pub breakpoints: Vec<Breakpoint>,
+        pub watches: Vec<String>,
+        cst: CstCode,
+        chars: Vec<char>,
+        line_starts: Vec<usize>,
+        // how the program ended, once it has
+        outcome: Option<EvalResult<()>>,
+    }
+
+    impl Debugger {
+        // parses the code and stops before its first exec
+        pub fn new(source: &str, mut interp: Interpreter) -> Result<Debugger, String> {
+            let cst = parse_cst(tokenize_with_trivia(source))?;
+            interp.start(cst.to_ast()?);
+            let chars: Vec<char> = source.chars().collect();
+            let mut line_starts = vec![0];
+            for (i, c) in chars.iter().enumerate() {
+                if *c == '\n' {
+                    line_starts.push(i + 1);
+                }
+            }
+            let mut debugger = Debugger {
+                interp,
+                breakpoints: Vec::new(),
+                watches: Vec::new(),
+                cst,
+                chars,
+                line_starts,
+                outcome: None,
+            };
+            // code with no execs is done before it starts
+            if let Err(e) = debugger.next_exec() {
+                debugger.outcome = Some(Err(e));
+            }
+            Ok(debugger)
+        }
+
+        // how the program ended, or None while it is still running
+        pub fn outcome(&self) -> Option<&EvalResult<()>> {
+            self.outcome.as_ref()
+        }
+
+        // runs a command, returning what to print
+        pub fn command(&mut self, line: &str) -> String {
+            let line = line.trim();
+            let (name, arg) = match line.split_once(char::is_whitespace) {
+                Some((name, arg)) => (name, arg.trim()),
+                None => (line, ""),
+            };
+            match name {
+                "s" | "step" => self.resume(|interp, _| interp.step()),
+                "n" | "next" => self.resume(|interp, _| interp.step_over()),
+                "c" | "continue" => self.resume(|interp, debugger| loop {
+                    interp.step()?;
+                    match interp.next_exec()? {
+                        Some((exec, path)) if debugger.hits(&exec, &path) => return Ok(()),
+                        Some(_) => {}
+                        None => return Ok(()),
+                    }
+                }),
+                "b" | "break" => self.add_breakpoint(arg),
+                "d" | "delete" => match remove(&mut self.breakpoints, arg) {
+                    Some(breakpoint) => format!("deleted the breakpoint on {}", breakpoint),
+                    None => format!("no breakpoint {}", arg),
+                },
+                "w" | "watch" => self.add_watch(arg),
+                "unwatch" => match remove(&mut self.watches, arg) {
+                    Some(watch) => format!("deleted the watch on {}", watch),
+                    None => format!("no watch {}", arg),
+                },
+                "i" | "info" => self.info(),
+                "p" | "print" => self.show(),
+                "h" | "help" => HELP.to_string(),
+                _ => format!("unknown command: {}, type help for the commands", name),
+            }
+        }
+
+        // where the program is stopped, the deque, and the watches
+        pub fn show(&mut self) -> String {
+            let mut lines = Vec::new();
+            match &self.outcome {
+                None => {}
+                Some(Ok(())) => lines.push("the program finished".to_string()),
+                Some(Err(EvalError::Exit(code))) => {
+                    lines.push(format!("the program exited with status {}", code))
+                }
+                Some(Err(e)) => lines.push(format!("the program failed: {}", e)),
+            }
+            if self.outcome.is_none() {
+                if let Ok(Some((_, path))) = self.interp.next_exec() {
+                    lines.extend(self.location(&path));
+                }
+            }
+            lines.push(format!("deque: {}", deque_text(&self.interp.deque)));
+            for (i, watch) in self.watches.iter().enumerate() {
+                lines.push(format!(
+                    "watch {}: {} = {}",
+                    i + 1,
+                    watch,
+                    self.watch_value(watch)
+                ));
+            }
+            lines.join("\n")
+        }
+
+        // runs the interpreter with one of the step commands, then shows where it stopped
+        fn resume<F>(&mut self, run: F) -> String
+        where
+            F: FnOnce(&mut Interpreter, &Debugger) -> EvalResult<()>,
+        {
+            if self.outcome.is_some() {
+                return "the program has finished".to_string();
+            }
+            // the interpreter is taken out, so the run can look at the breakpoints
+            let mut interp = std::mem::take(&mut self.interp);
+            let result = run(&mut interp, self).and_then(|()| interp.next_exec());
+            self.interp = interp;
+            let _ = self.interp.output.flush();
+            let _ = self.interp.error.flush();
+            match result {
+                Ok(Some(_)) => {}
+                Ok(None) => self.outcome = Some(Ok(())),
+                Err(e) => self.outcome = Some(Err(e)),
+            }
+            self.show()
+        }
+
+        fn next_exec(&mut self) -> EvalResult<()> {
+            if self.interp.next_exec()?.is_none() {
+                self.outcome = Some(Ok(()));
+            }
+            Ok(())
+        }
+
+        // whether the exec about to run is on a breakpoint
+        fn hits(&self, exec: &Exec, path: &[usize]) -> bool {
+            let line = self.line_of(path);
+            self.breakpoints.iter().any(|breakpoint| match breakpoint {
+                Breakpoint::Line(n) => line == Some(*n),
+                Breakpoint::Instr(name) => {
+                    matches!(exec, Exec::Left(Op::Instruction(instr)) | Exec::Right(Op::Instruction(instr)) if instr == name)
+                }
+            })
+        }
+
+        // the span of the exec at a path, in chars
+        fn span(&self, path: &[usize]) -> Option<(usize, usize)> {
+            let exec = self.cst.exec_at(path)?;
+            Some((exec.op.first_token().token.start, exec.sigil.token.end))
+        }
+
+        // the line of the exec at a path, counting from 1
+        fn line_of(&self, path: &[usize]) -> Option<usize> {
+            let (start, _) = self.span(path)?;
+            Some(self.line_index(start) + 1)
+        }
+
+        fn line_index(&self, offset: usize) -> usize {
+            match self.line_starts.binary_search(&offset) {
+                Ok(line) => line,
+                Err(line) => line - 1,
+            }
+        }
+
+        // the exec at a path, with its line of source and a mark under it
+        fn location(&self, path: &[usize]) -> Vec<String> {
+            let (start, end) = match self.span(path) {
+                Some(span) => span,
+                None => return vec!["in a block that isn't in the program".to_string()],
+            };
+            let line = self.line_index(start);
+            let line_start = self.line_starts[line];
+            let line_end = self
+                .line_starts
+                .get(line + 1)
+                .map_or(self.chars.len(), |next| next - 1);
+            let source: String = self.chars[line_start..line_end].iter().collect();
+            // a block on several lines is marked to the end of its first line
+            let end = end.min(line_end);
+            let exec: String = self.chars[start..end].iter().collect();
+            let number = (line + 1).to_string();
+            vec![
+                format!(
+                    "line {}, column {}: {}",
+                    line + 1,
+                    start - line_start + 1,
+                    exec
+                ),
+                format!("{} | {}", number, source.trim_end()),
+                format!(
+                    "{} | {}{}",
+                    " ".repeat(number.len()),
+                    " ".repeat(start - line_start),
+                    "^".repeat((end - start).max(1))
+                ),
+            ]
+        }
+
+        fn add_breakpoint(&mut self, arg: &str) -> String {
+            if arg.is_empty() {
+                return "break needs a line number or an instruction".to_string();
+            }
+            let breakpoint = match arg.parse::<usize>() {
+                Ok(line) => Breakpoint::Line(line),
+                // an instruction can be written with its sigil, but stops on both sides
+                Err(_) => Breakpoint::Instr(arg.trim_end_matches(['~', '!']).to_string()),
+            };
+            let message = format!("breakpoint {}: {}", self.breakpoints.len() + 1, breakpoint);
+            self.breakpoints.push(breakpoint);
+            message
+        }
+
+        fn add_watch(&mut self, arg: &str) -> String {
+            if let Err(e) = parse_watch(arg) {
+                return format!("error: {}", e);
+            }
+            self.watches.push(arg.to_string());
+            format!(
+                "watch {}: {} = {}",
+                self.watches.len(),
+                arg,
+                self.watch_value(arg)
+            )
+        }
+
+        // what the watch expression leaves on the back of a copy of the deque
+        fn watch_value(&self, watch: &str) -> String {
+            let code = match parse_watch(watch) {
+                Ok(code) => code,
+                Err(e) => return format!("error: {}", e),
+            };
+            let mut interp = Interpreter::with_deque(VecDeque::clone(&self.interp.deque))
+                .with_capabilities(Capabilities::none())
+                .with_limits(Limits {
+                    max_steps: Some(WATCH_STEPS),
+                    ..Limits::default()
+                });
+            match interp.run(code) {
+                Ok(()) => match interp.deque.back() {
+                    Some(val) => repr(val),
+                    None => "nothing".to_string(),
+                },
+                Err(e) => format!("error: {}", e),
+            }
+        }
+
+        fn info(&self) -> String {
+            let mut lines = Vec::new();
+            for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+                lines.push(format!("breakpoint {}: {}", i + 1, breakpoint));
+            }
+            for (i, watch) in self.watches.iter().enumerate() {
+                lines.push(format!("watch {}: {}", i + 1, watch));
+            }
+            if lines.is_empty() {
+                lines.push("no breakpoints or watches".to_string());
+            }
+            lines.join("\n")
+        }
+    }
+
+    fn parse_watch(watch: &str) -> Result<Code, String> {
+        if watch.is_empty() {
+            return Err("watch needs some code".to_string());
+        }
+        parse_source(watch)
+    }
+
+    // removes the item with a number, counting from 1
+    fn remove<T>(items: &mut Vec<T>, number: &str) -> Option<T> {
+        match number.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= items.len() => Some(items.remove(n - 1)),
+            _ => None,
+        }
+    }
+
+    fn deque_text(deque: &VecDeque<Value>) -> String {
+        let vals: Vec<String> = deque.iter().map(repr).collect();
+        format!("[{}]", vals.join(", "))
+    }
+}
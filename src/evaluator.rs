@@ -1,175 +1,332 @@
 pub mod eval_value {
     use crate::parser::par_ast::*;
+    use std::collections::VecDeque;
+    use std::fmt::{Debug, Formatter, Result as FmtResult};
+    use std::ops::Deref;
 
     pub type Value = Literal;
 
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum Place {
         Left,
         Right,
     }
+
+    // the size of a value, counting every value nested inside it
+    pub fn size_of(val: &Value) -> usize {
+        match val {
+            Value::List(list) => 1 + list.iter().map(size_of).sum::<usize>(),
+            Value::Block(block) => {
+                1 + block
+                    .execs
+                    .iter()
+                    .map(|exec| match exec {
+                        Exec::Left(Op::Literal(lit)) | Exec::Right(Op::Literal(lit)) => {
+                            size_of(lit)
+                        }
+                        _ => 1,
+                    })
+                    .sum::<usize>()
+            }
+            _ => 1,
+        }
+    }
+
+    // the deque code runs on, which keeps the total size of its values as they come and go
+    // reading goes through the VecDeque inside, changing it goes through the methods below
+    #[derive(Clone, Default, PartialEq)]
+    pub struct Deque {
+        values: VecDeque<Value>,
+        size: usize,
+    }
+
+    impl Deque {
+        pub fn new() -> Deque {
+            Deque::default()
+        }
+
+        // the sum of size_of over the values
+        pub fn total_size(&self) -> usize {
+            self.size
+        }
+
+        pub fn push_front(&mut self, val: Value) {
+            self.size += size_of(&val);
+            self.values.push_front(val);
+        }
+
+        pub fn push_back(&mut self, val: Value) {
+            self.size += size_of(&val);
+            self.values.push_back(val);
+        }
+
+        pub fn pop_front(&mut self) -> Option<Value> {
+            let val = self.values.pop_front()?;
+            self.size -= size_of(&val);
+            Some(val)
+        }
+
+        pub fn pop_back(&mut self) -> Option<Value> {
+            let val = self.values.pop_back()?;
+            self.size -= size_of(&val);
+            Some(val)
+        }
+
+        pub fn clear(&mut self) {
+            self.values.clear();
+            self.size = 0;
+        }
+
+        // rotating moves values without changing them, so the size stays
+        pub fn rotate_left(&mut self, n: usize) {
+            self.values.rotate_left(n);
+        }
+
+        pub fn rotate_right(&mut self, n: usize) {
+            self.values.rotate_right(n);
+        }
+
+        // lets code change the values in any way, then counts their size again
+        pub fn modify<R>(&mut self, func: impl FnOnce(&mut VecDeque<Value>) -> R) -> R {
+            let result = func(&mut self.values);
+            self.size = self.values.iter().map(size_of).sum();
+            result
+        }
+    }
+
+    impl Deref for Deque {
+        type Target = VecDeque<Value>;
+
+        fn deref(&self) -> &VecDeque<Value> {
+            &self.values
+        }
+    }
+
+    impl Debug for Deque {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            self.values.fmt(f)
+        }
+    }
+
+    impl From<VecDeque<Value>> for Deque {
+        fn from(values: VecDeque<Value>) -> Deque {
+            let size = values.iter().map(size_of).sum();
+            Deque { values, size }
+        }
+    }
+
+    impl From<Deque> for VecDeque<Value> {
+        fn from(deque: Deque) -> VecDeque<Value> {
+            deque.values
+        }
+    }
+
+    impl IntoIterator for Deque {
+        type Item = Value;
+        type IntoIter = std::collections::vec_deque::IntoIter<Value>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.values.into_iter()
+        }
+    }
+
+    impl PartialEq<VecDeque<Value>> for Deque {
+        fn eq(&self, other: &VecDeque<Value>) -> bool {
+            &self.values == other
+        }
+    }
+}
+
+pub mod eval_error {
+    use super::eval::Capability;
+    use crate::parser::par_ast::StackEffect;
+    use std::fmt::Display;
+    use std::io;
+    use std::time::Duration;
+
+    pub type EvalResult<T> = Result<T, EvalError>;
+
+    // errors that stop the interpreter
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum EvalError {
+        // an instruction failed
+        Instr(String),
+        // the limits set in eval::Limits were exceeded
+        StepLimit(u64),
+        DequeLimit(usize),
+        SizeLimit(usize),
+        DepthLimit(usize),
+        Timeout(Duration),
+        // an instruction needs a capability the interpreter wasn't granted
+        Permission(String, Capability),
+        // a file instruction failed: the instruction, the path, and what went wrong
+        Io(String, String, io::ErrorKind),
+        // the code asked to stop with an exit status
+        Exit(i32),
+        // with effect checks on, a block didn't match its stack effect annotation
+        Effect(StackEffect, String),
+    }
+
+    impl Display for EvalError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                EvalError::Instr(msg) => write!(f, "{}", msg),
+                EvalError::StepLimit(max) => {
+                    write!(f, "step limit exceeded: ran more than {} instructions", max)
+                }
+                EvalError::DequeLimit(max) => {
+                    write!(f, "deque limit exceeded: deque grew past {} values", max)
+                }
+                EvalError::SizeLimit(max) => {
+                    write!(
+                        f,
+                        "size limit exceeded: deque held more than {} values in total",
+                        max
+                    )
+                }
+                EvalError::DepthLimit(max) => {
+                    write!(f, "depth limit exceeded: blocks nested deeper than {}", max)
+                }
+                EvalError::Timeout(max) => {
+                    write!(f, "timeout: ran longer than {}ms", max.as_millis())
+                }
+                EvalError::Permission(instr, capability) => write!(
+                    f,
+                    "{}: permission denied: needs the {:?} capability",
+                    instr, capability
+                ),
+                EvalError::Io(instr, path, kind) => write!(f, "{}: {}: {}", instr, path, kind),
+                EvalError::Exit(code) => write!(f, "exited with status {}", code),
+                EvalError::Effect(effect, msg) => write!(f, "stack effect {}: {}", effect, msg),
+            }
+        }
+    }
+
+    impl From<String> for EvalError {
+        fn from(msg: String) -> EvalError {
+            EvalError::Instr(msg)
+        }
+    }
+
+    impl From<EvalError> for String {
+        fn from(err: EvalError) -> String {
+            err.to_string()
+        }
+    }
 }
 
 pub mod eval_instr {
-    use super::{eval::*, eval_value::*};
+    use super::{eval::*, eval_convert::*, eval_error::*, eval_value::*};
     use crate::parser::par_ast::*;
     use std::{
         collections::VecDeque,
-        io::{self, Read},
+        convert::TryFrom,
+        fs,
+        io::{self, BufRead, Read, Write},
+        mem,
+        path::Path,
+        rc::Rc,
         str::FromStr,
     };
 
-    type ValResult = Result<Value, String>;
-    type Nilary = fn() -> ValResult;
-    type Unary = fn(a: Value) -> ValResult;
-    type Binary = fn(a: Value, b: Value) -> ValResult;
-    type Ternary = fn(a: Value, b: Value, c: Value) -> ValResult;
+    pub type ValResult = Result<Value, String>;
+    // the shapes of instructions that pop a fixed number of values and push one result
+    pub type Nilary = dyn Fn() -> ValResult;
+    pub type Unary = dyn Fn(Value) -> ValResult;
+    pub type Binary = dyn Fn(Value, Value) -> ValResult;
+    pub type Ternary = dyn Fn(Value, Value, Value) -> ValResult;
+    // instructions that work on the deque directly
+    pub type Raw = dyn Fn(&mut VecDeque<Value>, Place) -> FnResult;
     // type Quaternary = fn(a: Value, b: Value, c: Value, d: Value) -> ValResult;
     pub type FnResult = Result<(), String>;
 
+    // pushes the result of an instruction to the side it was called on
+    // a result of Value::None pushes nothing
+    fn push_result_to(deque: &mut Deque, place: Place, result: Value) {
+        if let Value::None = result {
+            return;
+        }
+        match place {
+            Place::Left => deque.push_front(result),
+            Place::Right => deque.push_back(result),
+        }
+    }
+
+    // binary and ternary results always go to the front, whichever side they were called on
+    fn push_result_front(deque: &mut Deque, result: Value) {
+        if let Value::None = result {
+            return;
+        }
+        deque.push_front(result);
+    }
+
+    fn pop_arg(deque: &mut Deque, place: Place) -> Result<Value, String> {
+        let val = match place {
+            Place::Left => deque.pop_front(),
+            Place::Right => deque.pop_back(),
+        };
+        val.ok_or_else(|| "tried to pop empty deque".to_string())
+    }
+
     // bool is to push the result back to the stack
-    pub fn nilary(
-        deque: &mut VecDeque<Value>,
+    pub fn nilary<F: Fn() -> ValResult + ?Sized>(
+        deque: &mut Deque,
         place: Place,
-        func: Nilary,
+        func: &F,
         push_result: bool,
     ) -> FnResult {
         let result = func()?;
-        match place {
-            Place::Left => {
-                if push_result {
-                    deque.push_front(result);
-                }
-            }
-            Place::Right => {
-                if push_result {
-                    deque.push_back(result);
-                }
-            }
+        if push_result {
+            push_result_to(deque, place, result);
         }
         Ok(())
     }
-    pub fn unary(
-        deque: &mut VecDeque<Value>,
+    pub fn unary<F: Fn(Value) -> ValResult + ?Sized>(
+        deque: &mut Deque,
         place: Place,
-        func: Unary,
+        func: &F,
         push_result: bool,
     ) -> FnResult {
-        match place {
-            Place::Left => {
-                let val = deque.pop_front().ok_or_else(|| "tried to pop empty deque");
-                match val {
-                    Ok(val) => {
-                        let result = func(val)?;
-                        if push_result {
-                            deque.push_front(result);
-                        }
-                        Ok(())
-                    }
-                    Err(err) => Err(err.to_string()),
-                }
-            }
-            Place::Right => {
-                let val = deque.pop_back().ok_or_else(|| "tried to pop empty deque");
-                match val {
-                    Ok(val) => {
-                        let result = func(val)?;
-                        if push_result {
-                            deque.push_back(result);
-                        }
-                        Ok(())
-                    }
-                    Err(err) => Err(err.to_string()),
-                }
-            }
+        let val = pop_arg(deque, place)?;
+        let result = func(val)?;
+        if push_result {
+            push_result_to(deque, place, result);
         }
+        Ok(())
     }
 
-    pub fn binary(
-        deque: &mut VecDeque<Value>,
+    pub fn binary<F: Fn(Value, Value) -> ValResult + ?Sized>(
+        deque: &mut Deque,
         place: Place,
-        func: Binary,
+        func: &F,
         push_result: bool,
     ) -> FnResult {
-        match place {
-            Place::Left => {
-                let val_a = deque.pop_front().ok_or_else(|| "tried to pop empty deque");
-                let val_b = deque.pop_front().ok_or_else(|| "tried to pop empty deque");
-                match (val_a, val_b) {
-                    (Ok(val_a), Ok(val_b)) => {
-                        let result = func(val_a, val_b)?;
-                        if push_result {
-                            deque.push_front(result);
-                        }
-                        Ok(())
-                    }
-                    (Err(err), _) => Err(err.to_string()),
-                    (_, Err(err)) => Err(err.to_string()),
-                }
-            }
-            Place::Right => {
-                let val_a = deque.pop_back().ok_or_else(|| "tried to pop empty deque");
-                let val_b = deque.pop_back().ok_or_else(|| "tried to pop empty deque");
-                match (val_a, val_b) {
-                    (Ok(val_a), Ok(val_b)) => {
-                        let result = func(val_a, val_b)?;
-                        if push_result {
-                            deque.push_front(result);
-                        }
-                        Ok(())
-                    }
-                    (Err(err), _) => Err(err.to_string()),
-                    (_, Err(err)) => Err(err.to_string()),
-                }
-            }
+        let val_a = pop_arg(deque, place);
+        let val_b = pop_arg(deque, place);
+        let result = func(val_a?, val_b?)?;
+        if push_result {
+            push_result_front(deque, result);
         }
+        Ok(())
     }
-    pub fn ternary(
-        deque: &mut VecDeque<Value>,
+    pub fn ternary<F: Fn(Value, Value, Value) -> ValResult + ?Sized>(
+        deque: &mut Deque,
         place: Place,
-        func: Ternary,
+        func: &F,
         push_result: bool,
     ) -> FnResult {
-        match place {
-            Place::Left => {
-                let val_a = deque.pop_front().ok_or_else(|| "tried to pop empty deque");
-                let val_b = deque.pop_front().ok_or_else(|| "tried to pop empty deque");
-                let val_c = deque.pop_front().ok_or_else(|| "tried to pop empty deque");
-                match (val_a, val_b, val_c) {
-                    (Ok(val_a), Ok(val_b), Ok(val_c)) => {
-                        let result = func(val_a, val_b, val_c)?;
-                        if push_result {
-                            deque.push_front(result);
-                        }
-                        Ok(())
-                    }
-                    (Err(err), _, _) => Err(err.to_string()),
-                    (_, Err(err), _) => Err(err.to_string()),
-                    (_, _, Err(err)) => Err(err.to_string()),
-                }
-            }
-            Place::Right => {
-                let val_a = deque.pop_back().ok_or_else(|| "tried to pop empty deque");
-                let val_b = deque.pop_back().ok_or_else(|| "tried to pop empty deque");
-                let val_c = deque.pop_back().ok_or_else(|| "tried to pop empty deque");
-                match (val_a, val_b, val_c) {
-                    (Ok(val_a), Ok(val_b), Ok(val_c)) => {
-                        let result = func(val_a, val_b, val_c)?;
-                        if push_result {
-                            deque.push_front(result);
-                        }
-                        Ok(())
-                    }
-                    (Err(err), _, _) => Err(err.to_string()),
-                    (_, Err(err), _) => Err(err.to_string()),
-                    (_, _, Err(err)) => Err(err.to_string()),
-                }
-            }
+        let val_a = pop_arg(deque, place);
+        let val_b = pop_arg(deque, place);
+        let val_c = pop_arg(deque, place);
+        let result = func(val_a?, val_b?, val_c?)?;
+        if push_result {
+            push_result_front(deque, result);
         }
+        Ok(())
     }
 
     // pub fn quaternary(
-    //     deque: &mut VecDeque<Value>,
+    //     deque: &mut Deque,
     //     place: Place,
     //     func: Quaternary,
     //     push_result: bool,
@@ -211,12 +368,12 @@ pub mod eval_instr {
     // }
 
     // DEQUE OPS
-    pub fn clear(deque: &mut VecDeque<Value>, _place: Place) -> FnResult {
+    pub fn clear(deque: &mut Deque, _place: Place) -> FnResult {
         deque.clear();
         Ok(())
     }
 
-    pub fn pop(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn pop(deque: &mut Deque, place: Place) -> FnResult {
         match place {
             Place::Left => {
                 let result = deque.pop_front();
@@ -235,7 +392,7 @@ pub mod eval_instr {
         }
     }
 
-    pub fn dup(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn dup(deque: &mut Deque, place: Place) -> FnResult {
         match place {
             Place::Left => {
                 let val = deque.front();
@@ -262,7 +419,11 @@ pub mod eval_instr {
         }
     }
 
-    pub fn rot(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn rot(deque: &mut Deque, place: Place) -> FnResult {
+        // rotating an empty deque does nothing
+        if deque.is_empty() {
+            return Ok(());
+        }
         match place {
             Place::Left => {
                 deque.rotate_left(1);
@@ -274,14 +435,14 @@ pub mod eval_instr {
         Ok(())
     }
 
-    pub fn over(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn over(deque: &mut Deque, place: Place) -> FnResult {
         let mut iter = deque.iter();
         match place {
             Place::Left => {
                 iter.next();
                 let ele = iter
                     .next()
-                    .ok_or_else(|| "tried to over deque with less than 2 items")
+                    .ok_or("tried to over deque with less than 2 items")
                     .and_then(|v| {
                         if let Value::None = v {
                             Err("tried to over deque with less than 2 items")
@@ -302,7 +463,7 @@ pub mod eval_instr {
                 iter.next_back();
                 let ele = iter
                     .next_back()
-                    .ok_or_else(|| "tried to over deque with less than 2 items")
+                    .ok_or("tried to over deque with less than 2 items")
                     .and_then(|v| {
                         if let Value::None = v {
                             Err("tried to over deque with less than 2 items")
@@ -322,7 +483,7 @@ pub mod eval_instr {
         }
     }
 
-    pub fn swap(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn swap(deque: &mut Deque, place: Place) -> FnResult {
         match place {
             Place::Left => {
                 let val_a = deque.pop_front();
@@ -355,7 +516,7 @@ pub mod eval_instr {
         }
     }
 
-    pub fn len(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn len(deque: &mut Deque, place: Place) -> FnResult {
         match place {
             Place::Left => {
                 let len = deque.len();
@@ -381,7 +542,7 @@ pub mod eval_instr {
         for ele in list {
             match ele {
                 Value::Char(c) => chars.push(c),
-                _ => return Err(format!("{} is not a char", ele.to_string())),
+                _ => return Err(format!("{} is not a char", ele)),
             }
         }
         let str_val: String = chars.iter().collect();
@@ -444,6 +605,55 @@ pub mod eval_instr {
             _ => Err("Cannot cast to char".to_string()),
         }
     }
+    // renders any value the same way Literal::to_string does, as a list of chars
+    pub fn cast_to_str(val: Value) -> ValResult {
+        Ok(Value::List(
+            val.to_string().chars().map(Value::Char).collect(),
+        ))
+    }
+
+    // TYPE INTROSPECTION
+    pub fn type_name_of(val: &Value) -> &'static str {
+        match val {
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Bool(_) => "Bool",
+            Value::Char(_) => "Char",
+            Value::List(_) => "List",
+            Value::Block(_) => "Block",
+            Value::None => "None",
+        }
+    }
+    pub fn type_of(val: Value) -> ValResult {
+        Ok(Value::List(
+            type_name_of(&val).chars().map(Value::Char).collect(),
+        ))
+    }
+    pub fn is_int(val: Value) -> ValResult {
+        Ok(Value::Bool(matches!(val, Value::Int(_))))
+    }
+    pub fn is_float(val: Value) -> ValResult {
+        Ok(Value::Bool(matches!(val, Value::Float(_))))
+    }
+    pub fn is_bool(val: Value) -> ValResult {
+        Ok(Value::Bool(matches!(val, Value::Bool(_))))
+    }
+    pub fn is_char(val: Value) -> ValResult {
+        Ok(Value::Bool(matches!(val, Value::Char(_))))
+    }
+    pub fn is_list(val: Value) -> ValResult {
+        Ok(Value::Bool(matches!(val, Value::List(_))))
+    }
+    // a string is a list where every element is a char, including the empty list
+    pub fn is_str(val: Value) -> ValResult {
+        match val {
+            Value::List(l) => Ok(Value::Bool(l.iter().all(|v| matches!(v, Value::Char(_))))),
+            _ => Ok(Value::Bool(false)),
+        }
+    }
+    pub fn is_block(val: Value) -> ValResult {
+        Ok(Value::Bool(matches!(val, Value::Block(_))))
+    }
 
     // INT/FLOAT OPS
     pub fn add(a: Value, b: Value) -> ValResult {
@@ -751,184 +961,148 @@ pub mod eval_instr {
     // ld implemented in call_instr
 
     // LIST FUNCTIONS
-    pub fn map(list: Value, block: Value) -> ValResult {
-        match (list, &block) {
-            (Value::List(list), Value::Block(_)) => {
-                let mut new_list = Vec::new();
-                let mut temp_deque: VecDeque<Value> = VecDeque::new();
-                for val in list {
-                    temp_deque.push_front(val.clone());
-                    exec_block(&mut temp_deque, &block)?;
-                    new_list.push(temp_deque.pop_front().unwrap());
-                }
-                Ok(Value::List(new_list))
+    // like the control flow below, these push a frame, which runs the block
+    // on a deque of its own for each element of the list
+
+    // leaves a frame to run the block over the list
+    fn each(
+        deque: &mut Deque,
+        frames: &mut Frames,
+        (list, block): (Option<Value>, Option<Value>),
+        collect: Collect,
+    ) -> FnResult {
+        match (list, block) {
+            (Some(Value::List(list)), Some(block @ Value::Block(_))) => {
+                frames.push(Frame::Each(Box::new(Each {
+                    collect,
+                    items: list.into(),
+                    current: None,
+                    body: code_of(block),
+                    outer: mem::take(deque),
+                })));
+                Ok(())
             }
-            _ => Err("invalid operands for map".to_string()),
+            _ => Err(format!("invalid operands for {}", collect.name())),
         }
     }
-    pub fn filter(list: Value, block: Value) -> ValResult {
-        match (list, &block) {
-            (Value::List(list), Value::Block(_)) => {
-                let mut new_list = Vec::new();
-                let mut temp_deque: VecDeque<Value> = VecDeque::new();
-                for val in list {
-                    temp_deque.push_front(val.clone());
-                    exec_block(&mut temp_deque, &block)?;
-                    let result = temp_deque.pop_front().unwrap();
-                    if let Value::Bool(true) = result {
-                        new_list.push(val.clone());
-                    }
-                }
-                Ok(Value::List(new_list))
-            }
-            _ => Err("invalid operands for filter".to_string()),
-        }
+
+    pub fn map(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
+        let list = pop_place(deque, place);
+        let block = pop_place(deque, place);
+        each(deque, frames, (list, block), Collect::Map(Vec::new()))
     }
-    pub fn reduce(list: Value, accumulator: Value, block: Value) -> ValResult {
-        match (list, &accumulator, &block) {
-            (Value::List(list), _, Value::Block(_)) => {
-                let mut temp_deque: VecDeque<Value> = VecDeque::new();
-                let mut result = accumulator;
-                for val in list {
-                    temp_deque.push_front(val.clone());
-                    temp_deque.push_back(result.clone());
-                    exec_block(&mut temp_deque, &block)?;
-                    result = temp_deque.pop_front().unwrap();
-                }
-                Ok(result)
-            }
-            _ => Err("invalid operands for reduce".to_string()),
+
+    pub fn filter(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
+        let list = pop_place(deque, place);
+        let block = pop_place(deque, place);
+        each(deque, frames, (list, block), Collect::Filter(Vec::new()))
+    }
+
+    pub fn reduce(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
+        let list = pop_place(deque, place);
+        let accumulator = pop_place(deque, place);
+        let block = pop_place(deque, place);
+        match accumulator {
+            Some(acc) => each(deque, frames, (list, block), Collect::Reduce(acc)),
+            None => Err("invalid operands for reduce".to_string()),
         }
     }
 
     // CONTROL FLOW
-    pub fn exec(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
-        let block = match place {
+    // none of these run their blocks directly: they push frames onto the continuation
+    // stack, and the interpreter executes them. this keeps recursion off the Rust stack.
+
+    // pops from the given side of the deque
+    fn pop_place(deque: &mut Deque, place: Place) -> Option<Value> {
+        match place {
             Place::Left => deque.pop_front(),
             Place::Right => deque.pop_back(),
-        };
-        if let Some(block) = block {
-            if let Value::Block(block) = block {
-                for exec in block {
-                    match exec {
-                        Exec::Left(op) => match op {
-                            Op::Literal(lit) => {
-                                deque.push_front(lit);
-                            }
-                            Op::Instruction(instruction) => {
-                                let result = call_instr(deque, instruction, Place::Left);
-                                if let Err(err) = result {
-                                    return Err(err);
-                                }
-                            }
-                        },
-                        Exec::Right(op) => match op {
-                            Op::Literal(lit) => {
-                                deque.push_back(lit);
-                            }
-                            Op::Instruction(instruction) => {
-                                let result = call_instr(deque, instruction, Place::Right);
-                                if let Err(err) = result {
-                                    return Err(err);
-                                }
-                            }
-                        },
-                    }
+        }
+    }
+
+    // gets the code of a block value
+    // non-blocks are treated as an empty block
+    pub fn code_of(val: Value) -> Rc<Block> {
+        match val {
+            Value::Block(block) => Rc::new(block),
+            _ => Rc::new(Block {
+                execs: Vec::new(),
+                effect: None,
+                path: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn exec(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
+        match pop_place(deque, place) {
+            Some(block) => {
+                if let Value::Block(_) = block {
+                    frames.push(Frame::new_block(code_of(block)));
                 }
+                Ok(())
             }
-        } else {
-            return Err("Failed to pop block".to_string());
+            None => Err("Failed to pop block".to_string()),
         }
-        Ok(())
     }
 
-    // utility function to execute a block for the control flow instructions
-    pub fn exec_block(deque: &mut VecDeque<Value>, block: &Value) -> FnResult {
-        if let Value::Block(block) = block {
-            for exec in block {
-                // println!("{:?}", deque);
-                // println!("executing: {:?}", exec);
-                match exec {
-                    Exec::Left(op) => match op {
-                        Op::Literal(lit) => {
-                            deque.push_front(lit.clone());
-                        }
-                        Op::Instruction(instruction) => {
-                            let result = call_instr(deque, instruction.clone(), Place::Left);
-                            if let Err(err) = result {
-                                return Err(err);
-                            }
-                        }
-                    },
-                    Exec::Right(op) => match op {
-                        Op::Literal(lit) => {
-                            deque.push_back(lit.clone());
-                        }
-                        Op::Instruction(instruction) => {
-                            let result = call_instr(deque, instruction.clone(), Place::Right);
-                            if let Err(err) = result {
-                                return Err(err);
-                            }
-                        }
-                    },
+    pub fn loop_instr(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
+        // pop a block and run it forever
+        match pop_place(deque, place) {
+            Some(block) => {
+                if let Value::Block(_) = &block {
+                    frames.push(Frame::Loop {
+                        body: code_of(block),
+                    });
+                    Ok(())
+                } else {
+                    Err("loop: expected block".to_string())
                 }
             }
+            None => Err("loop: failed to pop block".to_string()),
         }
-        Ok(())
     }
 
-    pub fn loop_instr(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
-        // pop a block and run it forever
-        let block = match &place {
-            Place::Left => deque.pop_front(),
-            Place::Right => deque.pop_back(),
-        };
-        if let Some(block) = block {
-            if let Value::Block(_) = &block {
-                loop {
-                    exec_block(deque, &block)?;
+    pub fn lines(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
+        // pop a block and run it once for every line of input, with the line pushed
+        match pop_place(deque, place) {
+            Some(block) => {
+                if let Value::Block(_) = &block {
+                    frames.push(Frame::Lines {
+                        body: code_of(block),
+                        place,
+                    });
+                    Ok(())
+                } else {
+                    Err("lines: expected block".to_string())
                 }
-            } else {
-                return Err("loop: expected block".to_string());
             }
-        } else {
-            return Err("loop: failed to pop block".to_string());
+            None => Err("lines: failed to pop block".to_string()),
         }
     }
 
-    pub fn range(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn range(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
         // pop 4 times:
         // lower bound, upper bound, increment size, loop body block
         // run the loop body block for each value in the range
-        let (lower, upper, inc, body) = match &place {
-            Place::Left => (
-                deque.pop_front(),
-                deque.pop_front(),
-                deque.pop_front(),
-                deque.pop_front(),
-            ),
-            Place::Right => (
-                deque.pop_back(),
-                deque.pop_back(),
-                deque.pop_back(),
-                deque.pop_back(),
-            ),
-        };
+        let lower = pop_place(deque, place);
+        let upper = pop_place(deque, place);
+        let inc = pop_place(deque, place);
+        let body = pop_place(deque, place);
         match (lower, upper, inc, body) {
             (Some(lower), Some(upper), Some(inc), Some(body)) => {
                 if let (Value::Int(lower), Value::Int(upper), Value::Int(inc), Value::Block(_)) =
-                    (lower.clone(), upper.clone(), inc.clone(), &body)
+                    (&lower, &upper, &inc, &body)
                 {
-                    for i in (lower..upper).step_by(inc as usize) {
-                        match place {
-                            Place::Left => deque.push_front(Value::Int(i as i64)),
-                            Place::Right => deque.push_back(Value::Int(i as i64)),
-                        }
-                        let result = exec_block(deque, &body);
-                        if let Err(err) = result {
-                            return Err(err);
-                        }
+                    if *inc <= 0 {
+                        return Err(format!("range: step must be positive, got {}", inc));
                     }
+                    frames.push(Frame::Range {
+                        i: *lower,
+                        upper: *upper,
+                        step: *inc,
+                        body: code_of(body),
+                        place,
+                    });
                     Ok(())
                 } else {
                     Err(format!(
@@ -946,225 +1120,1875 @@ pub mod eval_instr {
         }
     }
 
-    pub fn while_instr(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn while_instr(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
         // pop 2 times:
         // condition block, loop body block
-        let (condition, loop_body) = match place {
-            Place::Left => (deque.pop_front().unwrap(), deque.pop_front().unwrap()),
-            Place::Right => (deque.pop_back().unwrap(), deque.pop_back().unwrap()),
-        };
-        if let Value::Block(_) = condition {
-            if let Value::Block(_) = loop_body {
-                loop {
-                    let result = exec_block(deque, &condition);
-                    if let Err(err) = result {
-                        return Err(err);
-                    }
-                    let top = match place {
-                        Place::Left => deque.pop_front(),
-                        Place::Right => deque.pop_back(),
-                    };
-                    if let Some(top) = top {
-                        // exit the loop if the condition is false
-                        let truth = truthiness_of(top.clone());
-                        if !truth {
-                            break;
-                        }
-                        let result = exec_block(deque, &loop_body);
-                        if let Err(err) = result {
-                            return Err(err);
-                        }
+        let condition = pop_place(deque, place);
+        let loop_body = pop_place(deque, place);
+        match (condition, loop_body) {
+            (Some(condition), Some(loop_body)) => {
+                if let Value::Block(_) = condition {
+                    if let Value::Block(_) = loop_body {
+                        frames.push(Frame::While {
+                            condition: code_of(condition),
+                            body: code_of(loop_body),
+                            place,
+                            testing: false,
+                        });
+                        Ok(())
                     } else {
-                        return Err("while: failed to get condition result".to_string());
+                        Err("while: expected loop body block".to_string())
                     }
+                } else {
+                    Err("while: expected condition block".to_string())
                 }
-                Ok(())
-            } else {
-                Err("while: expected loop body block".to_string())
             }
-        } else {
-            Err("while: expected condition block".to_string())
+            _ => Err("while: expected condition and loop body blocks".to_string()),
         }
     }
 
-    pub fn ite(deque: &mut VecDeque<Value>, place: Place) -> FnResult {
+    pub fn ite(deque: &mut Deque, frames: &mut Frames, place: Place) -> FnResult {
         // pop 3 blocks: condition, true, false
-        let (condition, true_block, false_block) = match place {
-            Place::Left => (deque.pop_front(), deque.pop_front(), deque.pop_front()),
-            Place::Right => (deque.pop_back(), deque.pop_back(), deque.pop_back()),
-        };
+        let condition = pop_place(deque, place);
+        let true_block = pop_place(deque, place);
+        let false_block = pop_place(deque, place);
         match (condition, true_block, false_block) {
             (Some(condition), Some(true_block), Some(false_block)) => {
-                // exec condition
-                let result = exec_block(deque, &condition);
-                if let Err(err) = result {
-                    return Err(err);
-                }
-                // check the truthiness of the top of the stack
-                let truth = match place {
-                    Place::Left => deque.pop_front(),
-                    Place::Right => deque.pop_back(),
-                };
-                if let Some(truth) = truth {
-                    let truth = truthiness_of(truth.clone());
-                    if truth {
-                        let result = exec_block(deque, &true_block);
-                        if let Err(err) = result {
-                            return Err(err);
-                        }
-                    } else {
-                        let result = exec_block(deque, &false_block);
-                        if let Err(err) = result {
-                            return Err(err);
-                        }
-                    }
-                    Ok(())
-                } else {
-                    return Err("ite: failed to get condition result".to_string());
-                }
+                // exec condition, then pick a branch once it is done
+                frames.push(Frame::Ite {
+                    true_block: code_of(true_block),
+                    false_block: code_of(false_block),
+                    place,
+                });
+                frames.push(Frame::new_block(code_of(condition)));
+                Ok(())
             }
             _ => Err("ite: expected condition, true, and false blocks".to_string()),
         }
     }
 
     // IO
-    pub fn il() -> ValResult {
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
+    // these read and write the interpreter's streams, which are stdin and stdout by default
+    // reads a line without its line ending, or None at the end of the input
+    pub fn read_line(input: &mut dyn BufRead, instr: &str) -> Result<Option<String>, String> {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => Ok(None),
             Ok(_) => {
-                let input = input.trim().to_string();
-                // turn input into a Literal::List(Literal::Char)
-                let mut input_list = Vec::new();
-                for c in input.chars() {
-                    input_list.push(Value::Char(c));
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
                 }
-                Ok(Value::List(input_list))
+                Ok(Some(line))
             }
-            Err(_) => Err("il: error reading from stdin".to_string()),
+            Err(_) => Err(format!("{}: error reading from input", instr)),
+        }
+    }
+    // pushes false instead of a string at the end of the input
+    pub fn il(input: &mut dyn BufRead) -> ValResult {
+        match read_line(input, "il")? {
+            Some(line) => Ok(line.into_value()),
+            None => Ok(Value::Bool(false)),
+        }
+    }
+    pub fn eof(input: &mut dyn BufRead) -> ValResult {
+        match input.fill_buf() {
+            Ok(buf) => Ok(Value::Bool(buf.is_empty())),
+            Err(_) => Err("eof: error reading from input".to_string()),
         }
     }
-    pub fn ia() -> ValResult {
-        // reads everything from stdin and puts it into a list of chars
-        let mut input = String::new();
-        let stdin = io::stdin();
-        let mut handle = stdin.lock();
-        match handle.read_to_string(&mut input) {
+    pub fn ia(input: &mut dyn BufRead) -> ValResult {
+        // reads everything from the input and puts it into a list of chars
+        let mut all = String::new();
+        match input.read_to_string(&mut all) {
             Ok(_) => {
-                let input = input.trim().to_string();
+                let all = all.trim().to_string();
                 // turn input into a Literal::List(Literal::Char)
                 let mut input_list = Vec::new();
-                for c in input.chars() {
+                for c in all.chars() {
                     input_list.push(Value::Char(c));
                 }
                 Ok(Value::List(input_list))
             }
-            Err(_) => Err("ia: error reading from stdin".to_string()),
+            Err(_) => Err("ia: error reading from input".to_string()),
         }
     }
 
-    pub fn ol(literal: Value) -> ValResult {
-        // if all elements in literal are Literal::Char, then print them in double quotes instead of as a list
-        let mut is_char_list = true;
-        match literal {
-            Literal::List(ref list) => {
-                for elem in list.iter() {
-                    if let Literal::Char(_) = elem {
-                        continue;
-                    } else {
-                        is_char_list = false;
-                        break;
-                    }
-                }
-                if is_char_list {
-                    let mut chars = String::new();
-                    for elem in list.iter() {
-                        if let Literal::Char(c) = elem {
-                            chars.push(*c);
-                        }
-                    }
-                    println!("{}", chars);
-                } else {
-                    println!("{}", literal.to_string());
-                }
-            }
-            _ => println!("{}", literal.to_string()),
-        };
-        Ok(Value::None)
-    }
-
-    // pretty much the same as ol, consider consolidating
-    pub fn ow(literal: Value) -> ValResult {
-        // if all elements in literal are Literal::Char, then print them in double quotes instead of as a list
-        let mut is_char_list = true;
-        match literal {
-            Literal::List(ref list) => {
-                for elem in list.iter() {
-                    if let Literal::Char(_) = elem {
-                        continue;
-                    } else {
-                        is_char_list = false;
-                        break;
+    // how the output instructions show a value: a string is shown without quotes
+    pub fn display_string(val: &Value) -> String {
+        match val {
+            Value::List(list) if list.iter().all(|elem| matches!(elem, Value::Char(_))) => list
+                .iter()
+                .filter_map(|elem| match elem {
+                    Value::Char(c) => Some(*c),
+                    _ => None,
+                })
+                .collect(),
+            _ => val.to_string(),
+        }
+    }
+
+    // how a value would be written in code, with strings and chars quoted and escaped
+    pub fn repr(val: &Value) -> String {
+        match val {
+            Value::Char(c) => format!("'{}'", escape(*c, '\'')),
+            Value::List(list) if list.is_empty() => "[]".to_string(),
+            Value::List(list) if list.iter().all(|elem| matches!(elem, Value::Char(_))) => {
+                let chars: String = list
+                    .iter()
+                    .filter_map(|elem| match elem {
+                        Value::Char(c) => Some(escape(*c, '"')),
+                        _ => None,
+                    })
+                    .collect();
+                format!("\"{}\"", chars)
+            }
+            Value::List(list) => {
+                let elems: Vec<String> = list.iter().map(repr).collect();
+                format!("[{}]", elems.join(", "))
+            }
+            _ => val.to_string(),
+        }
+    }
+
+    // the escape sequence the parser reads back as c, inside quotes of the given kind
+    fn escape(c: char, quote: char) -> String {
+        match c {
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            '\0' => "\\0".to_string(),
+            '\\' => "\\\\".to_string(),
+            _ if c == quote => format!("\\{}", c),
+            _ => c.to_string(),
+        }
+    }
+
+    fn write_value(output: &mut dyn Write, instr: &str, text: String, end: &str) -> FnResult {
+        write!(output, "{}{}", text, end).map_err(|_| format!("{}: error writing to output", instr))
+    }
+
+    pub fn ol(output: &mut dyn Write, val: Value) -> FnResult {
+        write_value(output, "ol", display_string(&val), "\n")
+    }
+    pub fn ow(output: &mut dyn Write, val: Value) -> FnResult {
+        write_value(output, "ow", display_string(&val), "")
+    }
+    pub fn el(error: &mut dyn Write, val: Value) -> FnResult {
+        write_value(error, "el", display_string(&val), "\n")
+    }
+    pub fn ew(error: &mut dyn Write, val: Value) -> FnResult {
+        write_value(error, "ew", display_string(&val), "")
+    }
+    pub fn od(output: &mut dyn Write, val: Value) -> FnResult {
+        write_value(output, "od", repr(&val), "\n")
+    }
+    pub fn printf(output: &mut dyn Write, text: String) -> FnResult {
+        write_value(output, "printf", text, "")
+    }
+
+    // pops a format string, then one argument for each directive in it, in order
+    // directives: %s any value as ol shows it, %d an int, %f a number (%.2f for 2 decimals),
+    // %c a char, and %% for a literal %
+    pub fn format_values(deque: &mut Deque, place: Place, instr: &str) -> Result<String, String> {
+        let fmt = String::from_value(pop_arg(deque, place)?)
+            .map_err(|e| format!("{}: format string: {}", instr, e))?;
+        let mut text = String::new();
+        let mut chars = fmt.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                text.push(c);
+                continue;
+            }
+            let mut precision = None;
+            if chars.peek() == Some(&'.') {
+                chars.next();
+                let mut digits = String::new();
+                while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(*d);
+                    chars.next();
+                }
+                precision = Some(
+                    digits
+                        .parse::<usize>()
+                        .map_err(|_| format!("{}: expected a precision after %.", instr))?,
+                );
+            }
+            match (chars.next(), precision) {
+                (Some('%'), None) => text.push('%'),
+                (Some('s'), None) => text.push_str(&display_string(&pop_arg(deque, place)?)),
+                (Some('d'), None) => match pop_arg(deque, place)? {
+                    Value::Int(i) => text.push_str(&i.to_string()),
+                    val => return Err(format_mismatch(instr, 'd', "Int", &val)),
+                },
+                (Some('f'), precision) => {
+                    let f = match pop_arg(deque, place)? {
+                        Value::Float(f) => f,
+                        Value::Int(i) => i as f64,
+                        val => return Err(format_mismatch(instr, 'f', "Float", &val)),
+                    };
+                    match precision {
+                        Some(precision) => text.push_str(&format!("{:.*}", precision, f)),
+                        None => text.push_str(&f.to_string()),
                     }
                 }
-                if is_char_list {
-                    let mut chars = String::new();
-                    for elem in list.iter() {
-                        if let Literal::Char(c) = elem {
-                            chars.push(*c);
+                (Some('c'), None) => match pop_arg(deque, place)? {
+                    Value::Char(c) => text.push(c),
+                    val => return Err(format_mismatch(instr, 'c', "Char", &val)),
+                },
+                (Some(d), _) => return Err(format!("{}: unknown directive %{}", instr, d)),
+                (None, _) => return Err(format!("{}: format string ends with %", instr)),
+            }
+        }
+        Ok(text)
+    }
+    pub fn format(deque: &mut Deque, place: Place) -> FnResult {
+        let text = format_values(deque, place, "format")?;
+        push_result_to(deque, place, text.into_value());
+        Ok(())
+    }
+    fn format_mismatch(instr: &str, directive: char, expected: &str, val: &Value) -> String {
+        format!(
+            "{}: %{} expected {}, got {}",
+            instr,
+            directive,
+            expected,
+            type_name_of(val)
+        )
+    }
+
+    // FILES
+    // paths are strings; failures are reported as EvalError::Io instead of panicking
+    fn path_of(instr: &str, val: Value) -> EvalResult<String> {
+        String::from_value(val).map_err(|e| EvalError::Instr(format!("{}: path: {}", instr, e)))
+    }
+    fn io_error(instr: &str, path: &str, err: io::Error) -> EvalError {
+        EvalError::Io(instr.to_string(), path.to_string(), err.kind())
+    }
+
+    pub fn fr(path: Value) -> EvalResult<Value> {
+        let path = path_of("fr", path)?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(contents.into_value()),
+            Err(e) => Err(io_error("fr", &path, e)),
+        }
+    }
+    pub fn frl(path: Value) -> EvalResult<Value> {
+        let path = path_of("frl", path)?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(contents.lines().collect::<Vec<_>>().into_value()),
+            Err(e) => Err(io_error("frl", &path, e)),
+        }
+    }
+    pub fn fw(path: Value, contents: Value) -> EvalResult<()> {
+        let path = path_of("fw", path)?;
+        let contents = String::from_value(contents).map_err(|e| format!("fw: {}", e))?;
+        fs::write(&path, contents).map_err(|e| io_error("fw", &path, e))
+    }
+    pub fn fa(path: Value, contents: Value) -> EvalResult<()> {
+        let path = path_of("fa", path)?;
+        let contents = String::from_value(contents).map_err(|e| format!("fa: {}", e))?;
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| io_error("fa", &path, e))
+    }
+    pub fn fe(path: Value) -> EvalResult<Value> {
+        let path = path_of("fe", path)?;
+        Ok(Value::Bool(Path::new(&path).exists()))
+    }
+    pub fn fls(path: Value) -> EvalResult<Value> {
+        let path = path_of("fls", path)?;
+        let entries = fs::read_dir(&path).map_err(|e| io_error("fls", &path, e))?;
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| io_error("fls", &path, e))?;
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names.into_value())
+    }
+    pub fn frm(path: Value) -> EvalResult<()> {
+        let path = path_of("frm", path)?;
+        fs::remove_file(&path).map_err(|e| io_error("frm", &path, e))
+    }
+
+    // BYTES
+    // binary data is a list of bytes, which are ints from 0 to 255
+    fn bytes_of(instr: &str, val: Value) -> Result<Vec<u8>, String> {
+        Vec::<u8>::from_value(val).map_err(|e| format!("{}: {}", instr, e))
+    }
+
+    // reads up to count bytes, fewer only at the end of the input
+    pub fn ib(input: &mut dyn BufRead, count: Value) -> ValResult {
+        let count = match count {
+            Value::Int(i) if i >= 0 => i as u64,
+            _ => return Err(format!("ib: expected a non-negative Int, got {}", count)),
+        };
+        let mut bytes = Vec::new();
+        match Read::take(input, count).read_to_end(&mut bytes) {
+            Ok(_) => Ok(bytes.into_value()),
+            Err(_) => Err("ib: error reading from input".to_string()),
+        }
+    }
+    pub fn iab(input: &mut dyn BufRead) -> ValResult {
+        let mut bytes = Vec::new();
+        match input.read_to_end(&mut bytes) {
+            Ok(_) => Ok(bytes.into_value()),
+            Err(_) => Err("iab: error reading from input".to_string()),
+        }
+    }
+    pub fn ob(output: &mut dyn Write, bytes: Value) -> FnResult {
+        let bytes = bytes_of("ob", bytes)?;
+        output
+            .write_all(&bytes)
+            .map_err(|_| "ob: error writing to output".to_string())
+    }
+    pub fn frb(path: Value) -> EvalResult<Value> {
+        let path = path_of("frb", path)?;
+        match fs::read(&path) {
+            Ok(bytes) => Ok(bytes.into_value()),
+            Err(e) => Err(io_error("frb", &path, e)),
+        }
+    }
+    pub fn fwb(path: Value, bytes: Value) -> EvalResult<()> {
+        let path = path_of("fwb", path)?;
+        let bytes = bytes_of("fwb", bytes)?;
+        fs::write(&path, bytes).map_err(|e| io_error("fwb", &path, e))
+    }
+
+    // converts a string to bytes: "utf8", "latin1" (every char must be below 256),
+    // or "hex" (the string is pairs of hex digits)
+    pub fn encode(encoding: Value, text: Value) -> ValResult {
+        let encoding = String::from_value(encoding).map_err(|e| format!("enc: encoding: {}", e))?;
+        let text = String::from_value(text).map_err(|e| format!("enc: {}", e))?;
+        let bytes = match encoding.as_str() {
+            "utf8" => text.into_bytes(),
+            "latin1" => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| format!("enc: {:?} is not latin1", c)))
+                .collect::<Result<_, _>>()?,
+            "hex" => {
+                let digits: Vec<char> = text.chars().collect();
+                if !digits.len().is_multiple_of(2) {
+                    return Err("enc: hex needs an even number of digits".to_string());
+                }
+                digits
+                    .chunks(2)
+                    .map(|pair| {
+                        let pair: String = pair.iter().collect();
+                        u8::from_str_radix(&pair, 16)
+                            .map_err(|_| format!("enc: {:?} is not a hex byte", pair))
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            _ => return Err(format!("enc: unknown encoding {:?}", encoding)),
+        };
+        Ok(bytes.into_value())
+    }
+    // the reverse of encode
+    pub fn decode(encoding: Value, bytes: Value) -> ValResult {
+        let encoding = String::from_value(encoding).map_err(|e| format!("dec: encoding: {}", e))?;
+        let bytes = bytes_of("dec", bytes)?;
+        let text = match encoding.as_str() {
+            "utf8" => String::from_utf8(bytes).map_err(|_| "dec: invalid utf8".to_string())?,
+            "latin1" => bytes.into_iter().map(char::from).collect(),
+            "hex" => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            _ => return Err(format!("dec: unknown encoding {:?}", encoding)),
+        };
+        Ok(text.into_value())
+    }
+
+    // PROCESS
+    // an unset variable pushes false instead of a string
+    pub fn env(name: Value) -> ValResult {
+        let name = String::from_value(name).map_err(|e| format!("env: {}", e))?;
+        match std::env::var(name) {
+            Ok(val) => Ok(val.into_value()),
+            Err(_) => Ok(Value::Bool(false)),
+        }
+    }
+    // every variable as a list of [name, value] pairs, sorted by name
+    pub fn envl() -> Value {
+        let mut vars: Vec<(String, String)> = std::env::vars().collect();
+        vars.sort();
+        vars.into_value()
+    }
+    // returns the error that stops the interpreter with the popped status
+    pub fn exit(code: Value) -> EvalResult<EvalError> {
+        match code {
+            Value::Int(code) => match i32::try_from(code) {
+                Ok(code) => Ok(EvalError::Exit(code)),
+                Err(_) => Err(EvalError::Instr(format!(
+                    "exit: status {} out of range",
+                    code
+                ))),
+            },
+            _ => Err(EvalError::Instr(format!(
+                "exit: expected Int, got {}",
+                type_name_of(&code)
+            ))),
+        }
+    }
+}
+
+pub mod eval_convert {
+    use super::eval_instr::type_name_of;
+    use super::eval_value::*;
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use std::hash::Hash;
+
+    // conversions between Rust values and deque values, for host functions
+    // derive these for your own structs with the rusty_deque_derive crate
+    pub trait IntoValue {
+        fn into_value(self) -> Value;
+    }
+
+    pub trait FromValue: Sized {
+        fn from_value(val: Value) -> Result<Self, String>;
+    }
+
+    fn expected(type_name: &str, val: &Value) -> String {
+        format!("expected {}, got {}", type_name, type_name_of(val))
+    }
+
+    impl IntoValue for Value {
+        fn into_value(self) -> Value {
+            self
+        }
+    }
+    impl FromValue for Value {
+        fn from_value(val: Value) -> Result<Self, String> {
+            Ok(val)
+        }
+    }
+
+    // a host function returning () pushes nothing
+    impl IntoValue for () {
+        fn into_value(self) -> Value {
+            Value::None
+        }
+    }
+
+    impl IntoValue for i64 {
+        fn into_value(self) -> Value {
+            Value::Int(self)
+        }
+    }
+    impl FromValue for i64 {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::Int(i) => Ok(i),
+                _ => Err(expected("Int", &val)),
+            }
+        }
+    }
+
+    // bytes are ints from 0 to 255
+    impl IntoValue for u8 {
+        fn into_value(self) -> Value {
+            Value::Int(self as i64)
+        }
+    }
+    impl FromValue for u8 {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::Int(i) => u8::try_from(i).map_err(|_| format!("expected byte, got {}", i)),
+                _ => Err(expected("Int", &val)),
+            }
+        }
+    }
+
+    impl IntoValue for f64 {
+        fn into_value(self) -> Value {
+            Value::Float(self)
+        }
+    }
+    // ints are accepted too, like the arithmetic instructions do
+    impl FromValue for f64 {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::Float(f) => Ok(f),
+                Value::Int(i) => Ok(i as f64),
+                _ => Err(expected("Float", &val)),
+            }
+        }
+    }
+
+    impl IntoValue for bool {
+        fn into_value(self) -> Value {
+            Value::Bool(self)
+        }
+    }
+    impl FromValue for bool {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::Bool(b) => Ok(b),
+                _ => Err(expected("Bool", &val)),
+            }
+        }
+    }
+
+    impl IntoValue for char {
+        fn into_value(self) -> Value {
+            Value::Char(self)
+        }
+    }
+    impl FromValue for char {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::Char(c) => Ok(c),
+                _ => Err(expected("Char", &val)),
+            }
+        }
+    }
+
+    // strings are lists of chars
+    impl IntoValue for String {
+        fn into_value(self) -> Value {
+            Value::List(self.chars().map(Value::Char).collect())
+        }
+    }
+    impl IntoValue for &str {
+        fn into_value(self) -> Value {
+            Value::List(self.chars().map(Value::Char).collect())
+        }
+    }
+    impl FromValue for String {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::List(list) => list
+                    .into_iter()
+                    .map(|v| match v {
+                        Value::Char(c) => Ok(c),
+                        _ => Err(expected("Char", &v)),
+                    })
+                    .collect(),
+                _ => Err(expected("List", &val)),
+            }
+        }
+    }
+
+    impl<T: IntoValue> IntoValue for Vec<T> {
+        fn into_value(self) -> Value {
+            Value::List(self.into_iter().map(IntoValue::into_value).collect())
+        }
+    }
+    impl<T: FromValue> FromValue for Vec<T> {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::List(list) => list.into_iter().map(T::from_value).collect(),
+                _ => Err(expected("List", &val)),
+            }
+        }
+    }
+
+    // None is the empty list, and Some(x) is a list of only x
+    impl<T: IntoValue> IntoValue for Option<T> {
+        fn into_value(self) -> Value {
+            Value::List(self.into_iter().map(IntoValue::into_value).collect())
+        }
+    }
+    impl<T: FromValue> FromValue for Option<T> {
+        fn from_value(val: Value) -> Result<Self, String> {
+            match val {
+                Value::List(mut list) if list.len() <= 1 => {
+                    list.pop().map(T::from_value).transpose()
+                }
+                _ => Err(expected("List of at most 1 element", &val)),
+            }
+        }
+    }
+
+    // tuples are lists with one element per field
+    macro_rules! tuple_conversions {
+        ($len:expr, $($name:ident),+) => {
+            impl<$($name: IntoValue),+> IntoValue for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn into_value(self) -> Value {
+                    let ($($name,)+) = self;
+                    Value::List(vec![$($name.into_value()),+])
+                }
+            }
+            impl<$($name: FromValue),+> FromValue for ($($name,)+) {
+                fn from_value(val: Value) -> Result<Self, String> {
+                    match val {
+                        Value::List(list) if list.len() == $len => {
+                            let mut iter = list.into_iter();
+                            Ok(($($name::from_value(iter.next().unwrap())?,)+))
                         }
+                        _ => Err(expected(concat!("List of ", $len, " elements"), &val)),
                     }
-                    print!("{}", chars);
-                } else {
-                    print!("{}", literal.to_string());
                 }
             }
-            _ => print!("{}", literal.to_string()),
         };
-        Ok(Value::None)
+    }
+    tuple_conversions!(1, A);
+    tuple_conversions!(2, A, B);
+    tuple_conversions!(3, A, B, C);
+    tuple_conversions!(4, A, B, C, D);
+
+    // maps are lists of [key, value] pairs
+    impl<K: IntoValue, V: IntoValue> IntoValue for HashMap<K, V> {
+        fn into_value(self) -> Value {
+            Value::List(self.into_iter().map(IntoValue::into_value).collect())
+        }
+    }
+    impl<K: FromValue + Eq + Hash, V: FromValue> FromValue for HashMap<K, V> {
+        fn from_value(val: Value) -> Result<Self, String> {
+            Vec::<(K, V)>::from_value(val).map(|pairs| pairs.into_iter().collect())
+        }
+    }
+}
+
+pub mod eval_io {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    // an in-memory output stream that can be read back after the interpreter has written to it
+    // clones share the same buffer, so keep a clone and give the other to Interpreter::with_output
+    #[derive(Debug, Clone, Default)]
+    pub struct SharedBuffer {
+        buffer: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl SharedBuffer {
+        pub fn new() -> SharedBuffer {
+            SharedBuffer::default()
+        }
+
+        pub fn contents(&self) -> Vec<u8> {
+            self.buffer.borrow().clone()
+        }
+
+        // the contents as text, with invalid UTF-8 replaced
+        pub fn text(&self) -> String {
+            String::from_utf8_lossy(&self.buffer.borrow()).to_string()
+        }
+
+        pub fn clear(&self) {
+            self.buffer.borrow_mut().clear();
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+pub mod eval_trace {
+    use super::eval::Capability;
+    use super::eval_error::EvalError;
+    use super::eval_instr::repr;
+    use super::eval_value::Value;
+    use crate::parser::par_ast::{Exec, Op};
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::{BufWriter, Write};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    // watches an interpreter run, once given to Interpreter::observe
+    // every method does nothing unless it is implemented
+    // paths are where execs and blocks are in the program, empty for blocks that weren't parsed,
+    // and depth is the number of blocks running, counting the top level
+    pub trait Observer {
+        // before an exec runs, with the deque it runs on
+        fn before_exec(
+            &mut self,
+            _exec: &Exec,
+            _path: &[usize],
+            _depth: usize,
+            _deque: &VecDeque<Value>,
+        ) {
+        }
+
+        // after an exec runs, or fails
+        fn after_exec(&mut self, _event: &TraceEvent) {}
+
+        // when a run fails, once, whether an exec or a limit failed it
+        fn on_error(&mut self, _error: &EvalError) {}
+
+        // when a block starts, including the code given to run at the top level
+        fn on_block_enter(&mut self, _path: &[usize], _depth: usize) {}
+
+        // after the last exec of a block, unless it failed
+        // a block run by the last exec of another starts after the other ends
+        fn on_block_exit(&mut self, _path: &[usize], _depth: usize) {}
+
+        // when an instruction is about to touch the outside world, once it has been allowed to
+        fn on_io(&mut self, _instr: &str, _capability: Capability) {}
+    }
+
+    // an observer shared with the code that reads what it saw after the run
+    impl<T: Observer> Observer for Rc<RefCell<T>> {
+        fn before_exec(
+            &mut self,
+            exec: &Exec,
+            path: &[usize],
+            depth: usize,
+            deque: &VecDeque<Value>,
+        ) {
+            self.borrow_mut().before_exec(exec, path, depth, deque)
+        }
+
+        fn after_exec(&mut self, event: &TraceEvent) {
+            self.borrow_mut().after_exec(event)
+        }
+
+        fn on_error(&mut self, error: &EvalError) {
+            self.borrow_mut().on_error(error)
+        }
+
+        fn on_block_enter(&mut self, path: &[usize], depth: usize) {
+            self.borrow_mut().on_block_enter(path, depth)
+        }
+
+        fn on_block_exit(&mut self, path: &[usize], depth: usize) {
+            self.borrow_mut().on_block_exit(path, depth)
+        }
+
+        fn on_io(&mut self, instr: &str, capability: Capability) {
+            self.borrow_mut().on_io(instr, capability)
+        }
+    }
+
+    // a closure given to Interpreter::with_tracer, called after every exec
+    pub struct Tracer<F: FnMut(&TraceEvent)>(pub F);
+
+    impl<F: FnMut(&TraceEvent)> Observer for Tracer<F> {
+        fn after_exec(&mut self, event: &TraceEvent) {
+            (self.0)(event)
+        }
+    }
+
+    // one exec, as seen by Observer::after_exec
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct TraceEvent {
+        // the steps run so far, counting this one
+        pub step: u64,
+        pub exec: Exec,
+        // where the exec is in the program, empty for blocks that weren't parsed
+        pub path: Vec<usize>,
+        // the number of blocks running, counting the top level
+        pub depth: usize,
+        pub before: VecDeque<Value>,
+        pub after: VecDeque<Value>,
+        // the error the exec failed with, if it did
+        pub error: Option<EvalError>,
+        // the time the exec took, not counting observers,
+        // and leaving out the blocks that map, filter and reduce run
+        pub elapsed: Duration,
+    }
+
+    impl TraceEvent {
+        // the exec as it would be written in code
+        pub fn exec_text(&self) -> String {
+            match &self.exec {
+                Exec::Left(op) => format!("{}!", op_text(op)),
+                Exec::Right(op) => format!("{}~", op_text(op)),
+            }
+        }
+
+        // the event as a JSON object, with values written as they would be in code
+        // the time is left out, so traces of the same code are the same
+        pub fn to_json(&self) -> serde_json::Value {
+            let (side, op) = match &self.exec {
+                Exec::Left(op) => ("left", op),
+                Exec::Right(op) => ("right", op),
+            };
+            let instruction = match op {
+                Op::Instruction(name) => json!(name),
+                Op::Literal(_) => json!(null),
+            };
+            let mut event = json!({
+                "step": self.step,
+                "exec": self.exec_text(),
+                "instruction": instruction,
+                "side": side,
+                "depth": self.depth,
+                "path": self.path,
+                "before": deque_json(&self.before),
+                "after": deque_json(&self.after),
+            });
+            if let Some(error) = &self.error {
+                event["error"] = json!(error.to_string());
+            }
+            event
+        }
+    }
+
+    fn op_text(op: &Op) -> String {
+        match op {
+            Op::Literal(lit) => repr(lit),
+            Op::Instruction(name) => name.clone(),
+        }
+    }
+
+    fn deque_json(deque: &VecDeque<Value>) -> serde_json::Value {
+        json!(deque.iter().map(repr).collect::<Vec<String>>())
+    }
+
+    // writes each exec to out as a line of JSON
+    pub struct JsonLines {
+        out: BufWriter<Box<dyn Write>>,
+    }
+
+    impl JsonLines {
+        pub fn new(out: Box<dyn Write>) -> JsonLines {
+            JsonLines {
+                out: BufWriter::new(out),
+            }
+        }
+    }
+
+    impl Observer for JsonLines {
+        fn after_exec(&mut self, event: &TraceEvent) {
+            let _ = writeln!(self.out, "{}", event.to_json());
+        }
     }
 }
 
 pub mod eval {
+    use super::eval_convert::*;
+    use super::eval_error::*;
+    use super::eval_trace::{Observer, TraceEvent, Tracer};
     use super::eval_value::*;
     use crate::evaluator::eval_instr::*;
     use crate::parser::par_ast::*;
 
-    use std::collections::VecDeque;
+    use std::collections::{HashMap, VecDeque};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::mem;
+    use std::ops::Deref;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
 
-    pub fn run_ast(deque: Option<VecDeque<Value>>, ast: Code) -> Result<VecDeque<Value>, String> {
-        let temp: VecDeque<Value> = VecDeque::new();
-        let d: &mut VecDeque<Value> = &mut deque.unwrap_or(temp);
+    // a pending piece of work on the continuation stack.
+    // control flow instructions push frames instead of recursing,
+    // so the nesting depth of running blocks is only bounded by the heap.
+    #[derive(Debug, Clone)]
+    pub enum Frame {
+        // run the execs of a block, starting at pc
+        Block {
+            code: Rc<Block>,
+            pc: usize,
+        },
+        // run the body again, forever
+        Loop {
+            body: Rc<Block>,
+        },
+        // push i and run the body, while i < upper
+        Range {
+            i: i64,
+            upper: i64,
+            step: i64,
+            body: Rc<Block>,
+            place: Place,
+        },
+        // read a line, then push it and run the body, until the end of the input
+        Lines {
+            body: Rc<Block>,
+            place: Place,
+        },
+        // run the condition, then if testing, check its result and run the body
+        While {
+            condition: Rc<Block>,
+            body: Rc<Block>,
+            place: Place,
+            testing: bool,
+        },
+        // check the result of the condition and run one of the branches
+        Ite {
+            true_block: Rc<Block>,
+            false_block: Rc<Block>,
+            place: Place,
+        },
+        // run a list function's body for each of its items
+        Each(Box<Each>),
+        // with effect checks on, check the deque length after an annotated block
+        Effect {
+            effect: StackEffect,
+            expected: usize,
+        },
+    }
+
+    // the body of map, filter or reduce runs on a deque of its own for each of the items,
+    // and what it leaves is collected
+    #[derive(Debug, Clone)]
+    pub struct Each {
+        pub collect: Collect,
+        pub items: VecDeque<Value>,
+        // the item the body last ran on, whose result is on the front of the deque
+        pub current: Option<Value>,
+        pub body: Rc<Block>,
+        // the deque the list function was called on, put back once the items are done
+        pub outer: Deque,
+    }
+
+    // what a list function keeps of the results of its block
+    #[derive(Debug, Clone)]
+    pub enum Collect {
+        Map(Vec<Value>),
+        Filter(Vec<Value>),
+        Reduce(Value),
+    }
+
+    impl Collect {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Collect::Map(_) => "map",
+                Collect::Filter(_) => "filter",
+                Collect::Reduce(_) => "reduce",
+            }
+        }
+    }
+
+    impl Frame {
+        pub fn new_block(code: Rc<Block>) -> Frame {
+            Frame::Block { code, pc: 0 }
+        }
+    }
+
+    // the continuation stack, which counts the block frames on it as they come and go
+    #[derive(Debug, Clone, Default)]
+    pub struct Frames {
+        frames: Vec<Frame>,
+        blocks: usize,
+    }
+
+    impl Frames {
+        pub fn push(&mut self, frame: Frame) {
+            if let Frame::Block { .. } = frame {
+                self.blocks += 1;
+            }
+            self.frames.push(frame);
+        }
+
+        pub fn pop(&mut self) -> Option<Frame> {
+            let frame = self.frames.pop()?;
+            if let Frame::Block { .. } = frame {
+                self.blocks -= 1;
+            }
+            Some(frame)
+        }
+
+        pub fn clear(&mut self) {
+            self.frames.clear();
+            self.blocks = 0;
+        }
+
+        // the number of block frames
+        pub fn blocks(&self) -> usize {
+            self.blocks
+        }
+
+        // the deque the outermost running list function was called on
+        pub fn outer_deque(&mut self) -> Option<&mut Deque> {
+            self.frames.iter_mut().find_map(|frame| match frame {
+                Frame::Each(each) => Some(&mut each.outer),
+                _ => None,
+            })
+        }
+    }
+
+    impl Deref for Frames {
+        type Target = [Frame];
 
-        for exec in ast {
+        fn deref(&self) -> &[Frame] {
+            &self.frames
+        }
+    }
+
+    // limits on a single run of the interpreter
+    // None means unlimited
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct Limits {
+        // number of steps: execs (literals and instructions) run, and turns of loops
+        pub max_steps: Option<u64>,
+        // number of values in the deque
+        pub max_deque_len: Option<usize>,
+        // number of values in the deque, counting every element of lists and blocks
+        pub max_total_size: Option<usize>,
+        // number of blocks running, like --max-depth
+        pub max_depth: Option<usize>,
+        // wall-clock time
+        pub timeout: Option<Duration>,
+    }
+
+    // something outside the interpreter that an instruction can touch
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Capability {
+        Stdin,
+        Stdout,
+        Stderr,
+        Files,
+        Env,
+        Time,
+        Random,
+    }
+
+    // the capabilities granted to an interpreter
+    // instructions that need a capability that isn't granted fail with EvalError::Permission
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Capabilities {
+        pub stdin: bool,
+        pub stdout: bool,
+        pub stderr: bool,
+        pub files: bool,
+        pub env: bool,
+        pub time: bool,
+        pub random: bool,
+    }
+
+    impl Default for Capabilities {
+        // the standard streams only
+        fn default() -> Self {
+            Capabilities {
+                stdin: true,
+                stdout: true,
+                stderr: true,
+                ..Capabilities::none()
+            }
+        }
+    }
+
+    impl Capabilities {
+        pub fn none() -> Capabilities {
+            Capabilities {
+                stdin: false,
+                stdout: false,
+                stderr: false,
+                files: false,
+                env: false,
+                time: false,
+                random: false,
+            }
+        }
+
+        pub fn all() -> Capabilities {
+            Capabilities {
+                stdin: true,
+                stdout: true,
+                stderr: true,
+                files: true,
+                env: true,
+                time: true,
+                random: true,
+            }
+        }
+
+        pub fn allows(&self, capability: Capability) -> bool {
+            match capability {
+                Capability::Stdin => self.stdin,
+                Capability::Stdout => self.stdout,
+                Capability::Stderr => self.stderr,
+                Capability::Files => self.files,
+                Capability::Env => self.env,
+                Capability::Time => self.time,
+                Capability::Random => self.random,
+            }
+        }
+    }
+
+    // the capability an instruction needs, if any
+    // every instruction that touches the outside world must be listed here
+    pub fn capability_of(instr: &str) -> Option<Capability> {
+        match instr {
+            "il" | "ia" | "ib" | "iab" | "eof" | "lines" => Some(Capability::Stdin),
+            "ol" | "ow" | "od" | "printf" | "ob" => Some(Capability::Stdout),
+            "el" | "ew" => Some(Capability::Stderr),
+            "fr" | "frl" | "fw" | "fa" | "fe" | "fls" | "frm" | "frb" | "fwb" => {
+                Some(Capability::Files)
+            }
+            "env" | "envl" => Some(Capability::Env),
+            _ => None,
+        }
+    }
+
+    // an instruction implemented by the program embedding the interpreter
+    // the arity shapes pop their arguments from the side the instruction was called on,
+    // like the builtins; nilary and unary results go back on that side, but binary and
+    // ternary results always go to the front; a result of Value::None pushes nothing
+    #[derive(Clone)]
+    pub enum HostFn {
+        Nilary(Rc<Nilary>),
+        Unary(Rc<Unary>),
+        Binary(Rc<Binary>),
+        Ternary(Rc<Ternary>),
+        // direct access to the deque, like the deque ops
+        Raw(Rc<Raw>),
+    }
+
+    impl HostFn {
+        pub fn new_nilary(func: impl Fn() -> ValResult + 'static) -> HostFn {
+            HostFn::Nilary(Rc::new(func))
+        }
+        pub fn new_unary(func: impl Fn(Value) -> ValResult + 'static) -> HostFn {
+            HostFn::Unary(Rc::new(func))
+        }
+        pub fn new_binary(func: impl Fn(Value, Value) -> ValResult + 'static) -> HostFn {
+            HostFn::Binary(Rc::new(func))
+        }
+        pub fn new_ternary(func: impl Fn(Value, Value, Value) -> ValResult + 'static) -> HostFn {
+            HostFn::Ternary(Rc::new(func))
+        }
+        pub fn new_raw(func: impl Fn(&mut VecDeque<Value>, Place) -> FnResult + 'static) -> HostFn {
+            HostFn::Raw(Rc::new(func))
+        }
+
+        // like the constructors above, but the arguments and result are converted
+        // from and to Rust types; an argument of the wrong type is an error
+        pub fn typed_nilary<R: IntoValue>(
+            func: impl Fn() -> Result<R, String> + 'static,
+        ) -> HostFn {
+            HostFn::new_nilary(move || Ok(func()?.into_value()))
+        }
+        pub fn typed_unary<A: FromValue, R: IntoValue>(
+            func: impl Fn(A) -> Result<R, String> + 'static,
+        ) -> HostFn {
+            HostFn::new_unary(move |a| Ok(func(A::from_value(a)?)?.into_value()))
+        }
+        pub fn typed_binary<A: FromValue, B: FromValue, R: IntoValue>(
+            func: impl Fn(A, B) -> Result<R, String> + 'static,
+        ) -> HostFn {
+            HostFn::new_binary(move |a, b| {
+                Ok(func(A::from_value(a)?, B::from_value(b)?)?.into_value())
+            })
+        }
+        pub fn typed_ternary<A: FromValue, B: FromValue, C: FromValue, R: IntoValue>(
+            func: impl Fn(A, B, C) -> Result<R, String> + 'static,
+        ) -> HostFn {
+            HostFn::new_ternary(move |a, b, c| {
+                Ok(func(A::from_value(a)?, B::from_value(b)?, C::from_value(c)?)?.into_value())
+            })
+        }
+
+        pub fn call(&self, deque: &mut Deque, place: Place) -> FnResult {
+            match self {
+                HostFn::Nilary(func) => nilary(deque, place, func.as_ref(), true),
+                HostFn::Unary(func) => unary(deque, place, func.as_ref(), true),
+                HostFn::Binary(func) => binary(deque, place, func.as_ref(), true),
+                HostFn::Ternary(func) => ternary(deque, place, func.as_ref(), true),
+                HostFn::Raw(func) => deque.modify(|values| func(values, place)),
+            }
+        }
+    }
+
+    pub struct Interpreter {
+        pub deque: Deque,
+        pub limits: Limits,
+        pub capabilities: Capabilities,
+        // where il and ia read from, ol and ow write to, and el and ew write to
+        pub input: Box<dyn BufRead>,
+        pub output: Box<dyn Write>,
+        pub error: Box<dyn Write>,
+        // the script arguments pushed by args
+        pub args: Vec<String>,
+        // whether blocks are checked against their stack effect annotations as they run
+        pub check_effects: bool,
+        host_fns: HashMap<String, HostFn>,
+        observers: Vec<Box<dyn Observer>>,
+        frames: Frames,
+        steps: u64,
+        started: Instant,
+        // when the observers last returned, to time execs without them
+        observed: Instant,
+    }
+
+    impl Default for Interpreter {
+        fn default() -> Self {
+            Interpreter::new()
+        }
+    }
+
+    impl Interpreter {
+        pub fn new() -> Interpreter {
+            Interpreter {
+                deque: Deque::new(),
+                limits: Limits::default(),
+                capabilities: Capabilities::default(),
+                input: Box::new(BufReader::new(io::stdin())),
+                output: Box::new(io::stdout()),
+                error: Box::new(io::stderr()),
+                args: Vec::new(),
+                check_effects: false,
+                host_fns: HashMap::new(),
+                observers: Vec::new(),
+                frames: Frames::default(),
+                steps: 0,
+                started: Instant::now(),
+                observed: Instant::now(),
+            }
+        }
+
+        pub fn with_deque(deque: VecDeque<Value>) -> Interpreter {
+            Interpreter {
+                deque: deque.into(),
+                ..Interpreter::new()
+            }
+        }
+
+        pub fn with_limits(mut self, limits: Limits) -> Interpreter {
+            self.limits = limits;
+            self
+        }
+
+        pub fn with_capabilities(mut self, capabilities: Capabilities) -> Interpreter {
+            self.capabilities = capabilities;
+            self
+        }
+
+        pub fn with_input(mut self, input: Box<dyn BufRead>) -> Interpreter {
+            self.input = input;
+            self
+        }
+
+        pub fn with_output(mut self, output: Box<dyn Write>) -> Interpreter {
+            self.output = output;
+            self
+        }
+
+        pub fn with_error(mut self, error: Box<dyn Write>) -> Interpreter {
+            self.error = error;
+            self
+        }
+
+        pub fn with_args(mut self, args: Vec<String>) -> Interpreter {
+            self.args = args;
+            self
+        }
+
+        pub fn with_effect_checks(mut self, check_effects: bool) -> Interpreter {
+            self.check_effects = check_effects;
+            self
+        }
+
+        // makes a native instruction callable by name from code run on this interpreter
+        // it takes precedence over a builtin with the same name
+        pub fn register(&mut self, name: &str, func: HostFn) {
+            self.host_fns.insert(name.to_string(), func);
+        }
+
+        pub fn with_host_fn(mut self, name: &str, func: HostFn) -> Interpreter {
+            self.register(name, func);
+            self
+        }
+
+        // tells the observer what every run on this interpreter does, after any observers before it
+        pub fn observe(&mut self, observer: impl Observer + 'static) {
+            self.observers.push(Box::new(observer));
+        }
+
+        pub fn with_observer(mut self, observer: impl Observer + 'static) -> Interpreter {
+            self.observe(observer);
+            self
+        }
+
+        // calls tracer after every exec, with the deque from before and after it
+        pub fn with_tracer(self, tracer: impl FnMut(&TraceEvent) + 'static) -> Interpreter {
+            self.with_observer(Tracer(tracer))
+        }
+
+        // runs code on the interpreter's deque
+        // the limits apply to each call separately
+        pub fn run(&mut self, code: Code) -> EvalResult<()> {
+            self.start(code);
+            let result = self.run_frames();
+            // make sure everything written so far is visible, even if the run failed
+            let _ = self.output.flush();
+            let _ = self.error.flush();
+            result
+        }
+
+        // sets up code to run on the interpreter's deque one exec at a time, with step
+        // the limits apply from here until the code is done
+        pub fn start(&mut self, code: Code) {
+            self.frames.clear();
+            self.steps = 0;
+            self.started = Instant::now();
+            self.observed = self.started;
+            self.frames.push(Frame::new_block(Rc::new(Block {
+                execs: code,
+                effect: None,
+                path: Vec::new(),
+            })));
+        }
+
+        // the exec that runs next, and its path in the program, or None once the code is done
+        // frames between execs, like the turns of a loop, run to get to it
+        pub fn next_exec(&mut self) -> EvalResult<Option<(Exec, Vec<usize>)>> {
+            loop {
+                match self.frames.last() {
+                    None => return Ok(None),
+                    Some(Frame::Block { code, pc }) if *pc < code.execs.len() => {
+                        let path = [&code.path[..], &[*pc]].concat();
+                        return Ok(Some((code.execs[*pc].clone(), path)));
+                    }
+                    Some(_) => self.run_step()?,
+                }
+            }
+        }
+
+        // runs the next exec, stopping before the first exec of any block it runs
+        pub fn step(&mut self) -> EvalResult<()> {
+            if self.next_exec()?.is_some() {
+                self.run_step()?;
+            }
+            self.next_exec().map(|_| ())
+        }
+
+        // runs the next exec, and the blocks it runs, until the exec after it is next
+        pub fn step_over(&mut self) -> EvalResult<()> {
+            let last = match self.frames.last() {
+                Some(Frame::Block { code, pc }) => *pc + 1 == code.execs.len(),
+                _ => false,
+            };
+            // the last exec of a block runs after its block is dropped
+            let depth = self.frames.len() - last as usize;
+            self.step()?;
+            while self.frames.len() > depth {
+                self.step()?;
+            }
+            Ok(())
+        }
+
+        // the number of blocks running, counting the code passed to start
+        pub fn depth(&self) -> usize {
+            self.frames.blocks()
+        }
+
+        // the continuation stack, with the frame that runs next last
+        pub fn frames(&self) -> &[Frame] {
+            &self.frames
+        }
+
+        // runs frames until the continuation stack is empty
+        // everything runs through here or step, one frame at a time: the list functions
+        // and control flow push frames instead of running blocks themselves
+        fn run_frames(&mut self) -> EvalResult<()> {
+            while !self.frames.is_empty() {
+                self.run_step()?;
+            }
+            Ok(())
+        }
+
+        // runs the frame on top of the continuation stack
+        fn run_step(&mut self) -> EvalResult<()> {
+            let result = self.try_step();
+            if let Err(e) = &result {
+                // a run that fails inside a list function is left with the deque it was called on
+                if let Some(outer) = self.frames.outer_deque() {
+                    self.deque = mem::take(outer);
+                }
+                for observer in self.observers.iter_mut() {
+                    observer.on_error(e);
+                }
+            }
+            result
+        }
+
+        fn try_step(&mut self) -> EvalResult<()> {
+            // every exec and every turn of a loop is a step,
+            // so even a loop with an empty body runs out of steps
+            self.steps += 1;
+            if let Some(max) = self.limits.max_steps {
+                if self.steps > max {
+                    return Err(EvalError::StepLimit(max));
+                }
+            }
+            let frame = self.frames.pop().unwrap();
+            self.run_frame(frame)?;
+            self.check_limits()
+        }
+
+        fn run_frame(&mut self, frame: Frame) -> EvalResult<()> {
+            match frame {
+                Frame::Block { code, pc } => {
+                    let observed = !self.observers.is_empty();
+                    // the frame was popped, so it counts as one more block running
+                    let depth = if observed { self.depth() + 1 } else { 0 };
+                    if pc == 0 {
+                        for observer in self.observers.iter_mut() {
+                            observer.on_block_enter(&code.path, depth);
+                        }
+                        if self.check_effects {
+                            if let Some(effect) = &code.effect {
+                                self.enter_effect(effect)?;
+                            }
+                        }
+                    }
+                    let exec = match code.execs.get(pc) {
+                        Some(exec) => exec.clone(),
+                        None => {
+                            // an empty block ends as it starts
+                            for observer in self.observers.iter_mut() {
+                                observer.on_block_exit(&code.path, depth);
+                            }
+                            return Ok(());
+                        }
+                    };
+                    if !observed {
+                        // a finished block is dropped before its last exec runs,
+                        // so a block called in tail position doesn't grow the stack
+                        if pc + 1 < code.execs.len() {
+                            self.frames.push(Frame::Block { code, pc: pc + 1 });
+                        }
+                        return self.run_exec(exec);
+                    }
+                    let path = [&code.path[..], &[pc]].concat();
+                    if pc + 1 < code.execs.len() {
+                        self.frames.push(Frame::Block { code, pc: pc + 1 });
+                        self.run_observed(exec, path, depth)?;
+                    } else {
+                        self.run_observed(exec, path, depth)?;
+                        for observer in self.observers.iter_mut() {
+                            observer.on_block_exit(&code.path, depth);
+                        }
+                    }
+                }
+                Frame::Loop { body } => {
+                    self.frames.push(Frame::Loop { body: body.clone() });
+                    self.frames.push(Frame::new_block(body));
+                }
+                Frame::Range {
+                    i,
+                    upper,
+                    step,
+                    body,
+                    place,
+                } => {
+                    if i < upper {
+                        match place {
+                            Place::Left => self.deque.push_front(Value::Int(i)),
+                            Place::Right => self.deque.push_back(Value::Int(i)),
+                        }
+                        self.frames.push(Frame::Range {
+                            i: i + step,
+                            upper,
+                            step,
+                            body: body.clone(),
+                            place,
+                        });
+                        self.frames.push(Frame::new_block(body));
+                    }
+                }
+                Frame::Lines { body, place } => {
+                    if let Some(line) = read_line(&mut self.input, "lines")? {
+                        self.push(place, line.into_value());
+                        self.frames.push(Frame::Lines {
+                            body: body.clone(),
+                            place,
+                        });
+                        self.frames.push(Frame::new_block(body));
+                    }
+                }
+                Frame::While {
+                    condition,
+                    body,
+                    place,
+                    testing,
+                } => {
+                    if !testing {
+                        self.frames.push(Frame::While {
+                            condition: condition.clone(),
+                            body,
+                            place,
+                            testing: true,
+                        });
+                        self.frames.push(Frame::new_block(condition));
+                        return Ok(());
+                    }
+                    let top = match place {
+                        Place::Left => self.deque.pop_front(),
+                        Place::Right => self.deque.pop_back(),
+                    };
+                    match top {
+                        // exit the loop if the condition is false
+                        Some(top) => {
+                            if truthiness_of(top) {
+                                self.frames.push(Frame::While {
+                                    condition,
+                                    body: body.clone(),
+                                    place,
+                                    testing: false,
+                                });
+                                self.frames.push(Frame::new_block(body));
+                            }
+                        }
+                        None => {
+                            return Err("while: failed to get condition result".to_string().into())
+                        }
+                    }
+                }
+                Frame::Ite {
+                    true_block,
+                    false_block,
+                    place,
+                } => {
+                    // check the truthiness of the top of the stack
+                    let truth = match place {
+                        Place::Left => self.deque.pop_front(),
+                        Place::Right => self.deque.pop_back(),
+                    };
+                    match truth {
+                        Some(truth) => {
+                            if truthiness_of(truth) {
+                                self.frames.push(Frame::new_block(true_block));
+                            } else {
+                                self.frames.push(Frame::new_block(false_block));
+                            }
+                        }
+                        None => {
+                            return Err("ite: failed to get condition result".to_string().into())
+                        }
+                    }
+                }
+                Frame::Each(mut each) => {
+                    if let Some(current) = each.current.take() {
+                        let result = match self.deque.pop_front() {
+                            Some(result) => result,
+                            None => {
+                                self.deque = mem::take(&mut each.outer);
+                                let msg =
+                                    format!("{}: the block left nothing", each.collect.name());
+                                return Err(msg.into());
+                            }
+                        };
+                        match &mut each.collect {
+                            Collect::Map(list) => list.push(result),
+                            Collect::Filter(list) => {
+                                if let Value::Bool(true) = result {
+                                    list.push(current);
+                                }
+                            }
+                            Collect::Reduce(acc) => *acc = result,
+                        }
+                    }
+                    match each.items.pop_front() {
+                        Some(item) => {
+                            self.deque.push_front(item.clone());
+                            if let Collect::Reduce(acc) = &each.collect {
+                                self.deque.push_back(acc.clone());
+                            }
+                            let body = each.body.clone();
+                            each.current = Some(item);
+                            self.frames.push(Frame::Each(each));
+                            self.frames.push(Frame::new_block(body));
+                        }
+                        None => {
+                            let Each { collect, outer, .. } = *each;
+                            self.deque = outer;
+                            let result = match collect {
+                                Collect::Map(list) | Collect::Filter(list) => Value::List(list),
+                                Collect::Reduce(acc) => acc,
+                            };
+                            // like the other instructions of two or three values
+                            self.deque.push_front(result);
+                        }
+                    }
+                }
+                Frame::Effect { effect, expected } => {
+                    if self.deque.len() != expected {
+                        let msg = format!(
+                            "the deque should have {} values after the block, but it has {}",
+                            expected,
+                            self.deque.len()
+                        );
+                        return Err(EvalError::Effect(effect, msg));
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        // checks that the deque has the inputs of an annotated block,
+        // and leaves a frame to check its outputs once the block is done
+        fn enter_effect(&mut self, effect: &StackEffect) -> EvalResult<()> {
+            let len = self.deque.len();
+            if len < effect.inputs() {
+                let msg = format!(
+                    "the block needs {} values, but the deque has {}",
+                    effect.inputs(),
+                    len
+                );
+                return Err(EvalError::Effect(effect.clone(), msg));
+            }
+            let expected = len - effect.inputs() + effect.outputs();
+            // a block called in tail position ends when its caller does, so an effect frame on
+            // top is checked right after this one; when both expect the same length they are
+            // the same check, and an annotated block that calls itself runs in constant space
+            if let Some(Frame::Effect {
+                expected: outer, ..
+            }) = self.frames.last()
+            {
+                if *outer == expected {
+                    self.frames.pop();
+                }
+            }
+            self.frames.push(Frame::Effect {
+                effect: effect.clone(),
+                expected,
+            });
+            Ok(())
+        }
+
+        fn check_limits(&self) -> EvalResult<()> {
+            if let Some(max) = self.limits.max_deque_len {
+                if self.deque.len() > max {
+                    return Err(EvalError::DequeLimit(max));
+                }
+            }
+            if let Some(max) = self.limits.max_total_size {
+                if self.deque.total_size() > max {
+                    return Err(EvalError::SizeLimit(max));
+                }
+            }
+            if let Some(max) = self.limits.max_depth {
+                if self.frames.blocks() > max {
+                    return Err(EvalError::DepthLimit(max));
+                }
+            }
+            if let Some(max) = self.limits.timeout {
+                if self.started.elapsed() > max {
+                    return Err(EvalError::Timeout(max));
+                }
+            }
+            Ok(())
+        }
+
+        // runs an exec, telling the observers before and after
+        fn run_observed(&mut self, exec: Exec, path: Vec<usize>, depth: usize) -> EvalResult<()> {
+            for observer in self.observers.iter_mut() {
+                observer.before_exec(&exec, &path, depth, &self.deque);
+            }
+            let before = VecDeque::clone(&self.deque);
+            self.observed = Instant::now();
+            let result = self.run_exec(exec.clone());
+            let elapsed = self.observed.elapsed();
+            let event = TraceEvent {
+                step: self.steps,
+                exec,
+                path,
+                depth,
+                before,
+                after: VecDeque::clone(&self.deque),
+                error: result.as_ref().err().cloned(),
+                elapsed,
+            };
+            for observer in self.observers.iter_mut() {
+                observer.after_exec(&event);
+            }
+            self.observed = Instant::now();
+            result
+        }
+
+        // pushes a literal or calls an instruction on the side given by the sigil
+        fn run_exec(&mut self, exec: Exec) -> EvalResult<()> {
             match exec {
                 Exec::Left(op) => match op {
                     Op::Literal(lit) => {
-                        d.push_front(lit);
-                    }
-                    Op::Instruction(instruction) => {
-                        let result = call_instr(d, instruction, Place::Left);
-                        if let Err(err) = result {
-                            return Err(err);
-                        }
+                        self.deque.push_front(lit);
+                        Ok(())
                     }
+                    Op::Instruction(instruction) => self.call_instr(instruction, Place::Left),
                 },
                 Exec::Right(op) => match op {
                     Op::Literal(lit) => {
-                        d.push_back(lit);
-                    }
-                    Op::Instruction(instruction) => {
-                        let result = call_instr(d, instruction, Place::Right);
-                        if let Err(err) = result {
-                            return Err(err);
-                        }
+                        self.deque.push_back(lit);
+                        Ok(())
                     }
+                    Op::Instruction(instruction) => self.call_instr(instruction, Place::Right),
                 },
             }
         }
-        Ok(d.to_owned())
+
+        fn pop(&mut self, place: Place) -> EvalResult<Value> {
+            let val = match place {
+                Place::Left => self.deque.pop_front(),
+                Place::Right => self.deque.pop_back(),
+            };
+            val.ok_or_else(|| EvalError::Instr("tried to pop empty deque".to_string()))
+        }
+
+        fn push(&mut self, place: Place, val: Value) {
+            match place {
+                Place::Left => self.deque.push_front(val),
+                Place::Right => self.deque.push_back(val),
+            }
+        }
+
+        // host functions and instructions that need the interpreter itself,
+        // everything else goes to the builtins in call_instr
+        fn call_instr(&mut self, instr: String, place: Place) -> EvalResult<()> {
+            if let Some(capability) = capability_of(&instr) {
+                if !self.capabilities.allows(capability) {
+                    return Err(EvalError::Permission(instr, capability));
+                }
+                for observer in self.observers.iter_mut() {
+                    observer.on_io(&instr, capability);
+                }
+            }
+            if let Some(func) = self.host_fns.get(&instr) {
+                return Ok(func.call(&mut self.deque, place)?);
+            }
+            match instr.as_str() {
+                // IO
+                "il" => {
+                    let line = il(&mut self.input)?;
+                    self.push(place, line);
+                    Ok(())
+                }
+                "ia" => {
+                    let all = ia(&mut self.input)?;
+                    self.push(place, all);
+                    Ok(())
+                }
+                "eof" => {
+                    let at_end = eof(&mut self.input)?;
+                    self.push(place, at_end);
+                    Ok(())
+                }
+                "ol" => {
+                    let val = self.pop(place)?;
+                    Ok(ol(&mut self.output, val)?)
+                }
+                "ow" => {
+                    let val = self.pop(place)?;
+                    Ok(ow(&mut self.output, val)?)
+                }
+                "el" => {
+                    let val = self.pop(place)?;
+                    Ok(el(&mut self.error, val)?)
+                }
+                "ew" => {
+                    let val = self.pop(place)?;
+                    Ok(ew(&mut self.error, val)?)
+                }
+                "od" => {
+                    let val = self.pop(place)?;
+                    Ok(od(&mut self.output, val)?)
+                }
+                "printf" => {
+                    let text = format_values(&mut self.deque, place, "printf")?;
+                    Ok(printf(&mut self.output, text)?)
+                }
+                "ib" => {
+                    let count = self.pop(place)?;
+                    let bytes = ib(&mut self.input, count)?;
+                    self.push(place, bytes);
+                    Ok(())
+                }
+                "iab" => {
+                    let bytes = iab(&mut self.input)?;
+                    self.push(place, bytes);
+                    Ok(())
+                }
+                "ob" => {
+                    let bytes = self.pop(place)?;
+                    Ok(ob(&mut self.output, bytes)?)
+                }
+                // FILES
+                "fr" => {
+                    let path = self.pop(place)?;
+                    let result = fr(path)?;
+                    self.push(place, result);
+                    Ok(())
+                }
+                "frl" => {
+                    let path = self.pop(place)?;
+                    let result = frl(path)?;
+                    self.push(place, result);
+                    Ok(())
+                }
+                "fe" => {
+                    let path = self.pop(place)?;
+                    let result = fe(path)?;
+                    self.push(place, result);
+                    Ok(())
+                }
+                "fls" => {
+                    let path = self.pop(place)?;
+                    let result = fls(path)?;
+                    self.push(place, result);
+                    Ok(())
+                }
+                "fw" => {
+                    let path = self.pop(place)?;
+                    let contents = self.pop(place)?;
+                    fw(path, contents)
+                }
+                "fa" => {
+                    let path = self.pop(place)?;
+                    let contents = self.pop(place)?;
+                    fa(path, contents)
+                }
+                "frm" => {
+                    let path = self.pop(place)?;
+                    frm(path)
+                }
+                "frb" => {
+                    let path = self.pop(place)?;
+                    let result = frb(path)?;
+                    self.push(place, result);
+                    Ok(())
+                }
+                "fwb" => {
+                    let path = self.pop(place)?;
+                    let bytes = self.pop(place)?;
+                    fwb(path, bytes)
+                }
+                // PROCESS
+                "args" => {
+                    let args = self.args.clone().into_value();
+                    self.push(place, args);
+                    Ok(())
+                }
+                "env" => {
+                    let name = self.pop(place)?;
+                    let val = env(name)?;
+                    self.push(place, val);
+                    Ok(())
+                }
+                "envl" => {
+                    self.push(place, envl());
+                    Ok(())
+                }
+                "exit" => {
+                    let code = self.pop(place)?;
+                    Err(exit(code)?)
+                }
+                _ => Ok(call_instr(&mut self.deque, &mut self.frames, instr, place)?),
+            }
+        }
     }
 
-    pub fn call_instr(deque: &mut VecDeque<Value>, instr: String, place: Place) -> FnResult {
+    pub fn run_ast(deque: Option<VecDeque<Value>>, ast: Code) -> Result<VecDeque<Value>, String> {
+        let mut interp = Interpreter::with_deque(deque.unwrap_or_default());
+        interp.run(ast)?;
+        Ok(interp.deque.into())
+    }
+
+    pub fn call_instr(
+        deque: &mut Deque,
+        frames: &mut Frames,
+        instr: String,
+        place: Place,
+    ) -> FnResult {
         match instr.as_str() {
             // DEQUE OPS
             "clear" => clear(deque, place),
@@ -1175,42 +2999,57 @@ pub mod eval {
             "len" => len(deque, place),
             "swap" => swap(deque, place),
             // CASTINGS
-            "toInt" => unary(deque, place, cast_to_int, true),
-            "toFloat" => unary(deque, place, cast_to_float, true),
-            "toChar" => unary(deque, place, cast_to_char, true),
-            "toBool" => unary(deque, place, cast_to_bool, true),
+            "toInt" => unary(deque, place, &cast_to_int, true),
+            "toFloat" => unary(deque, place, &cast_to_float, true),
+            "toChar" => unary(deque, place, &cast_to_char, true),
+            "toBool" => unary(deque, place, &cast_to_bool, true),
+            "toStr" => unary(deque, place, &cast_to_str, true),
+            // FORMATTING
+            "format" => format(deque, place),
+            // BYTES
+            "enc" => binary(deque, place, &encode, true),
+            "dec" => binary(deque, place, &decode, true),
+            // TYPE INTROSPECTION
+            "type" => unary(deque, place, &type_of, true),
+            "isInt" => unary(deque, place, &is_int, true),
+            "isFloat" => unary(deque, place, &is_float, true),
+            "isBool" => unary(deque, place, &is_bool, true),
+            "isChar" => unary(deque, place, &is_char, true),
+            "isList" => unary(deque, place, &is_list, true),
+            "isStr" => unary(deque, place, &is_str, true),
+            "isBlock" => unary(deque, place, &is_block, true),
             // INT/FLOAT OPS
-            "+" => binary(deque, place, add, true),
-            "-" => binary(deque, place, sub, true),
-            "*" => binary(deque, place, mult, true),
-            "/" => binary(deque, place, intdiv, true),
-            "//" => binary(deque, place, floatdiv, true),
-            "%" => binary(deque, place, modulo, true),
-            "exp" => binary(deque, place, exp, true),
-            "log" => binary(deque, place, log, true),
-            "--" => unary(deque, place, neg, true),
-            "&" => binary(deque, place, bitand, true),
-            "|" => binary(deque, place, bitor, true),
-            "^" => binary(deque, place, bitxor, true),
-            "n" => unary(deque, place, bitnot, true),
+            "+" => binary(deque, place, &add, true),
+            "-" => binary(deque, place, &sub, true),
+            "*" => binary(deque, place, &mult, true),
+            "/" => binary(deque, place, &intdiv, true),
+            "//" => binary(deque, place, &floatdiv, true),
+            "%" => binary(deque, place, &modulo, true),
+            "exp" => binary(deque, place, &exp, true),
+            "log" => binary(deque, place, &log, true),
+            "--" => unary(deque, place, &neg, true),
+            "&" => binary(deque, place, &bitand, true),
+            "|" => binary(deque, place, &bitor, true),
+            "^" => binary(deque, place, &bitxor, true),
+            "n" => unary(deque, place, &bitnot, true),
             // COMPARISON OPS
-            "=" => binary(deque, place, eq, true),
-            "ne" => binary(deque, place, neq, true),
-            "<" => binary(deque, place, lt, true),
-            ">" => binary(deque, place, gt, true),
-            "<=" => binary(deque, place, leq, true),
-            ">=" => binary(deque, place, geq, true),
+            "=" => binary(deque, place, &eq, true),
+            "ne" => binary(deque, place, &neq, true),
+            "<" => binary(deque, place, &lt, true),
+            ">" => binary(deque, place, &gt, true),
+            "<=" => binary(deque, place, &leq, true),
+            ">=" => binary(deque, place, &geq, true),
             // LOGICAL OPS
-            "nn" => unary(deque, place, lognot, true),
-            "&&" => binary(deque, place, logand, true),
-            "||" => binary(deque, place, logor, true),
+            "nn" => unary(deque, place, &lognot, true),
+            "&&" => binary(deque, place, &logand, true),
+            "||" => binary(deque, place, &logor, true),
 
             // LIST OPS
-            "l+" => binary(deque, place, listcat, true),
-            "lj" => binary(deque, place, listjoin, true),
-            "l/" => ternary(deque, place, listslice, true),
-            "li" => binary(deque, place, listindex, true),
-            "ll" => unary(deque, place, listlen, true),
+            "l+" => binary(deque, place, &listcat, true),
+            "lj" => binary(deque, place, &listjoin, true),
+            "l/" => ternary(deque, place, &listslice, true),
+            "li" => binary(deque, place, &listindex, true),
+            "ll" => unary(deque, place, &listlen, true),
             "lb" => {
                 let lit = match place {
                     Place::Left => deque.pop_front(),
@@ -1268,23 +3107,141 @@ pub mod eval {
                 }
             }
             // LIST FUNCTIONS
-            "map" => binary(deque, place, map, true),
-            "filter" => binary(deque, place, filter, true),
-            "reduce" => ternary(deque, place, reduce, true),
+            "map" => map(deque, frames, place),
+            "filter" => filter(deque, frames, place),
+            "reduce" => reduce(deque, frames, place),
 
             // CONTROL FLOW OPS
-            "exec" => exec(deque, place),
-            "loop" => loop_instr(deque, place),
-            "range" => range(deque, place),
-            "while" => while_instr(deque, place),
-            "ite" => ite(deque, place),
-
-            // IO
-            "il" => nilary(deque, place, il, true),
-            "ia" => nilary(deque, place, ia, true),
-            "ol" => unary(deque, place, ol, false),
-            "ow" => unary(deque, place, ow, false),
+            "exec" => exec(deque, frames, place),
+            "loop" => loop_instr(deque, frames, place),
+            "lines" => lines(deque, frames, place),
+            "range" => range(deque, frames, place),
+            "while" => while_instr(deque, frames, place),
+            "ite" => ite(deque, frames, place),
+
+            // IO is implemented in Interpreter::call_instr
             _ => Err(format!("Unknown instruction: {}", instr)),
         }
     }
 }
+
+pub mod eval_docs {
+    // documentation for every builtin instruction, for editor tooling
+    // effects list the popped values first, in the order they are popped: `( a b -- c )`
+
+    pub struct InstrDoc {
+        pub name: &'static str,
+        pub effect: &'static str,
+        pub summary: &'static str,
+    }
+
+    macro_rules! docs {
+        ($($name:expr, $effect:expr, $summary:expr;)*) => {
+            &[$(InstrDoc { name: $name, effect: $effect, summary: $summary }),*]
+        };
+    }
+
+    pub const INSTR_DOCS: &[InstrDoc] = docs![
+        // DEQUE OPS
+        "clear", "( ... -- )", "Clear the deque.";
+        "pop", "( a -- )", "Discard one element.";
+        "dup", "( a -- a a )", "Duplicate one element.";
+        "swap", "( a b -- b a )", "Swap the two elements on this side.";
+        "rot", "( -- )", "Rotate the deque one element: `rot~` moves the back element to the front, `rot!` the front element to the back.";
+        "over", "( a b -- b a b )", "Duplicate the element below the one on this side.";
+        "len", "( -- n )", "Push the length of the deque.";
+        // CASTS
+        "toInt", "( a -- int )", "Cast to an int. Floats are truncated, chars give their code point, bools give 1 or 0.";
+        "toFloat", "( a -- float )", "Cast to a float.";
+        "toChar", "( a -- char )", "Cast an int to the char with that code point.";
+        "toBool", "( a -- bool )", "Cast to a bool by truthiness.";
+        "toStr", "( a -- str )", "Push the printed representation of a value as a string.";
+        // TYPES
+        "type", "( a -- str )", "Push the name of the value's type: Int, Float, Bool, Char, List or Block.";
+        "isInt", "( a -- bool )", "Whether the value is an int.";
+        "isFloat", "( a -- bool )", "Whether the value is a float.";
+        "isBool", "( a -- bool )", "Whether the value is a bool.";
+        "isChar", "( a -- bool )", "Whether the value is a char.";
+        "isList", "( a -- bool )", "Whether the value is a list.";
+        "isStr", "( a -- bool )", "Whether the value is a list of only chars, including the empty list.";
+        "isBlock", "( a -- bool )", "Whether the value is a block.";
+        // NUMBERS
+        "+", "( a b -- a+b )", "Sum.";
+        "-", "( a b -- a-b )", "Difference.";
+        "*", "( a b -- a*b )", "Product.";
+        "/", "( a b -- a/b )", "Integer division.";
+        "//", "( a b -- a/b )", "Float division.";
+        "%", "( a b -- a%b )", "Remainder.";
+        "exp", "( a b -- a^b )", "Exponent, as a float.";
+        "log", "( a b -- log_a[b] )", "Logarithm of b in base a, as a float.";
+        "--", "( a -- -a )", "Negation.";
+        "&", "( a b -- a&b )", "Bitwise AND.";
+        "|", "( a b -- a|b )", "Bitwise OR.";
+        "^", "( a b -- a^b )", "Bitwise XOR.";
+        "n", "( a -- ~a )", "Bitwise NOT.";
+        // COMPARISONS
+        "=", "( a b -- bool )", "Whether a equals b.";
+        "ne", "( a b -- bool )", "Whether a does not equal b.";
+        "<", "( a b -- bool )", "Whether a is less than b.";
+        ">", "( a b -- bool )", "Whether a is greater than b.";
+        "<=", "( a b -- bool )", "Whether a is at most b.";
+        ">=", "( a b -- bool )", "Whether a is at least b.";
+        "nn", "( a -- bool )", "Logical NOT.";
+        "&&", "( a b -- bool )", "Logical AND.";
+        "||", "( a b -- bool )", "Logical OR.";
+        // LISTS
+        "l+", "( a b -- list )", "Concatenate two values or lists into a list.";
+        "lj", "( list v -- list )", "Join: put v between the elements of the list.";
+        "l/", "( list a b -- list )", "Slice the list from index a, inclusive, to b, exclusive.";
+        "li", "( list i -- a )", "Push the element at index i.";
+        "ll", "( list -- n )", "Push the length of the list.";
+        "lb", "( n ... -- list )", "Build a list from the next n elements.";
+        "ld", "( list -- ... )", "Push every element of the list.";
+        "map", "( list block -- list )", "Run the block on each element, collecting the results.";
+        "filter", "( list block -- list )", "Keep the elements the block leaves `true` for.";
+        "reduce", "( list acc block -- acc )", "Fold the list into the accumulator with the block.";
+        // CONTROL FLOW
+        "exec", "( block -- )", "Run a block.";
+        "loop", "( block -- )", "Run a block forever.";
+        "range", "( a b c block -- )", "For i from a to b, exclusive, in steps of c: push i and run the block.";
+        "while", "( cond body -- )", "Run the body while the condition block leaves a truthy value.";
+        "ite", "( cond then else -- )", "Run the condition block, then the then block if it left a truthy value, otherwise the else block.";
+        // IO
+        "il", "( -- str )", "Read a line from stdin, without its newline. Pushes false at the end of the input.";
+        "ia", "( -- str )", "Read all of stdin.";
+        "eof", "( -- bool )", "Whether stdin is at the end of the input.";
+        "lines", "( block -- )", "For each line of stdin: push it and run the block.";
+        "ol", "( a -- )", "Print a value with a newline. Strings print bare.";
+        "ow", "( a -- )", "Print a value. Strings print bare.";
+        "el", "( a -- )", "Print a value with a newline to stderr.";
+        "ew", "( a -- )", "Print a value to stderr.";
+        "od", "( a -- )", "Print a value as it would be written in code, with a newline.";
+        "printf", "( fmt args... -- )", "Print a format string, filling %s %d %f %c directives from the deque.";
+        "format", "( fmt args... -- str )", "Like printf, but push the result as a string.";
+        // FILES
+        "fr", "( path -- str )", "Read a file as a string.";
+        "frl", "( path -- list )", "Read a file as a list of lines.";
+        "fw", "( path str -- )", "Write a string to a file, replacing its contents.";
+        "fa", "( path str -- )", "Append a string to a file.";
+        "fe", "( path -- bool )", "Whether a file or directory exists.";
+        "fls", "( path -- list )", "List the entries of a directory, sorted.";
+        "frm", "( path -- )", "Delete a file.";
+        // BYTES
+        "ib", "( n -- bytes )", "Read up to n bytes from stdin.";
+        "iab", "( -- bytes )", "Read all of stdin as bytes.";
+        "ob", "( bytes -- )", "Write bytes to stdout.";
+        "frb", "( path -- bytes )", "Read a file as bytes.";
+        "fwb", "( path bytes -- )", "Write bytes to a file.";
+        "enc", "( encoding str -- bytes )", "Encode a string as utf8, latin1 or hex bytes.";
+        "dec", "( encoding bytes -- str )", "Decode bytes from utf8, latin1 or hex.";
+        // PROCESS
+        "args", "( -- list )", "Push the script's arguments as a list of strings.";
+        "env", "( name -- str )", "Push an environment variable, or false if it is not set.";
+        "envl", "( -- list )", "Push every environment variable as [name, value] pairs.";
+        "exit", "( code -- )", "Stop the program with an exit status.";
+    ];
+
+    pub fn instr_doc(name: &str) -> Option<&'static InstrDoc> {
+        INSTR_DOCS.iter().find(|doc| doc.name == name)
+    }
+}
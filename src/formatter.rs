@@ -0,0 +1,280 @@
+pub mod fmt {
+
+    use crate::lexer::lex::tokenize_with_trivia;
+    use crate::lexer::lex_token::{Trivia, TriviaKind, TriviaToken};
+    use crate::parser::par_ast::StackEffect;
+    use crate::parser::par_cst::*;
+
+    // how formatted code is laid out
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FormatOptions {
+        pub max_width: usize, // lines are wrapped before an exec that would pass this many chars
+        pub indent: usize,    // spaces per level of nesting
+    }
+
+    impl Default for FormatOptions {
+        fn default() -> Self {
+            FormatOptions {
+                max_width: 80,
+                indent: 4,
+            }
+        }
+    }
+
+    // the program as the formatter sees it: the syntax tree, plus comments and line breaks
+    enum Node {
+        Item(Operand, Option<char>), // an exec with its sigil, or a list element without one
+        Effect(String),              // the stack effect annotation at the start of a block
+        Comment(String),
+        Break(usize),
+    }
+
+    enum Operand {
+        Atom(String), // a literal or instruction, printed as spelled in the source
+        List(Vec<Node>),
+        Block(Vec<Node>),
+    }
+
+    // formats code in the canonical style:
+    // - execs on a line are separated by single spaces, list elements by `, `
+    // - line breaks are kept, but runs of blank lines become one blank line
+    // - a line is wrapped before an exec that would make it longer than max_width
+    // - blocks and lists stay on one line if they fit and had no line breaks or comments,
+    //   otherwise their contents go on their own lines, indented one level
+    // - comments are kept, after the code on their line or on a line of their own
+    // formatting formatted code gives back the same code
+    pub fn format_code(source: &str, options: &FormatOptions) -> Result<String, String> {
+        let cst = parse_cst(tokenize_with_trivia(source))?;
+        // lowering checks the literals, such as char escapes
+        cst.to_ast()?;
+        let nodes = exec_nodes(&cst.execs, &cst.end);
+        let mut printer = Printer {
+            options,
+            lines: Vec::new(),
+            line: String::new(),
+            depth: 0,
+        };
+        printer.print_nodes(&nodes);
+        printer.flush();
+
+        let mut formatted = printer.lines.join("\n");
+        if !formatted.is_empty() {
+            formatted.push('\n');
+        }
+        Ok(formatted)
+    }
+
+    // the nodes of a sequence of execs, with the trivia before each one
+    fn exec_nodes(execs: &[CstExec], end: &TriviaToken) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        for exec in execs {
+            push_trivia(&exec.op.first_token().leading, &mut nodes);
+            let sigil = if exec.is_left() { '!' } else { '~' };
+            nodes.push(Node::Item(operand(&exec.op), Some(sigil)));
+            // comments between an op and its sigil move after the exec
+            for trivia in exec.sigil.leading.iter() {
+                if trivia.kind == TriviaKind::Comment {
+                    nodes.push(Node::Comment(trivia.text.trim_end().to_string()));
+                }
+            }
+        }
+        push_trivia(&end.leading, &mut nodes);
+        nodes
+    }
+
+    fn operand(op: &CstOp) -> Operand {
+        match op {
+            CstOp::Atom(token) => Operand::Atom(token.text.clone()),
+            CstOp::List(list) => list_operand(list),
+            CstOp::Block(block) => {
+                let mut nodes = Vec::new();
+                if let Some(effect) = &block.effect {
+                    push_trivia(&effect.leading, &mut nodes);
+                    // the annotation is respaced, like the code around it
+                    let text = match StackEffect::parse(&effect.text) {
+                        Ok(parsed) => parsed.to_string(),
+                        Err(_) => effect.text.clone(),
+                    };
+                    nodes.push(Node::Effect(text));
+                }
+                nodes.extend(exec_nodes(&block.execs, &block.close));
+                Operand::Block(nodes)
+            }
+        }
+    }
+
+    fn list_operand(list: &CstList) -> Operand {
+        let mut nodes = Vec::new();
+        for item in list.items.iter() {
+            match item {
+                CstItem::Atom(token) => {
+                    push_trivia(&token.leading, &mut nodes);
+                    nodes.push(Node::Item(Operand::Atom(token.text.clone()), None));
+                }
+                CstItem::List(nested) => {
+                    push_trivia(&nested.open.leading, &mut nodes);
+                    nodes.push(Node::Item(list_operand(nested), None));
+                }
+                // commas are put back by the printer
+                CstItem::Comma(token) => push_trivia(&token.leading, &mut nodes),
+            }
+        }
+        push_trivia(&list.close.leading, &mut nodes);
+        Operand::List(nodes)
+    }
+
+    // keeps the comments and runs of newlines in trivia; whitespace is dropped
+    fn push_trivia(trivia: &[Trivia], nodes: &mut Vec<Node>) {
+        let mut newlines = 0;
+        for piece in trivia {
+            match piece.kind {
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::Comment => {
+                    if newlines > 0 {
+                        nodes.push(Node::Break(newlines));
+                        newlines = 0;
+                    }
+                    nodes.push(Node::Comment(piece.text.trim_end().to_string()));
+                }
+                TriviaKind::Whitespace => {}
+            }
+        }
+        if newlines > 0 {
+            nodes.push(Node::Break(newlines));
+        }
+    }
+
+    // the operand on one line, if it has no comments or line breaks inside
+    fn flat(operand: &Operand) -> Option<String> {
+        match operand {
+            Operand::Atom(lexeme) => Some(lexeme.clone()),
+            Operand::List(nodes) => Some(format!("[{}]", flat_items(nodes, ", ")?)),
+            Operand::Block(nodes) => Some(format!("{{{}}}", flat_items(nodes, " ")?)),
+        }
+    }
+
+    fn flat_items(nodes: &[Node], separator: &str) -> Option<String> {
+        let mut items = Vec::new();
+        for node in nodes {
+            match node {
+                Node::Item(operand, Some(sigil)) => {
+                    items.push(format!("{}{}", flat(operand)?, sigil))
+                }
+                Node::Item(operand, None) => items.push(flat(operand)?),
+                Node::Effect(text) => items.push(text.clone()),
+                Node::Comment(_) | Node::Break(_) => return None,
+            }
+        }
+        Some(items.join(separator))
+    }
+
+    struct Printer<'a> {
+        options: &'a FormatOptions,
+        lines: Vec<String>,
+        line: String, // the line being built, without its indentation
+        depth: usize,
+    }
+
+    impl Printer<'_> {
+        // prints a sequence of execs or list elements
+        // a blank line is kept between items, but not at the start or end of the sequence
+        fn print_nodes(&mut self, nodes: &[Node]) {
+            let mut started = false;
+            let mut blank = false;
+            for node in nodes {
+                match node {
+                    Node::Break(n) => {
+                        self.flush();
+                        blank = blank || (*n > 1 && started);
+                    }
+                    Node::Comment(text) => {
+                        if self.line.is_empty() && blank {
+                            self.lines.push(String::new());
+                        }
+                        self.push(text);
+                        self.flush();
+                        blank = false;
+                    }
+                    Node::Item(operand, suffix) => {
+                        if self.line.is_empty() && blank {
+                            self.lines.push(String::new());
+                        }
+                        self.print_item(operand, suffix.unwrap_or(','));
+                        blank = false;
+                    }
+                    Node::Effect(text) => {
+                        self.push(text);
+                        blank = false;
+                    }
+                }
+                started = started || !matches!(node, Node::Break(_));
+            }
+        }
+
+        fn print_item(&mut self, operand: &Operand, suffix: char) {
+            if let Some(text) = flat(operand) {
+                let text = format!("{}{}", text, suffix);
+                if !self.fits(&text) && !self.line.is_empty() {
+                    self.flush();
+                }
+                // an atom can't be broken, so a long one goes past the width
+                if self.fits(&text) || matches!(operand, Operand::Atom(_)) {
+                    self.push(&text);
+                    return;
+                }
+            }
+
+            let (open, close, nodes) = match operand {
+                Operand::List(nodes) => ('[', ']', nodes),
+                Operand::Block(nodes) => ('{', '}', nodes),
+                Operand::Atom(_) => unreachable!(),
+            };
+            self.push(&open.to_string());
+            // an annotation, then a comment, right after the opening bracket stay on its line
+            let nodes = match nodes.first() {
+                Some(Node::Effect(text)) => {
+                    self.line.push_str(text);
+                    &nodes[1..]
+                }
+                _ => &nodes[..],
+            };
+            let nodes = match nodes.first() {
+                Some(Node::Comment(text)) => {
+                    self.push(text);
+                    &nodes[1..]
+                }
+                _ => nodes,
+            };
+            self.flush();
+            self.depth += 1;
+            self.print_nodes(nodes);
+            self.flush();
+            self.depth -= 1;
+            self.line = format!("{}{}", close, suffix);
+        }
+
+        // whether text fits on the current line
+        fn fits(&self, text: &str) -> bool {
+            let mut width = self.depth * self.options.indent + text.chars().count();
+            if !self.line.is_empty() {
+                width += self.line.chars().count() + 1;
+            }
+            width <= self.options.max_width
+        }
+
+        fn push(&mut self, text: &str) {
+            if !self.line.is_empty() {
+                self.line.push(' ');
+            }
+            self.line.push_str(text);
+        }
+
+        fn flush(&mut self) {
+            if !self.line.is_empty() {
+                let indent = " ".repeat(self.depth * self.options.indent);
+                self.lines.push(format!("{}{}", indent, self.line));
+                self.line.clear();
+            }
+        }
+    }
+}
@@ -19,6 +19,7 @@ pub mod lex_token {
         ConstChar,
         ConstString,
         Instr,
+        Effect, // a stack effect annotation, `( a -- b )`
         // Others
         Error,
         End,
@@ -54,6 +55,39 @@ pub mod lex_token {
             )
         }
     }
+
+    // The kind of source text the lexer skips between tokens.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TriviaKind {
+        Whitespace, // spaces and tabs
+        Newline,    // a single \n or \r\n
+        Comment,    // from # up to, but not including, the end of the line
+    }
+
+    // A piece of skipped source text, spelled exactly as in the source.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Trivia {
+        pub kind: TriviaKind,
+        pub text: String,
+    }
+
+    // A token with the trivia before it.
+    // A token stream ends with an End token, which holds the trivia after the last token.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct TriviaToken {
+        pub leading: Vec<Trivia>,
+        pub token: Token,
+        pub text: String, // the token as spelled in the source
+    }
+
+    impl Display for TriviaToken {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+            for trivia in self.leading.iter() {
+                write!(f, "{}", trivia.text)?;
+            }
+            write!(f, "{}", self.text)
+        }
+    }
 }
 
 pub mod lex {
@@ -71,11 +105,12 @@ pub mod lex {
         InInstrOrBool,
         // InInstr,
         InComment,
+        InEffect,
     }
 
     // parses an entire code string into a vector of tokens
     // calls get_next_token() to get the next token
-    pub fn tokenize_code(input: &String) -> Vec<Token> {
+    pub fn tokenize_code(input: &str) -> Vec<Token> {
         let mut start: usize = 0;
         let mut line: usize = 0;
         let mut tokens = Vec::new();
@@ -83,21 +118,110 @@ pub mod lex {
         // remove \r
         let input = input.replace("\r", "");
 
-        while start < input.len() {
+        // start and end are char indices, so stop at the char count, not the byte length
+        let len = input.chars().count();
+        while start < len {
             let (token, s, l) = get_next_token(&input, start, line);
             tokens.push(token);
             start = s;
             line = l;
         }
-        if tokens.last().unwrap().token_type == TokenType::End {
+        if tokens.last().map(|token| token.token_type) == Some(TokenType::End) {
             tokens.pop();
         }
         tokens
     }
 
+    // parses an entire code string into tokens that keep the comments and whitespace before them
+    // writing the tokens out in order gives back the input exactly
+    pub fn tokenize_with_trivia(input: &str) -> Vec<TriviaToken> {
+        // the lexer works on the input without \r, so map its indices back to the input
+        let chars: Vec<char> = input.chars().collect();
+        let mut index_of: Vec<usize> = (0..chars.len()).filter(|&i| chars[i] != '\r').collect();
+        index_of.push(chars.len());
+
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+        for token in tokenize_code(input) {
+            let last = index_of.len() - 1;
+            let start = index_of[token.start.min(last)];
+            // a \r just after the token is trivia, so map the token's last char instead of its end
+            let end = if token.end > token.start {
+                index_of[(token.end - 1).min(last)] + 1
+            } else {
+                start
+            };
+            // unlike tokenize_code, start and end count the \r chars in the input
+            tokens.push(TriviaToken {
+                leading: split_trivia(&chars[pos..start]),
+                text: chars[start..end].iter().collect(),
+                token: Token {
+                    start,
+                    end,
+                    ..token
+                },
+            });
+            pos = end;
+        }
+        let line = tokens.last().map_or(0, |t: &TriviaToken| {
+            t.token.line + t.text.matches('\n').count()
+        });
+        let leading = split_trivia(&chars[pos..]);
+        let line = line
+            + leading
+                .iter()
+                .filter(|t| t.kind == TriviaKind::Newline)
+                .count();
+        tokens.push(TriviaToken {
+            leading,
+            token: Token {
+                token_type: TokenType::End,
+                lexeme: String::new(),
+                error_msg: String::new(),
+                start: chars.len(),
+                end: chars.len(),
+                line,
+            },
+            text: String::new(),
+        });
+        tokens
+    }
+
+    // splits the text between two tokens into whitespace, newlines and comments
+    fn split_trivia(text: &[char]) -> Vec<Trivia> {
+        let mut trivia: Vec<Trivia> = Vec::new();
+        let mut i = 0;
+        while i < text.len() {
+            let (kind, len) = match text[i] {
+                '#' => {
+                    let len = text[i..]
+                        .iter()
+                        .position(|&c| c == '\n' || c == '\r')
+                        .unwrap_or(text.len() - i);
+                    (TriviaKind::Comment, len)
+                }
+                '\n' => (TriviaKind::Newline, 1),
+                '\r' if text.get(i + 1) == Some(&'\n') => (TriviaKind::Newline, 2),
+                _ => {
+                    let len = text[i..]
+                        .iter()
+                        .position(|&c| c == '\n' || c == '\r' || c == '#' || !c.is_whitespace())
+                        .unwrap_or(text.len() - i);
+                    (TriviaKind::Whitespace, len.max(1))
+                }
+            };
+            trivia.push(Trivia {
+                kind,
+                text: text[i..i + len].iter().collect(),
+            });
+            i += len;
+        }
+        trivia
+    }
+
     // Gets the next token from an input string.
     // Returns the next token, as well as the index of the last character read, and the current line number.
-    pub fn get_next_token(input: &String, start: usize, line: usize) -> (Token, usize, usize) {
+    pub fn get_next_token(input: &str, start: usize, line: usize) -> (Token, usize, usize) {
         let mut state = LexerState::Start;
         let mut lexeme = String::new();
         let mut error_msg = String::new();
@@ -195,7 +319,14 @@ pub mod lex {
                         }
                         Some('#') => {
                             state = LexerState::InComment;
+                            s += 1;
+                            i += 1;
+                        }
+                        Some('(') => {
+                            state = LexerState::InEffect;
                             i += 1;
+                            lexeme.push('(');
+                            token_type = TokenType::Effect;
                         }
                         None => {
                             token_type = TokenType::End;
@@ -249,7 +380,7 @@ pub mod lex {
                         break;
                     }
                     _ => {
-                        if lexeme.chars().last().unwrap() == '.' {
+                        if lexeme.ends_with('.') {
                             error_msg =
                                 "Invalid float literal: missing decimal portion".to_string();
                             token_type = TokenType::Error;
@@ -268,7 +399,7 @@ pub mod lex {
                     Some('\'') => {
                         i += 1;
                         lexeme.push(input.chars().nth(i - 1).unwrap());
-                        if lexeme.len() > 3 && lexeme.chars().nth(1).unwrap() != '\\' {
+                        if lexeme.chars().count() > 3 && lexeme.chars().nth(1).unwrap() != '\\' {
                             token_type = TokenType::Error;
                             error_msg =
                                 "Invalid char literal: Character constant too long".to_string();
@@ -315,8 +446,9 @@ pub mod lex {
                     }
                 },
                 LexerState::InInstrOrBool => match input.chars().nth(i) {
-                    Some(' ') | Some('\t') | Some(',') | Some(']') | Some('}') | Some('~')
-                    | Some('!') => {
+                    // the newline is left for the next token, so it is counted as a line
+                    Some(' ') | Some('\t') | Some('\n') | Some(',') | Some(']') | Some('}')
+                    | Some('~') | Some('!') => {
                         if lexeme == "true" || lexeme == "false" {
                             token_type = TokenType::ConstBool;
                             break;
@@ -324,15 +456,6 @@ pub mod lex {
                         token_type = TokenType::Instr;
                         break;
                     }
-                    Some('\n') => {
-                        i += 1;
-                        if lexeme == "true" || lexeme == "false" {
-                            token_type = TokenType::ConstBool;
-                        } else {
-                            token_type = TokenType::Instr;
-                        }
-                        break;
-                    }
                     Some(_) => {
                         i += 1;
                         lexeme.push(input.chars().nth(i - 1).unwrap());
@@ -346,6 +469,23 @@ pub mod lex {
                         break;
                     }
                 },
+                // everything up to the closing parenthesis, which may be on another line
+                LexerState::InEffect => match input.chars().nth(i) {
+                    Some(')') => {
+                        i += 1;
+                        lexeme.push(')');
+                        break;
+                    }
+                    Some(c) => {
+                        i += 1;
+                        lexeme.push(c);
+                    }
+                    None => {
+                        token_type = TokenType::Error;
+                        error_msg = "Invalid stack effect: Unterminated stack effect".to_string();
+                        break;
+                    }
+                },
                 LexerState::InComment => match input.chars().nth(i) {
                     Some('\n') => {
                         i += 1;
@@ -366,17 +506,19 @@ pub mod lex {
             }
         }
 
+        // newlines inside a string literal count towards the next token's line
+        let next_line = line + lexeme.matches('\n').count();
         (
             Token {
-                token_type: token_type,
-                lexeme: lexeme,
-                error_msg: error_msg,
+                token_type,
+                lexeme,
+                error_msg,
                 start: s,
                 end: i,
-                line: line,
+                line,
             },
             i,
-            line,
+            next_line,
         )
     }
 }
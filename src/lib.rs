@@ -0,0 +1,14 @@
+// the library half of the crate, for embedding the language in other programs
+// main.rs is the command-line interpreter built on top of it
+
+pub mod checker;
+pub mod debugger;
+pub mod evaluator;
+pub mod formatter;
+pub mod lexer;
+pub mod linter;
+pub mod parser;
+pub mod profiler;
+
+// tests
+mod tests;
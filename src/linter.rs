@@ -0,0 +1,457 @@
+pub mod lint {
+    // a pass over the AST that looks for code that runs, but probably doesn't do what was meant
+    // each lint has the span of the code it is about, and most have a fix that can be applied
+    // rules can be turned off with a config file
+
+    use crate::checker::check::check_code;
+    use crate::evaluator::eval_docs::instr_doc;
+    use crate::lexer::lex::tokenize_with_trivia;
+    use crate::parser::par_ast::{Code, Exec, Literal, Op};
+    use crate::parser::par_cst::{parse_cst, CstCode, CstExec, CstOp};
+    use std::fmt::{Display, Formatter, Result as FmtResult};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Rule {
+        WrongSide,     // a literal pushed on one end, then an instruction that pops the other
+        UnusedLiteral, // a literal that is dropped, or left on the deque, without being used
+        RangeStep,     // range with a step that is zero or negative
+        Unreachable,   // code after loop or exit
+    }
+
+    impl Rule {
+        pub const ALL: [Rule; 4] = [
+            Rule::WrongSide,
+            Rule::UnusedLiteral,
+            Rule::RangeStep,
+            Rule::Unreachable,
+        ];
+
+        pub fn name(self) -> &'static str {
+            match self {
+                Rule::WrongSide => "wrong-side",
+                Rule::UnusedLiteral => "unused-literal",
+                Rule::RangeStep => "range-step",
+                Rule::Unreachable => "unreachable",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Rule> {
+            Rule::ALL.iter().copied().find(|rule| rule.name() == name)
+        }
+    }
+
+    impl Display for Rule {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{}", self.name())
+        }
+    }
+
+    // a range of chars in the source, like the start and end of a token
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Span {
+        pub start: usize,
+        pub end: usize,
+    }
+
+    // replaces the chars in a span with new text
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Edit {
+        pub span: Span,
+        pub text: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Fix {
+        pub message: String,
+        pub edits: Vec<Edit>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Lint {
+        pub rule: Rule,
+        pub span: Span,
+        pub line: usize,   // of the start of the span, counting from 1
+        pub column: usize, // counting from 1
+        pub message: String,
+        pub fix: Option<Fix>,
+    }
+
+    // which rules are on; all of them are by default
+    #[derive(Debug, Clone, PartialEq, Default)]
+    pub struct LintConfig {
+        pub disabled: Vec<Rule>,
+    }
+
+    impl LintConfig {
+        // reads a config file, which has a line for each rule to change:
+        //   # comments start with a hash mark
+        //   unused-literal = off
+        //   range-step = on
+        pub fn parse(text: &str) -> Result<LintConfig, String> {
+            let mut config = LintConfig::default();
+            for (i, line) in text.lines().enumerate() {
+                let line = match line.find('#') {
+                    Some(comment) => &line[..comment],
+                    None => line,
+                }
+                .trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let (name, value) = match line.split_once('=') {
+                    Some((name, value)) => (name.trim(), value.trim()),
+                    None => {
+                        return Err(format!(
+                            "line {}: expected `rule = on` or `rule = off`",
+                            i + 1
+                        ))
+                    }
+                };
+                let rule = Rule::from_name(name)
+                    .ok_or_else(|| format!("line {}: unknown rule: {}", i + 1, name))?;
+                config.disabled.retain(|disabled| *disabled != rule);
+                match value {
+                    "on" => {}
+                    "off" => config.disabled.push(rule),
+                    _ => return Err(format!("line {}: expected on or off, got {}", i + 1, value)),
+                }
+            }
+            Ok(config)
+        }
+
+        pub fn enabled(&self, rule: Rule) -> bool {
+            !self.disabled.contains(&rule)
+        }
+    }
+
+    // lints code, returning the lints of the enabled rules in the order they appear,
+    // or the first error if the code can't be parsed
+    pub fn lint_code(source: &str, config: &LintConfig) -> Result<Vec<Lint>, String> {
+        let cst = parse_cst(tokenize_with_trivia(source))?;
+        let ast = cst.to_ast()?;
+        let mut linter = Linter {
+            cst: &cst,
+            chars: source.chars().collect(),
+            lines: line_starts(source),
+            lints: Vec::new(),
+        };
+        linter.sequence(&ast, &[]);
+        if config.enabled(Rule::UnusedLiteral) {
+            linter.unused_literals(&ast);
+        }
+
+        let mut lints = linter.lints;
+        lints.retain(|lint| config.enabled(lint.rule));
+        lints.sort_by_key(|lint| (lint.span.start, lint.span.end));
+        Ok(lints)
+    }
+
+    // applies the fixes of the lints, skipping any that overlap a fix already applied
+    // returns the fixed source and the lints that weren't fixed
+    pub fn apply_fixes(source: &str, lints: Vec<Lint>) -> (String, Vec<Lint>) {
+        let mut edits: Vec<&Edit> = Vec::new();
+        let mut unfixed = Vec::new();
+        for lint in lints.iter() {
+            let fix = match &lint.fix {
+                Some(fix) => fix,
+                None => {
+                    unfixed.push(lint.clone());
+                    continue;
+                }
+            };
+            let overlaps = fix.edits.iter().any(|edit| {
+                edits.iter().any(|other| {
+                    edit.span.start < other.span.end && other.span.start < edit.span.end
+                })
+            });
+            if overlaps {
+                unfixed.push(lint.clone());
+            } else {
+                edits.extend(fix.edits.iter());
+            }
+        }
+        edits.sort_by_key(|edit| edit.span.start);
+
+        let chars: Vec<char> = source.chars().collect();
+        let mut fixed = String::new();
+        let mut at = 0;
+        for edit in edits {
+            fixed.extend(&chars[at..edit.span.start]);
+            fixed.push_str(&edit.text);
+            at = edit.span.end;
+        }
+        fixed.extend(&chars[at..]);
+        (fixed, unfixed)
+    }
+
+    // the char offset that each line starts at
+    fn line_starts(source: &str) -> Vec<usize> {
+        let mut starts = vec![0];
+        for (i, c) in source.chars().enumerate() {
+            if c == '\n' {
+                starts.push(i + 1);
+            }
+        }
+        starts
+    }
+
+    // the side an exec works on, as its sigil
+    fn sigil(exec: &Exec) -> char {
+        match exec {
+            Exec::Left(_) => '!',
+            Exec::Right(_) => '~',
+        }
+    }
+
+    fn op(exec: &Exec) -> &Op {
+        match exec {
+            Exec::Left(op) | Exec::Right(op) => op,
+        }
+    }
+
+    fn end_name(sigil: char) -> &'static str {
+        if sigil == '!' {
+            "front"
+        } else {
+            "back"
+        }
+    }
+
+    // whether an instruction pops anything, going by the effect in its docs
+    fn pops(instr: &str) -> bool {
+        match instr_doc(instr) {
+            Some(doc) => doc
+                .effect
+                .trim_start_matches('(')
+                .split_whitespace()
+                .take_while(|word| *word != "--")
+                .any(|word| word != "..."),
+            None => false,
+        }
+    }
+
+    struct Linter<'c> {
+        cst: &'c CstCode,
+        chars: Vec<char>,
+        lines: Vec<usize>,
+        lints: Vec<Lint>,
+    }
+
+    impl Linter<'_> {
+        fn exec(&self, path: &[usize]) -> &CstExec {
+            self.cst
+                .exec_at(path)
+                .expect("the CST has the same execs as the AST")
+        }
+
+        // the span of an exec, from its op to its sigil
+        fn span(&self, path: &[usize]) -> Span {
+            let exec = self.exec(path);
+            Span {
+                start: exec.op.first_token().token.start,
+                end: exec.sigil.token.end,
+            }
+        }
+
+        // how an exec is written in a message; blocks and lists are shortened
+        fn text(&self, path: &[usize]) -> String {
+            let exec = self.exec(path);
+            match &exec.op {
+                CstOp::Atom(token) => format!("`{}{}`", token.text, exec.sigil.text),
+                CstOp::List(_) => format!("`[...]{}`", exec.sigil.text),
+                CstOp::Block(_) => format!("`{{...}}{}`", exec.sigil.text),
+            }
+        }
+
+        // deletes a span, with the spaces after it,
+        // or the spaces before it if it ends its line or block
+        fn removal(&self, span: Span) -> Edit {
+            let blank = |i: usize| matches!(self.chars.get(i), Some(' ') | Some('\t'));
+            let (mut start, mut end) = (span.start, span.end);
+            while blank(end) {
+                end += 1;
+            }
+            if matches!(
+                self.chars.get(end),
+                None | Some('\n') | Some('\r') | Some('}')
+            ) {
+                end = span.end;
+                while start > 0 && blank(start - 1) {
+                    start -= 1;
+                }
+            }
+            Edit {
+                span: Span { start, end },
+                text: String::new(),
+            }
+        }
+
+        fn lint(&mut self, rule: Rule, span: Span, message: String, fix: Option<Fix>) {
+            let line = match self.lines.binary_search(&span.start) {
+                Ok(line) => line,
+                Err(line) => line - 1,
+            };
+            self.lints.push(Lint {
+                rule,
+                span,
+                line: line + 1,
+                column: span.start - self.lines[line] + 1,
+                message,
+                fix,
+            });
+        }
+
+        // lints a sequence of execs, then the blocks in it
+        fn sequence(&mut self, code: &Code, path: &[usize]) {
+            let paths: Vec<Vec<usize>> = (0..code.len()).map(|i| [path, &[i]].concat()).collect();
+            for i in 0..code.len() {
+                if i + 1 < code.len() {
+                    self.wrong_side(&code[i], &code[i + 1], &paths[i], &paths[i + 1]);
+                }
+                if let Op::Instruction(instr) = op(&code[i]) {
+                    if instr == "range" && i >= 3 {
+                        self.range_step(&code[i - 3..=i], &paths[i - 3..=i]);
+                    }
+                    if (instr == "loop" || instr == "exit") && i + 1 < code.len() {
+                        self.unreachable(&paths[i], &paths[i + 1..]);
+                        break;
+                    }
+                }
+            }
+            for (exec, path) in code.iter().zip(paths.iter()) {
+                if let Op::Literal(Literal::Block(block)) = op(exec) {
+                    self.sequence(&block.execs, path);
+                }
+            }
+        }
+
+        // a literal, then an instruction that pops from the other end, which doesn't get it
+        // rot and over move values between the ends on purpose
+        fn wrong_side(&mut self, first: &Exec, next: &Exec, path: &[usize], next_path: &[usize]) {
+            let instr = match (op(first), op(next)) {
+                (Op::Literal(_), Op::Instruction(instr)) => instr,
+                _ => return,
+            };
+            let (pushed, popped) = (sigil(first), sigil(next));
+            if pushed == popped || !pops(instr) || instr == "rot" || instr == "over" {
+                return;
+            }
+            let sigil_token = &self.exec(next_path).sigil.token;
+            let fix = Fix {
+                message: format!(
+                    "pop from the {} with `{}{}`",
+                    end_name(pushed),
+                    instr,
+                    pushed
+                ),
+                edits: vec![Edit {
+                    span: Span {
+                        start: sigil_token.start,
+                        end: sigil_token.end,
+                    },
+                    text: pushed.to_string(),
+                }],
+            };
+            let message = format!(
+                "{} is pushed onto the {}, but `{}{}` pops from the {}",
+                self.text(path),
+                end_name(pushed),
+                instr,
+                popped,
+                end_name(popped)
+            );
+            self.lint(Rule::WrongSide, self.span(next_path), message, Some(fix));
+        }
+
+        // range pops its lower bound, upper bound, step and block, so the step is pushed
+        // three values before the range
+        fn range_step(&mut self, execs: &[Exec], paths: &[Vec<usize>]) {
+            let place = sigil(&execs[3]);
+            let pushed_here = execs[..3]
+                .iter()
+                .all(|exec| sigil(exec) == place && matches!(op(exec), Op::Literal(_)));
+            let step = match op(&execs[0]) {
+                Op::Literal(Literal::Int(step)) if pushed_here && *step <= 0 => *step,
+                _ => return,
+            };
+            let message = if step == 0 {
+                format!("`range{}` has a step of 0, which fails when it runs", place)
+            } else {
+                format!(
+                    "`range{}` has a step of {}, but range only counts up, so it fails when it runs",
+                    place, step
+                )
+            };
+            let fixed = if step == 0 { 1 } else { -step };
+            let token = &self.exec(&paths[0]).op.first_token().token;
+            let fix = Fix {
+                message: format!("use a step of {}", fixed),
+                edits: vec![Edit {
+                    span: Span {
+                        start: token.start,
+                        end: token.end,
+                    },
+                    text: fixed.to_string(),
+                }],
+            };
+            self.lint(Rule::RangeStep, self.span(&paths[0]), message, Some(fix));
+        }
+
+        // everything after a loop or exit, which never runs
+        fn unreachable(&mut self, path: &[usize], rest: &[Vec<usize>]) {
+            let span = Span {
+                start: self.span(&rest[0]).start,
+                end: self.span(&rest[rest.len() - 1]).end,
+            };
+            let message = format!("code after {} never runs", self.text(path));
+            let fix = Fix {
+                message: "remove it".to_string(),
+                edits: vec![self.removal(span)],
+            };
+            self.lint(Rule::Unreachable, span, message, Some(fix));
+        }
+
+        // literals the checker saw dropped, or left on the deque at the end, without being used
+        fn unused_literals(&mut self, ast: &Code) {
+            for unused in check_code(ast).unused {
+                let span = self.span(&unused.path);
+                let literal = self.text(&unused.path);
+                let dropper = match &unused.dropped_by {
+                    Some(dropper) => dropper,
+                    None => {
+                        let message = format!("{} is left on the deque, and never used", literal);
+                        let fix = Fix {
+                            message: "remove it".to_string(),
+                            edits: vec![self.removal(span)],
+                        };
+                        self.lint(Rule::UnusedLiteral, span, message, Some(fix));
+                        continue;
+                    }
+                };
+                let message = format!(
+                    "{} is pushed, then dropped by {}",
+                    literal,
+                    self.text(dropper)
+                );
+                let is_pop =
+                    matches!(&self.exec(dropper).op, CstOp::Atom(token) if token.text == "pop");
+                let fix = if !is_pop {
+                    // clear drops whatever is on the deque, so only the literal goes
+                    Some(Fix {
+                        message: "remove it".to_string(),
+                        edits: vec![self.removal(span)],
+                    })
+                } else if dropper[..dropper.len() - 1] == unused.path[..unused.path.len() - 1] {
+                    Some(Fix {
+                        message: "remove it and the pop".to_string(),
+                        edits: vec![self.removal(span), self.removal(self.span(dropper))],
+                    })
+                } else {
+                    // a pop in other code may drop other values when that code runs again
+                    None
+                };
+                self.lint(Rule::UnusedLiteral, span, message, fix);
+            }
+        }
+    }
+}
@@ -1,151 +1,504 @@
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-mod evaluator;
-mod lexer;
-mod parser;
-use evaluator::eval_value::*;
+use rusty_deque::checker::check::check_code;
+use rusty_deque::debugger::debug::Debugger;
+use rusty_deque::evaluator::eval::{Capabilities, Interpreter, Limits};
+use rusty_deque::evaluator::eval_error::*;
+use rusty_deque::evaluator::eval_io::SharedBuffer;
+use rusty_deque::evaluator::eval_trace::JsonLines;
+use rusty_deque::evaluator::eval_value::*;
+use rusty_deque::formatter::fmt::{format_code, FormatOptions};
+use rusty_deque::lexer;
+use rusty_deque::lexer::lex::tokenize_with_trivia;
+use rusty_deque::lexer::lex_token::*;
+use rusty_deque::linter::lint::{apply_fixes, lint_code, LintConfig};
+use rusty_deque::parser::par_ast::*;
+use rusty_deque::parser::par_cst::{parse_cst, parse_cst_recovering, CstCode};
+use rusty_deque::profiler::profile::Profile;
 
-// tests
-mod tests;
+// exit statuses, one for each class of failure
+// a script that runs exit chooses its own status instead
+const EXIT_OK: i32 = 0;
+const EXIT_RUNTIME: i32 = 1; // the code failed while running, or a test failed
+const EXIT_USAGE: i32 = 2; // the command line was wrong
+const EXIT_SYNTAX: i32 = 3; // the code could not be lexed or parsed
+const EXIT_IO: i32 = 4; // a script could not be read
+const EXIT_LIMIT: i32 = 5; // the code ran past one of the limits
+const EXIT_PERMISSION: i32 = 6; // the code used a capability it wasn't granted
 
 fn main() {
-    // possible command-line args
-    // all of these are optional
-    // no args: run the REPL
-    // -f <filename>: read code from this file
-    // -c <expr>: evaluate this expression
-    // -p: print the stack after code exectution
-    // -t: print the tokens after lexing
-    // -a: print the AST after parsing
-
-    // cli format:
-    // cargo run -- [-p] [-t] [-a] [-f <filename>] [-c <expr>]
-
-    let args = std::env::args();
-    let mut args_iter = args.skip(1); // skip program name
-    let mut filename = String::new();
-    let mut expr = String::new();
-    let mut print_stack = false;
-    let mut print_tokens = false;
-    let mut print_ast = false;
-
-    // check for args
+    // rusty_deque [<command>] [options] [<script> [<args>]]
+    // with no command, a script is run, and with no arguments at all, the REPL starts
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args.first().map(String::as_str) {
+        Some("run") | Some("repl") | Some("debug") | Some("check") | Some("lint") | Some("fmt")
+        | Some("test") | Some("dump-tokens") | Some("dump-ast") => args.remove(0),
+        Some("-h") | Some("--help") | Some("help") => {
+            print_usage();
+            std::process::exit(EXIT_OK);
+        }
+        Some("-V") | Some("--version") => {
+            println!("rusty_deque {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(EXIT_OK);
+        }
+        None => "repl".to_string(),
+        Some(_) => "run".to_string(),
+    };
+
+    // only run and debug take script arguments, so only they stop at the first script
+    let invocation = match parse_args(args, command == "run" || command == "debug") {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("run `rusty_deque --help` for usage");
+            std::process::exit(EXIT_USAGE);
+        }
+    };
+    let status = match command.as_str() {
+        "run" => run_command(invocation),
+        "repl" => repl_command(invocation),
+        "debug" => debug_command(invocation),
+        "check" => check_command(invocation),
+        "lint" => lint_command(invocation),
+        "fmt" => fmt_command(invocation),
+        "test" => test_command(invocation),
+        "dump-tokens" => dump_command(invocation, false),
+        _ => dump_command(invocation, true),
+    };
+    std::process::exit(status);
+}
+
+pub fn print_usage() {
+    println!("usage: rusty_deque [<command>] [options] [<script> [<args>]]");
+    println!();
+    println!("commands:");
+    println!("  run          run a script, or code given with -c (the default)");
+    println!("  repl         start the REPL (the default with no arguments)");
+    println!("  debug        step through a script, with breakpoints and watches");
+    println!(
+        "  check        lex, parse and check scripts without running them, reporting every error"
+    );
+    println!("  lint         warn about code that probably doesn't do what was meant");
+    println!("  fmt          print scripts in a canonical format");
+    println!("  test         run .deque scripts, comparing their output to .expected files");
+    println!("  dump-tokens  print the tokens of a script");
+    println!("  dump-ast     print the syntax tree of a script");
+    println!();
+    println!("sources:");
+    println!("  <script>            a script file, or - to read it from stdin");
+    println!("  -f <script>         the same as <script>");
+    println!("  -c <expr>           code given on the command line");
+    println!("  -- <args>           pass the remaining arguments to the code");
+    println!();
+    println!("options:");
+    println!("  -p                  print the deque after running");
+    println!("  -t                  print the tokens after lexing");
+    println!("  -a                  print the AST after parsing");
+    println!("  --max-steps <n>     stop after running n instructions");
+    println!("  --max-deque <n>     stop if the deque holds more than n values");
+    println!(
+        "  --max-size <n>      stop if the deque holds more than n values, counting nested values"
+    );
+    println!("  --max-depth <n>     stop if blocks nest deeper than n");
+    println!("  --timeout <ms>      stop after running for ms milliseconds");
+    println!("  --deny-io           stop if the code tries to use stdin, stdout or stderr");
+    println!("  --allow-files       let the code read and write files");
+    println!("  --allow-env         let the code read environment variables");
+    println!("  --check-effects     fail if a block doesn't match its stack effect annotation");
+    println!(
+        "  --trace <file>      run: write each exec and the deque around it to file as JSON lines,"
+    );
+    println!("                      or to stderr with -");
+    println!(
+        "  --profile           run: print the time spent in each instruction and block to stderr"
+    );
+    println!("  --profile-folded <file>");
+    println!("                      run: write the time spent in each stack of blocks to file,");
+    println!("                      in the folded format of flamegraph tools");
+    println!("  --effects           check: print the inferred stack effect of each block");
+    println!("  --width <n>         fmt: wrap lines longer than n characters (default 80)");
+    println!(
+        "  --check             fmt: list scripts that aren't formatted, instead of printing them"
+    );
+    println!("  --write             fmt: rewrite scripts in place, instead of printing them;");
+    println!("                      lint: apply the suggested fixes");
+    println!(
+        "  --config <file>     lint: turn rules on and off with a config file (default .dequelint)"
+    );
+    println!("  -h, --help          print this message");
+    println!("  -V, --version       print the version");
+    println!();
+    println!("exit status:");
+    println!("  0 success, 1 runtime error, failed test or lint, 2 usage error, 3 syntax error,");
+    println!("  4 unreadable script, 5 limit exceeded, 6 permission denied,");
+    println!("  or the status given to the exit instruction");
+}
+
+// where code comes from
+enum Source {
+    File(String),
+    Expr(String),
+}
+
+impl Source {
+    fn name(&self) -> &str {
+        match self {
+            Source::File(filename) => filename,
+            Source::Expr(_) => "<expr>",
+        }
+    }
+
+    // reads the code, skipping a #! line at the start of a file
+    fn read(&self) -> std::io::Result<String> {
+        match self {
+            Source::File(filename) => {
+                let contents = read_script(filename)?;
+                Ok(strip_shebang(&contents).to_string())
+            }
+            Source::Expr(expr) => Ok(expr.clone()),
+        }
+    }
+}
+
+// the parsed command line after the command
+struct Invocation {
+    sources: Vec<Source>,
+    script_args: Vec<String>,
+    print_stack: bool,
+    print_tokens: bool,
+    print_ast: bool,
+    limits: Limits,
+    capabilities: Capabilities,
+    format_options: FormatOptions,
+    format_check: bool,
+    format_write: bool,
+    print_effects: bool,
+    check_effects: bool,
+    lint_config: Option<String>,
+    trace: Option<String>,
+    profile: bool,
+    profile_folded: Option<String>,
+}
+
+impl Invocation {
+    fn interpreter(&self) -> Interpreter {
+        Interpreter::new()
+            .with_limits(self.limits)
+            .with_capabilities(self.capabilities)
+            .with_args(self.script_args.clone())
+            .with_effect_checks(self.check_effects)
+    }
+
+    // the one source that run and the dump commands work on
+    fn single_source(&self) -> Result<&Source, String> {
+        match self.sources.as_slice() {
+            [source] => Ok(source),
+            [] => Err("no script or expression given".to_string()),
+            _ => Err("only one script or expression can be given".to_string()),
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>, takes_script_args: bool) -> Result<Invocation, String> {
+    let mut invocation = Invocation {
+        sources: Vec::new(),
+        script_args: Vec::new(),
+        print_stack: false,
+        print_tokens: false,
+        print_ast: false,
+        limits: Limits::default(),
+        capabilities: Capabilities::default(),
+        format_options: FormatOptions::default(),
+        format_check: false,
+        format_write: false,
+        print_effects: false,
+        check_effects: false,
+        lint_config: None,
+        trace: None,
+        profile: false,
+        profile_folded: None,
+    };
+    let mut args_iter = args.into_iter();
     while let Some(arg) = args_iter.next() {
         match arg.as_str() {
             "-f" => {
-                if let Some(arg) = args_iter.next() {
-                    filename = arg;
-                } else {
-                    println!("-f requires an argument: filename");
-                    print_usage();
-                    std::process::exit(1);
-                }
+                let filename = args_iter
+                    .next()
+                    .ok_or("-f requires an argument: filename")?;
+                invocation.sources.push(Source::File(filename));
             }
             "-c" => {
-                if let Some(arg) = args_iter.next() {
-                    expr = arg;
-                } else {
-                    println!("-c requires an argument: code");
-                    print_usage();
-                    std::process::exit(1);
-                }
+                let expr = args_iter.next().ok_or("-c requires an argument: code")?;
+                invocation.sources.push(Source::Expr(expr));
+            }
+            "-p" => invocation.print_stack = true,
+            "-t" => invocation.print_tokens = true,
+            "-a" => invocation.print_ast = true,
+            "--max-steps" => {
+                invocation.limits.max_steps = Some(parse_limit(&arg, args_iter.next())?);
+            }
+            "--max-deque" => {
+                invocation.limits.max_deque_len = Some(parse_limit(&arg, args_iter.next())?);
+            }
+            "--max-size" => {
+                invocation.limits.max_total_size = Some(parse_limit(&arg, args_iter.next())?);
+            }
+            "--max-depth" => {
+                invocation.limits.max_depth = Some(parse_limit(&arg, args_iter.next())?);
+            }
+            "--timeout" => {
+                let ms = parse_limit(&arg, args_iter.next())?;
+                invocation.limits.timeout = Some(Duration::from_millis(ms));
+            }
+            "--deny-io" => {
+                invocation.capabilities.stdin = false;
+                invocation.capabilities.stdout = false;
+                invocation.capabilities.stderr = false;
             }
-            "-p" => {
-                print_stack = true;
+            "--allow-files" => invocation.capabilities.files = true,
+            "--allow-env" => invocation.capabilities.env = true,
+            "--width" => {
+                invocation.format_options.max_width = parse_limit(&arg, args_iter.next())?;
             }
-            "-t" => {
-                print_tokens = true;
+            "--check" => invocation.format_check = true,
+            "--write" => invocation.format_write = true,
+            "--effects" => invocation.print_effects = true,
+            "--check-effects" => invocation.check_effects = true,
+            "--config" => {
+                let filename = args_iter
+                    .next()
+                    .ok_or("--config requires an argument: filename")?;
+                invocation.lint_config = Some(filename);
             }
-            "-a" => {
-                print_ast = true;
+            "--trace" => {
+                let filename = args_iter
+                    .next()
+                    .ok_or("--trace requires an argument: filename")?;
+                invocation.trace = Some(filename);
+            }
+            "--profile" => invocation.profile = true,
+            "--profile-folded" => {
+                let filename = args_iter
+                    .next()
+                    .ok_or("--profile-folded requires an argument: filename")?;
+                invocation.profile_folded = Some(filename);
+            }
+            "--" if takes_script_args => {
+                invocation.script_args = args_iter.by_ref().collect();
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unrecognized argument: {}", arg));
             }
             _ => {
-                println!("unrecognized argument: {}", arg);
-                print_usage();
-                std::process::exit(1);
-            }
-        }
-    }
-    // if no args, run the repl
-    if filename.is_empty() && expr.is_empty() {
-        repl(print_tokens, print_ast, print_stack);
-        std::process::exit(0);
-    } else if filename.is_empty() {
-        // if no filename, but expr, run expr
-        if !expr.is_empty() {
-            let result = run_code(Option::None, expr, print_tokens, print_ast, print_stack);
-            match result {
-                Ok(_) => std::process::exit(0),
-                Err(e) => {
-                    println!("{}", e);
-                    std::process::exit(1);
+                invocation.sources.push(Source::File(arg));
+                // everything after the script belongs to the script
+                if takes_script_args {
+                    invocation.script_args = args_iter.by_ref().collect();
                 }
             }
-        } else {
-            println!("no expression specified");
-            print_usage();
-            std::process::exit(1);
         }
+    }
+    Ok(invocation)
+}
+
+// parses the argument of a limit flag
+fn parse_limit<T: std::str::FromStr>(flag: &str, arg: Option<String>) -> Result<T, String> {
+    match arg.as_deref().map(str::parse::<T>) {
+        Some(Ok(n)) => Ok(n),
+        _ => Err(format!(
+            "{} requires an argument: a non-negative integer",
+            flag
+        )),
+    }
+}
+
+// reads a script from a file, or from stdin if the filename is -
+fn read_script(filename: &str) -> std::io::Result<String> {
+    if filename == "-" {
+        let mut contents = String::new();
+        std::io::stdin().read_to_string(&mut contents)?;
+        Ok(contents)
     } else {
-        // if filename, run file
-        // read from file
-        let contents = std::fs::read_to_string(filename).expect("file not found");
-        let contents = contents.trim().to_string();
-        let result = run_code(Option::None, contents, print_tokens, print_ast, print_stack);
-        match result {
-            Ok(_) => std::process::exit(0),
-            Err(e) => {
-                println!("{}", e);
-                std::process::exit(1);
-            }
+        std::fs::read_to_string(filename)
+    }
+}
+
+// blanks out a #! first line, keeping the newline so line numbers don't change
+fn strip_shebang(contents: &str) -> &str {
+    if contents.starts_with("#!") {
+        match contents.find('\n') {
+            Some(end) => &contents[end..],
+            None => "",
         }
+    } else {
+        contents
     }
 }
 
-pub fn print_usage() {
-    println!("usage: cargo run -- [-p] [-t] [-a] [-f <filename>] [-c <expr>]");
-    println!("-p: print the stack after code execution");
-    println!("-t: print the tokens after lexing");
-    println!("-a: print the AST after parsing");
-    println!("-f <filename>: read code from this file");
-    println!("-c <expr>: evaluate this expression");
-    println!("(no args): run the REPL");
-}
-
-pub fn run_code(
-    deque: Option<VecDeque<Value>>,
-    code: String,
-    print_tokens: bool,
-    print_ast: bool,
-    print_stack: bool,
-) -> Result<VecDeque<Value>, String> {
-    // lex
-    let tokens = lexer::lex::tokenize_code(&code);
-    if print_tokens {
-        println!("{:?}", &tokens);
-    }
-    // parse
-    let ast = parser::par::parse_tokens(&mut tokens.into_iter());
-    match ast {
-        Ok(ast) => {
-            if print_ast {
-                println!("{:#?}", ast);
-            }
-            // run
-            let deque = evaluator::eval::run_ast(deque, ast)?;
-            if print_stack {
-                print_deque(&deque);
-            }
-            Ok(deque)
+// reads a source, reporting a failure on stderr
+fn read_source(source: &Source) -> Result<String, i32> {
+    source.read().map_err(|e| {
+        eprintln!("error: {}: {}", source.name(), e);
+        EXIT_IO
+    })
+}
+
+// the lexer errors in the tokens, one message per error token
+fn lex_errors(tokens: &[Token]) -> Vec<String> {
+    tokens
+        .iter()
+        .filter(|token| token.token_type == TokenType::Error)
+        .map(|token| format!("line {}: {}", token.line + 1, token.error_msg))
+        .collect()
+}
+
+// lexes and parses code, reporting every lexer error, or else every parser error
+fn parse_code(code: &str) -> Result<Code, Vec<String>> {
+    let tokens = lexer::lex::tokenize_code(code);
+    let errors = lex_errors(&tokens);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let (cst, mut errors) = parse_cst_recovering(tokenize_with_trivia(code));
+    // the execs that did parse can still have a bad literal or annotation
+    errors.extend(cst.execs.iter().filter_map(|exec| {
+        let mut error = exec.to_ast().err()?;
+        error.message = format!("{} on line {}", error.message, error.token.line + 1);
+        Some(error)
+    }));
+    if errors.is_empty() {
+        return cst.to_ast().map_err(|e| vec![e.message]);
+    }
+    errors.sort_by_key(|e| (e.token.line, e.token.start));
+    Err(errors.into_iter().map(|e| e.message).collect())
+}
+
+// why code didn't run to the end, after any error has been reported
+enum RunFailure {
+    Syntax,
+    Eval(EvalError),
+}
+
+impl RunFailure {
+    fn status(&self) -> i32 {
+        match self {
+            RunFailure::Syntax => EXIT_SYNTAX,
+            RunFailure::Eval(EvalError::Exit(code)) => *code,
+            RunFailure::Eval(EvalError::StepLimit(_))
+            | RunFailure::Eval(EvalError::DequeLimit(_))
+            | RunFailure::Eval(EvalError::SizeLimit(_))
+            | RunFailure::Eval(EvalError::DepthLimit(_))
+            | RunFailure::Eval(EvalError::Timeout(_)) => EXIT_LIMIT,
+            RunFailure::Eval(EvalError::Permission(_, _)) => EXIT_PERMISSION,
+            RunFailure::Eval(EvalError::Instr(_))
+            | RunFailure::Eval(EvalError::Io(_, _, _))
+            | RunFailure::Eval(EvalError::Effect(_, _)) => EXIT_RUNTIME,
         }
+    }
+}
+
+fn run_command(invocation: Invocation) -> i32 {
+    let source = match invocation.single_source() {
+        Ok(source) => source,
         Err(e) => {
-            println!("{}", e);
-            Err(e)
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let code = match read_source(source) {
+        Ok(code) => code,
+        Err(status) => return status,
+    };
+    let mut interp = invocation.interpreter();
+    if let Some(filename) = &invocation.trace {
+        let out: Box<dyn Write> = if filename == "-" {
+            Box::new(std::io::stderr())
+        } else {
+            match std::fs::File::create(filename) {
+                Ok(file) => Box::new(file),
+                Err(e) => {
+                    eprintln!("error: {}: {}", filename, e);
+                    return EXIT_IO;
+                }
+            }
+        };
+        interp.observe(JsonLines::new(out));
+    }
+    let profile = Rc::new(RefCell::new(Profile::new()));
+    let profiling = invocation.profile || invocation.profile_folded.is_some();
+    if profiling {
+        interp.observe(profile.clone());
+    }
+    let result = run_code(&mut interp, &code, &invocation);
+    // the trace is written as it is dropped
+    drop(interp);
+    // code that didn't parse has nothing to profile
+    if profiling && !matches!(result, Err(RunFailure::Syntax)) {
+        if let Err(status) = write_profile(&profile.borrow(), &code, &invocation) {
+            return status;
+        }
+    }
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(failure) => failure.status(),
+    }
+}
+
+// prints the profile report on stderr, and writes the folded stacks for --profile-folded
+fn write_profile(profile: &Profile, code: &str, invocation: &Invocation) -> Result<(), i32> {
+    if invocation.profile {
+        match profile.report(code) {
+            Ok(report) => eprintln!("{}", report),
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+    if let Some(filename) = &invocation.profile_folded {
+        let folded = profile.folded(code).unwrap_or_default();
+        if let Err(e) = std::fs::write(filename, folded + "\n") {
+            eprintln!("error: {}: {}", filename, e);
+            return Err(EXIT_IO);
         }
     }
+    Ok(())
+}
+
+// runs code on the interpreter, printing whatever the flags ask for
+// errors are reported on stderr, except for the exit instruction, which isn't an error
+fn run_code(
+    interp: &mut Interpreter,
+    code: &str,
+    invocation: &Invocation,
+) -> Result<(), RunFailure> {
+    if invocation.print_tokens {
+        println!("{:?}", lexer::lex::tokenize_code(code));
+    }
+    let ast = parse_code(code).map_err(|errors| {
+        for e in errors {
+            eprintln!("error: {}", e);
+        }
+        RunFailure::Syntax
+    })?;
+    if invocation.print_ast {
+        println!("{:#?}", ast);
+    }
+    let result = interp.run(ast);
+    if invocation.print_stack {
+        print_deque(&interp.deque);
+    }
+    result.map_err(|e| {
+        if !matches!(e, EvalError::Exit(_)) {
+            eprintln!("error: {}", e);
+        }
+        RunFailure::Eval(e)
+    })
 }
 
 pub fn print_deque(deque: &VecDeque<Value>) {
@@ -156,43 +509,34 @@ pub fn print_deque(deque: &VecDeque<Value>) {
         out_str.push_str(val.clone().to_string().as_str());
         out_str.push_str(", ");
     }
-    out_str.push_str(")");
+    out_str.push(')');
     println!("{}", out_str);
 }
 
-pub fn repl(print_tokens: bool, print_ast: bool, print_stack: bool) {
-    let mut deque: VecDeque<evaluator::eval_value::Value> = std::collections::VecDeque::new();
-
+fn repl_command(invocation: Invocation) -> i32 {
+    if !invocation.sources.is_empty() {
+        eprintln!("error: repl doesn't take a script");
+        return EXIT_USAGE;
+    }
+    let mut interp = invocation.interpreter();
     // adapted from the example code on https://github.com/kkawakam/rustyline
     let mut rl = Editor::<()>::new();
     loop {
-        print!("");
         let readline = rl.readline(">>> ");
         match readline {
             Ok(input) => {
                 rl.add_history_entry(input.as_str());
-                if input.len() <= 0 {
-                    println!("");
+                if input.is_empty() {
+                    println!();
                     break;
                 }
-                let code_result = run_code(
-                    Some(deque.clone()),
-                    input,
-                    print_tokens,
-                    print_ast,
-                    print_stack,
-                );
-                match code_result {
-                    Ok(new_deque) => {
-                        deque = new_deque;
-                        // println!();
-                    }
-                    Err(e) => {
-                        println!("{}", e);
-                        if print_stack {
-                            print_deque(&deque);
-                        }
-                    }
+                // keep the deque from before the line if it fails
+                let deque = interp.deque.clone();
+                match run_code(&mut interp, &input, &invocation) {
+                    Ok(()) => {}
+                    // the exit instruction ends the REPL; other errors only end the line
+                    Err(RunFailure::Eval(EvalError::Exit(code))) => return code,
+                    Err(_) => interp.deque = deque,
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -204,9 +548,402 @@ pub fn repl(print_tokens: bool, print_ast: bool, print_stack: bool) {
                 break;
             }
             Err(err) => {
-                println!("Error: {:?}", err);
-                break;
+                eprintln!("error: {:?}", err);
+                return EXIT_IO;
             }
         }
     }
+    EXIT_OK
+}
+
+fn debug_command(invocation: Invocation) -> i32 {
+    let source = match invocation.single_source() {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    // spans count from the start of the file, so a shebang is kept, as a comment
+    let read = match source {
+        Source::File(filename) => read_script(filename),
+        Source::Expr(expr) => Ok(expr.clone()),
+    };
+    let code = match read {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}: {}", source.name(), e);
+            return EXIT_IO;
+        }
+    };
+    let mut debugger = match Debugger::new(&code, invocation.interpreter()) {
+        Ok(debugger) => debugger,
+        Err(e) => {
+            eprintln!("error: {}: {}", source.name(), e);
+            return EXIT_SYNTAX;
+        }
+    };
+    println!("{}", debugger.show());
+    let mut rl = Editor::<()>::new();
+    let mut last = String::new();
+    loop {
+        match rl.readline("(debug) ") {
+            Ok(input) => {
+                let input = input.trim().to_string();
+                // an empty line repeats the last command that ran the program
+                let command = if input.is_empty() {
+                    last.clone()
+                } else {
+                    rl.add_history_entry(input.as_str());
+                    input
+                };
+                if command.is_empty() {
+                    continue;
+                }
+                if command == "q" || command == "quit" {
+                    break;
+                }
+                println!("{}", debugger.command(&command));
+                let name = command.split_whitespace().next().unwrap_or("");
+                if ["s", "step", "n", "next", "c", "continue"].contains(&name) {
+                    last = command;
+                }
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("error: {:?}", err);
+                return EXIT_IO;
+            }
+        }
+    }
+    match debugger.outcome() {
+        Some(Err(e)) => RunFailure::Eval(e.clone()).status(),
+        _ => EXIT_OK,
+    }
+}
+
+fn check_command(invocation: Invocation) -> i32 {
+    if invocation.sources.is_empty() {
+        eprintln!("error: no script or expression given");
+        return EXIT_USAGE;
+    }
+    let mut status = EXIT_OK;
+    for source in invocation.sources.iter() {
+        let code = match read_source(source) {
+            Ok(code) => code,
+            Err(e) => {
+                status = status.max(e);
+                continue;
+            }
+        };
+        if let Err(errors) = parse_code(&code) {
+            for e in errors {
+                eprintln!("{}: {}", source.name(), e);
+            }
+            status = status.max(EXIT_SYNTAX);
+            continue;
+        }
+        // the concrete syntax tree gives the line of each exec the checker reports
+        let cst = match parse_cst(tokenize_with_trivia(&code)) {
+            Ok(cst) => cst,
+            Err(_) => continue,
+        };
+        let ast = match cst.to_ast() {
+            Ok(ast) => ast,
+            Err(_) => continue,
+        };
+        let report = check_code(&ast);
+        // warnings are about code that will fail, but they don't change the exit status
+        for warning in report.warnings.iter() {
+            eprintln!(
+                "{}: line {}: warning: {}",
+                source.name(),
+                exec_line(&cst, &warning.path),
+                warning.message
+            );
+        }
+        if invocation.print_effects {
+            for block in report.effects.iter() {
+                let effect = match &block.effect {
+                    Some(effect) => effect.to_string(),
+                    None => "( ? )".to_string(),
+                };
+                println!(
+                    "{}: line {}: {}",
+                    source.name(),
+                    exec_line(&cst, &block.path),
+                    effect
+                );
+            }
+        }
+    }
+    status
+}
+
+// the line, counting from 1, of the exec at a path
+fn exec_line(cst: &CstCode, path: &[usize]) -> usize {
+    cst.exec_at(path)
+        .map_or(0, |exec| exec.op.first_token().token.line + 1)
+}
+
+// the lint config named with --config, or else .dequelint in the current directory, if there is one
+fn lint_config(invocation: &Invocation) -> Result<LintConfig, String> {
+    let filename = match &invocation.lint_config {
+        Some(filename) => filename.as_str(),
+        None if Path::new(DEFAULT_LINT_CONFIG).exists() => DEFAULT_LINT_CONFIG,
+        None => return Ok(LintConfig::default()),
+    };
+    let text = std::fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    LintConfig::parse(&text).map_err(|e| format!("{}: {}", filename, e))
+}
+
+const DEFAULT_LINT_CONFIG: &str = ".dequelint";
+
+fn lint_command(invocation: Invocation) -> i32 {
+    if invocation.sources.is_empty() {
+        eprintln!("error: no script or expression given");
+        return EXIT_USAGE;
+    }
+    let config = match lint_config(&invocation) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let mut status = EXIT_OK;
+    for source in invocation.sources.iter() {
+        // spans count from the start of the file, so a shebang is kept, as a comment
+        let read = match source {
+            Source::File(filename) => read_script(filename),
+            Source::Expr(expr) => Ok(expr.clone()),
+        };
+        let code = match read {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("error: {}: {}", source.name(), e);
+                status = status.max(EXIT_IO);
+                continue;
+            }
+        };
+        let mut lints = match lint_code(&code, &config) {
+            Ok(lints) => lints,
+            Err(e) => {
+                eprintln!("{}: {}", source.name(), e);
+                status = status.max(EXIT_SYNTAX);
+                continue;
+            }
+        };
+        if invocation.format_write {
+            let filename = match source {
+                Source::File(filename) if filename != "-" => filename,
+                _ => {
+                    eprintln!("error: --write only rewrites script files");
+                    return EXIT_USAGE;
+                }
+            };
+            let (fixed, unfixed) = apply_fixes(&code, lints);
+            if fixed != code {
+                if let Err(e) = std::fs::write(filename, fixed) {
+                    eprintln!("error: {}: {}", filename, e);
+                    status = status.max(EXIT_IO);
+                }
+            }
+            lints = unfixed;
+        }
+        for lint in lints.iter() {
+            println!(
+                "{}: line {}, column {}: {}: {}",
+                source.name(),
+                lint.line,
+                lint.column,
+                lint.rule,
+                lint.message
+            );
+            if let Some(fix) = &lint.fix {
+                println!("    fix: {}", fix.message);
+            }
+        }
+        if !lints.is_empty() {
+            status = status.max(EXIT_RUNTIME);
+        }
+    }
+    status
+}
+
+fn fmt_command(invocation: Invocation) -> i32 {
+    if invocation.sources.is_empty() {
+        eprintln!("error: no script or expression given");
+        return EXIT_USAGE;
+    }
+    let mut status = EXIT_OK;
+    for source in invocation.sources.iter() {
+        // a shebang is a comment to the formatter, so it is kept
+        let read = match source {
+            Source::File(filename) => read_script(filename),
+            Source::Expr(expr) => Ok(expr.clone()),
+        };
+        let code = match read {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("error: {}: {}", source.name(), e);
+                status = status.max(EXIT_IO);
+                continue;
+            }
+        };
+        let formatted = match format_code(&code, &invocation.format_options) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("{}: {}", source.name(), e);
+                status = status.max(EXIT_SYNTAX);
+                continue;
+            }
+        };
+        if invocation.format_check {
+            if formatted != code {
+                println!("{}", source.name());
+                status = status.max(EXIT_RUNTIME);
+            }
+        } else if invocation.format_write {
+            match source {
+                Source::File(filename) if filename != "-" => {
+                    if formatted != code {
+                        if let Err(e) = std::fs::write(filename, formatted) {
+                            eprintln!("error: {}: {}", filename, e);
+                            status = status.max(EXIT_IO);
+                        }
+                    }
+                }
+                _ => {
+                    eprintln!("error: --write only rewrites script files");
+                    return EXIT_USAGE;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    status
+}
+
+fn dump_command(invocation: Invocation, ast: bool) -> i32 {
+    let source = match invocation.single_source() {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let code = match read_source(source) {
+        Ok(code) => code,
+        Err(status) => return status,
+    };
+    if !ast {
+        for token in lexer::lex::tokenize_code(&code.to_string()) {
+            println!("{}", token);
+        }
+        return EXIT_OK;
+    }
+    match parse_code(&code) {
+        Ok(ast) => {
+            println!("{:#?}", ast);
+            EXIT_OK
+        }
+        Err(errors) => {
+            for e in errors {
+                eprintln!("{}: {}", source.name(), e);
+            }
+            EXIT_SYNTAX
+        }
+    }
+}
+
+// runs every .deque script in the given files and directories
+// a script passes if it runs without an error and, if there is a .expected file next to it,
+// prints exactly its contents; a .in file next to it is used as its stdin
+fn test_command(invocation: Invocation) -> i32 {
+    let mut scripts = Vec::new();
+    for source in invocation.sources.iter() {
+        match source {
+            Source::File(path) => {
+                if let Err(e) = collect_scripts(Path::new(path), &mut scripts) {
+                    eprintln!("error: {}: {}", path, e);
+                    return EXIT_IO;
+                }
+            }
+            Source::Expr(_) => {
+                eprintln!("error: test only runs script files");
+                return EXIT_USAGE;
+            }
+        }
+    }
+    if scripts.is_empty() {
+        eprintln!("error: no .deque scripts found");
+        return EXIT_USAGE;
+    }
+
+    let mut failed = 0;
+    for script in scripts.iter() {
+        match test_script(script, &invocation) {
+            Ok(()) => println!("ok   {}", script.display()),
+            Err(e) => {
+                println!("FAIL {}", script.display());
+                for line in e.lines() {
+                    println!("     {}", line);
+                }
+                failed += 1;
+            }
+        }
+    }
+    println!("{} passed, {} failed", scripts.len() - failed, failed);
+    if failed == 0 {
+        EXIT_OK
+    } else {
+        EXIT_RUNTIME
+    }
+}
+
+fn collect_scripts(path: &Path, scripts: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            entries.push(entry?.path());
+        }
+        entries.sort();
+        for entry in entries {
+            if entry.extension().is_some_and(|ext| ext == "deque") {
+                scripts.push(entry);
+            }
+        }
+    } else {
+        // make sure the file exists, so a typo isn't reported as a failing test
+        std::fs::metadata(path)?;
+        scripts.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn test_script(script: &Path, invocation: &Invocation) -> Result<(), String> {
+    let code = std::fs::read_to_string(script).map_err(|e| e.to_string())?;
+    let ast = parse_code(strip_shebang(&code)).map_err(|errors| errors.join("\n"))?;
+    let input = std::fs::read(script.with_extension("in")).unwrap_or_default();
+    let output = SharedBuffer::new();
+    let mut interp = invocation
+        .interpreter()
+        .with_input(Box::new(Cursor::new(input)))
+        .with_output(Box::new(output.clone()));
+    match interp.run(ast) {
+        Ok(()) | Err(EvalError::Exit(0)) => {}
+        Err(e) => return Err(e.to_string()),
+    }
+    if let Ok(expected) = std::fs::read_to_string(script.with_extension("expected")) {
+        let actual = output.text();
+        if actual != expected {
+            return Err(format!(
+                "expected output:\n{}\nactual output:\n{}",
+                expected, actual
+            ));
+        }
+    }
+    Ok(())
 }
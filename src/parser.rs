@@ -21,8 +21,8 @@ pub mod par_ast {
     impl Display for Exec {
         fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
             match self {
-                Exec::Left(op) => write!(f, "{}", op.to_string()),
-                Exec::Right(op) => write!(f, "{}", op.to_string()),
+                Exec::Left(op) => write!(f, "{}", op),
+                Exec::Right(op) => write!(f, "{}", op),
             }
         }
     }
@@ -40,11 +40,13 @@ pub mod par_ast {
         pub fn new_instruction(instruction: String) -> Op {
             Op::Instruction(instruction)
         }
+    }
 
-        pub fn to_string(&self) -> String {
+    impl Display for Op {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
             match self {
-                Op::Literal(literal) => literal.clone().to_string(),
-                Op::Instruction(instruction) => instruction.clone(),
+                Op::Literal(literal) => write!(f, "{}", literal),
+                Op::Instruction(instruction) => write!(f, "{}", instruction),
             }
         }
     }
@@ -56,10 +58,124 @@ pub mod par_ast {
         Bool(bool),
         Char(char),
         List(Vec<Literal>),
-        Block(Vec<Exec>),
+        Block(Block),
         None,
     }
 
+    // the code of a block literal, with the stack effect it was annotated with, if any
+    #[derive(Debug, Clone)]
+    pub struct Block {
+        pub execs: Code,
+        pub effect: Option<StackEffect>,
+        // where the literal is in the program it was parsed from: its index in the code,
+        // then its index in each block around it; empty for blocks that weren't parsed
+        pub path: Vec<usize>,
+    }
+
+    // where a block was written isn't part of its value
+    impl PartialEq for Block {
+        fn eq(&self, other: &Block) -> bool {
+            self.execs == other.execs && self.effect == other.effect
+        }
+    }
+
+    // records in each block literal in the code where it is
+    pub fn set_paths(code: &mut Code, path: &[usize]) {
+        for (i, exec) in code.iter_mut().enumerate() {
+            if let Exec::Left(Op::Literal(Literal::Block(block)))
+            | Exec::Right(Op::Literal(Literal::Block(block))) = exec
+            {
+                block.path = [path, &[i]].concat();
+                set_paths(&mut block.execs, &block.path.clone());
+            }
+        }
+    }
+
+    // a stack effect annotation, `( a b -- c )`, written at the start of a block
+    // the names are only documentation; what counts is how many there are on each side
+    // inputs are in the order they are popped, outputs in the order they are pushed,
+    // like the effects in the instruction docs
+    // names before a `|` are on the front of the deque: `( a | b -- c | d )`
+    #[derive(Debug, PartialEq, Clone, Default)]
+    pub struct StackEffect {
+        pub left_in: Vec<String>,
+        pub right_in: Vec<String>,
+        pub left_out: Vec<String>,
+        pub right_out: Vec<String>,
+    }
+
+    impl StackEffect {
+        // parses the text of an annotation, parentheses included
+        pub fn parse(text: &str) -> Result<StackEffect, String> {
+            let inner = text
+                .strip_prefix('(')
+                .and_then(|text| text.strip_suffix(')'))
+                .ok_or("Invalid stack effect: expected parentheses")?;
+            // a | needs no spaces around it
+            let inner = inner.replace('|', " | ");
+            let words: Vec<&str> = inner.split_whitespace().collect();
+            let mut halves = words.split(|word| *word == "--");
+            let (inputs, outputs) = match (halves.next(), halves.next(), halves.next()) {
+                (Some(inputs), Some(outputs), None) => (inputs, outputs),
+                _ => return Err("Invalid stack effect: expected exactly one --".to_string()),
+            };
+            let (left_in, right_in) = StackEffect::sides(inputs)?;
+            let (left_out, right_out) = StackEffect::sides(outputs)?;
+            // a | on one side of the -- needs one on the other, so the sides line up
+            if inputs.contains(&"|") != outputs.contains(&"|") {
+                return Err("Invalid stack effect: | must be on both sides of --".to_string());
+            }
+            Ok(StackEffect {
+                left_in,
+                right_in,
+                left_out,
+                right_out,
+            })
+        }
+
+        // splits the names on one side of the -- into the front and the back
+        fn sides(words: &[&str]) -> Result<(Vec<String>, Vec<String>), String> {
+            let names = |words: &[&str]| words.iter().map(|word| word.to_string()).collect();
+            let mut sides = words.split(|word| *word == "|");
+            match (sides.next(), sides.next(), sides.next()) {
+                (Some(back), None, _) => Ok((Vec::new(), names(back))),
+                (Some(front), Some(back), None) => Ok((names(front), names(back))),
+                _ => Err("Invalid stack effect: more than one | on a side".to_string()),
+            }
+        }
+
+        // the number of values popped
+        pub fn inputs(&self) -> usize {
+            self.left_in.len() + self.right_in.len()
+        }
+
+        // the number of values pushed
+        pub fn outputs(&self) -> usize {
+            self.left_out.len() + self.right_out.len()
+        }
+    }
+
+    impl Display for StackEffect {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            let mut words = vec!["("];
+            if self.left_in.is_empty() && self.left_out.is_empty() {
+                words.extend(self.right_in.iter().map(String::as_str));
+                words.push("--");
+                words.extend(self.right_out.iter().map(String::as_str));
+            } else {
+                words.extend(self.left_in.iter().map(String::as_str));
+                words.push("|");
+                words.extend(self.right_in.iter().map(String::as_str));
+                words.push("--");
+                words.extend(self.left_out.iter().map(String::as_str));
+                words.push("|");
+                words.extend(self.right_out.iter().map(String::as_str));
+            }
+            words.push(")");
+            write!(f, "{}", words.join(" "))
+        }
+    }
+
     impl Literal {
         pub fn new_int(value: i64) -> Literal {
             Literal::Int(value)
@@ -77,13 +193,26 @@ pub mod par_ast {
             Literal::List(value)
         }
         pub fn new_block(value: Vec<Exec>) -> Literal {
-            Literal::Block(value)
+            Literal::Block(Block {
+                execs: value,
+                effect: None,
+                path: Vec::new(),
+            })
         }
+        pub fn new_annotated_block(value: Vec<Exec>, effect: StackEffect) -> Literal {
+            Literal::Block(Block {
+                execs: value,
+                effect: Some(effect),
+                path: Vec::new(),
+            })
+        }
+    }
 
-        pub fn to_string(&self) -> String {
-            match self {
+    impl Display for Literal {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            let s = match self {
                 Literal::Int(i) => i.to_string(),
-                Literal::Float(f) => f.to_string(),
+                Literal::Float(n) => n.to_string(),
                 Literal::Bool(b) => b.to_string(),
                 Literal::Char(c) => format!("'{}'", c),
                 Literal::List(ref l) => {
@@ -110,229 +239,219 @@ pub mod par_ast {
                         // and put it in square brackets, comma separated
                         let mut s = "[".to_string();
                         for lit in l {
-                            s.push_str(&lit.clone().to_string());
+                            s.push_str(&lit.to_string());
                             s.push_str(", ");
                         }
-                        s.push_str("]");
+                        s.push(']');
                         s
                     }
                 }
                 Literal::Block(b) => {
                     // do the same thing as list, but execs print their lexeme and sigil
                     let mut s = "{".to_string();
-                    for exec in b {
+                    if let Some(effect) = &b.effect {
+                        s.push_str(&effect.to_string());
+                        s.push(' ');
+                    }
+                    for exec in b.execs.iter() {
                         match exec {
                             Exec::Left(op) => {
                                 s.push_str(op.to_string().as_str());
-                                s.push_str("!");
+                                s.push('!');
                             }
                             Exec::Right(op) => {
                                 s.push_str(op.to_string().as_str());
-                                s.push_str("~");
+                                s.push('~');
                             }
                         }
-                        s.push_str(" ");
+                        s.push(' ');
                     }
-                    s.push_str("}");
+                    s.push('}');
                     s
                 }
                 _ => "".to_string(),
-            }
+            };
+            write!(f, "{}", s)
         }
     }
-
-    // impl Display for Literal {
-    //     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    //         match self {
-    //             Literal::Int(value) => write!(f, "{}", value),
-    //             Literal::Float(value) => write!(f, "{}", value),
-    //             Literal::Bool(value) => write!(f, "{}", value),
-    //             Literal::Char(value) => write!(f, "{}", value),
-    //             Literal::List(value) => write!(f, "{:?}", value),
-    //             Literal::Block(value) => write!(f, "{:?}", value),
-    //         }
-    //     }
-    // }
 }
 
 pub mod par {
+    // the AST is only built by lowering the concrete syntax tree, so running, checking,
+    // linting and formatting code all parse it the same way
 
-    use crate::lexer::lex_token::{Token, TokenType};
+    use crate::lexer::lex::tokenize_with_trivia;
+    use crate::lexer::lex_token::{Token, TokenType, TriviaToken};
     use crate::parser::par_ast::*;
+    use crate::parser::par_cst::*;
     use std::vec;
 
-    // type CodeResult = Result<Code, String>;
     type ExecResult = Result<Exec, String>;
     type OpResult = Result<Op, String>;
     type LiteralResult = Result<Literal, String>;
 
+    // parses source code into an ast, with root at Code
+    pub fn parse_source(source: &str) -> Result<Code, String> {
+        Ok(parse_cst(tokenize_with_trivia(source))?.to_ast()?)
+    }
+
     // parses an input vec of tokens into an ast, with root at Code
     pub fn parse_tokens(tokens: &mut vec::IntoIter<Token>) -> Result<Code, String> {
-        let mut code: Code = Vec::new();
-        // while ther are tokens left, parse execs by calling parse_exec
-        while tokens.into_iter().len() > 0 {
-            let exec = parse_exec(tokens);
-            match exec {
-                Ok(e) => code.push(e),
-                Err(e) => return Err(e),
-            }
-        }
-        Ok(code)
+        let end = end_token(tokens);
+        let mut code: Vec<TriviaToken> = tokens.map(bare).collect();
+        code.push(bare(end));
+        Ok(parse_cst(code)?.to_ast()?)
     }
 
     // parses a list of tokens into an Exec
     pub fn parse_exec(tokens: &mut vec::IntoIter<Token>) -> ExecResult {
-        // println!("parse_exec {:?}", &tokens);
-        let op = parse_op(tokens);
-        match op {
-            Ok(op) => {
-                let mut iter = tokens.peekable();
-                let sigil = iter.peek();
-                match sigil {
-                    Some(_) => match sigil.unwrap().token_type {
-                        TokenType::Bang => Ok(Exec::new_left(op)),
-                        TokenType::Tilde => Ok(Exec::new_right(op)),
-                        _ => Err(format!(
-                            "Parser Error: Expected sigil Bang or Tilde, instead got {:?}",
-                            sigil.unwrap().token_type
-                        )),
-                    },
-                    None => {
-                        Err("Parser Error: Expected sigil Bang or Tilde, but got None".to_string())
-                    }
-                }
-            }
-            // println!("    exec sigil {:?}", &sigil);
-            Err(e) => Err(e),
+        match with_cst(tokens, parse_cst_exec)? {
+            Some(exec) => Ok(exec.to_ast()?),
+            None => Err("Parser Error: Expected sigil Bang or Tilde, but got None".to_string()),
         }
     }
 
     // parses a list of tokens into an Op
     pub fn parse_op(tokens: &mut vec::IntoIter<Token>) -> OpResult {
-        // println!("parse_op {:?}", &tokens);
-        let op_token = tokens.next().unwrap();
-        // println!("    op_token {:?}", &op_token);
-        match op_token.token_type {
-            TokenType::ConstInt
-            | TokenType::ConstFloat
-            | TokenType::ConstChar
-            | TokenType::ConstString
-            | TokenType::ConstBool => {
-                let parsed = parse_literal(&op_token);
-                match parsed {
-                    Ok(lit) => Ok(Op::new_literal(lit)),
-                    Err(e) => Err(format!("Parser Error: {} for {:?}", e, op_token)),
-                }
-            }
-            TokenType::LeftSquare => {
-                let parsed = parse_list(tokens, true);
-                match parsed {
-                    Ok(list) => Ok(Op::new_literal(list)),
-                    Err(e) => Err(format!("Parser Error: {} for {:?}", e, op_token)),
-                }
-            }
-            TokenType::LeftCurly => {
-                let parsed = parse_block(tokens);
-                match parsed {
-                    Ok(block) => Ok(Op::new_literal(block)),
-                    Err(e) => Err(format!("Parser Error: {} for {:?}", e, op_token)),
-                }
-            }
-            TokenType::Instr => Ok(Op::new_instruction(op_token.lexeme.clone())),
-            tt => Err(format!(
-                "Parse Error: Unexpected token type {:?} for Op",
-                tt
-            )),
-        }
+        Ok(with_cst(tokens, parse_cst_op)?.to_ast()?)
     }
 
-    // parses a list of tokens into a list literal
-    // returns the list literal and the remaining tokens
-    // https://stackoverflow.com/questions/60087757/passing-an-iterator-into-a-recursive-call-during-an-iteration-in-rust
+    // parses a list of tokens into a list literal, leaving the tokens after it
+    // a nested list has had its left square taken already
     pub fn parse_list(tokens: &mut vec::IntoIter<Token>, nested: bool) -> LiteralResult {
-        let iter = tokens;
-        let mut list: Vec<Literal> = vec![];
-        let mut ended_last_list = false;
+        let open = if nested {
+            open_token(TokenType::LeftSquare, "[", tokens)
+        } else {
+            tokens.next().unwrap_or_else(|| end_token(tokens))
+        };
+        let unclosed = unclosed_lists(tokens.as_slice());
+        let list = with_cst(tokens, |tokens, _| parse_cst_list(bare(open), tokens));
+        match list {
+            Ok(list) => Ok(list.to_ast()?),
+            // an unclosed list names each list it is nested in
+            Err(_) if unclosed.is_some() => Err(unclosed
+                .unwrap()
+                .iter()
+                .rev()
+                .fold("Parsing Error: Unclosed list".to_string(), |e, token| {
+                    format!("Parser Error: {} for {:?}", e, token)
+                })),
+            Err(e) => Err(e),
+        }
+    }
+
+    // parses the tokens after a left curly into a block literal, up to its right curly
+    pub fn parse_block(tokens: &mut vec::IntoIter<Token>) -> LiteralResult {
+        let open = open_token(TokenType::LeftCurly, "{", tokens);
+        let mut block = vec![bare(open)];
+        block.extend(tokens.as_slice().iter().cloned().map(bare));
+        block.push(bare(end_token(tokens)));
+        let mut block = block.into_iter().peekable();
+        let mut errors = vec![];
+        let op = parse_cst_op(&mut block, &mut errors);
+        take_used(tokens, block.len());
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error.into());
+        }
+        match op?.to_ast()? {
+            Op::Literal(block) => Ok(block),
+            op => Err(format!("Parser Error: Expected a block, got {}", op)),
+        }
+    }
+
+    // runs a CST parser over what is left of the tokens, then takes the tokens it used
+    fn with_cst<T>(
+        tokens: &mut vec::IntoIter<Token>,
+        parse: impl FnOnce(&mut Tokens, &mut Vec<CstError>) -> Result<T, CstError>,
+    ) -> Result<T, String> {
+        let mut rest: Vec<TriviaToken> = tokens.as_slice().iter().cloned().map(bare).collect();
+        rest.push(bare(end_token(tokens)));
+        let mut rest = rest.into_iter().peekable();
+        let mut errors = vec![];
+        let parsed = parse(&mut rest, &mut errors);
+        take_used(tokens, rest.len());
+        match errors.into_iter().next() {
+            Some(e) => Err(e.into()),
+            None => Ok(parsed?),
+        }
+    }
 
-        // skip initial left square
-        if !nested {
-            iter.next();
+    // takes the tokens a CST parser used, given how many of them and the End are left
+    fn take_used(tokens: &mut vec::IntoIter<Token>, left: usize) {
+        let used = (tokens.len() + 1).saturating_sub(left).min(tokens.len());
+        for _ in 0..used {
+            tokens.next();
         }
-        while let Some(token) = iter.next() {
-            // panic if the input ends before a closing square bracket
-            // println!("current token: {:?}", token);
+    }
+
+    // the left squares of the nested lists left open at the end of the tokens,
+    // or None when the list is closed
+    fn unclosed_lists(tokens: &[Token]) -> Option<Vec<Token>> {
+        let mut open = vec![];
+        for token in tokens {
             match token.token_type {
-                // if see another list, recurse into another list
-                TokenType::LeftSquare => {
-                    // println!("    making nested list at {:?}", token);
-                    ended_last_list = false;
-                    // make a new list
-                    let parsed = parse_list(iter, true);
-                    match parsed {
-                        Ok(list_lit) => {
-                            // println!("    nested list {:?}", list_lit);
-                            list.push(list_lit);
-                        }
-                        Err(e) => return Err(format!("Parser Error: {} for {:?}", e, token)),
-                    }
-                }
-                // ignore commas
-                TokenType::Comma => {
-                    continue;
-                }
-                // finish the current list
+                TokenType::LeftSquare => open.push(token.clone()),
+                TokenType::RightSquare if open.is_empty() => return None,
                 TokenType::RightSquare => {
-                    // println!("    closing this list");
-                    ended_last_list = true;
-                    break;
-                }
-                // otherwise, parse the literal and add it to the list
-                _ => {
-                    let parsed = parse_literal(&token);
-                    match parsed {
-                        Ok(lit) => list.push(lit),
-                        Err(e) => return Err(format!("Parser Error: {} for {:?}", e, token)),
-                    }
+                    open.pop();
                 }
+                _ => {}
             }
         }
-        // if the list did not close, panic
-        if !ended_last_list {
-            return Err(format!("Parsing Error: Unclosed list"));
+        Some(open)
+    }
+
+    fn bare(token: Token) -> TriviaToken {
+        TriviaToken {
+            leading: vec![],
+            text: token.lexeme.clone(),
+            token,
         }
-        Ok(Literal::new_list(list))
     }
 
-    pub fn parse_block(tokens: &mut vec::IntoIter<Token>) -> LiteralResult {
-        // println!("parse_block {:?}", &tokens);
-        let mut block: Vec<Exec> = vec![];
-        while tokens.len() > 0 {
-            // println!("bef----  {:?}", &tokens);
-            let mut iter = tokens.clone();
-            if let Some(Token {
-                token_type: TokenType::RightCurly,
-                ..
-            }) = iter.next()
-            {
-                break;
-            }
-            let exec = parse_exec(tokens);
-            match exec {
-                Ok(e) => block.push(e),
-                Err(e) => return Err(format!("Parser Error: {} for {:?}", e, tokens)),
-            }
-            // println!("aft----  {:?}", &tokens);
+    // stands in for a bracket a caller has already taken
+    fn open_token(token_type: TokenType, lexeme: &str, tokens: &vec::IntoIter<Token>) -> Token {
+        Token {
+            token_type,
+            lexeme: lexeme.to_string(),
+            ..end_token(tokens)
+        }
+    }
+
+    // the End after the tokens left, which tokenize_code leaves off
+    fn end_token(tokens: &vec::IntoIter<Token>) -> Token {
+        let (end, line) = match tokens.as_slice().last() {
+            Some(last) => (last.end, last.line),
+            None => (0, 0),
+        };
+        Token {
+            token_type: TokenType::End,
+            lexeme: String::new(),
+            error_msg: String::new(),
+            start: end,
+            end,
+            line,
         }
-        // println!("finish--  {:?}", &tokens);
-        tokens.next();
-        Ok(Literal::new_block(block))
     }
 
     // parses a literal token into a literal
     pub fn parse_literal(token: &Token) -> LiteralResult {
         match token.token_type {
-            TokenType::ConstInt => Ok(Literal::new_int(token.lexeme.parse::<i64>().unwrap())),
-            TokenType::ConstFloat => Ok(Literal::new_float(token.lexeme.parse::<f64>().unwrap())),
+            TokenType::ConstInt => match token.lexeme.parse::<i64>() {
+                Ok(int) => Ok(Literal::new_int(int)),
+                Err(_) => Err(format!(
+                    "Parser Error: Integer literal `{}` is out of range",
+                    token.lexeme
+                )),
+            },
+            // floats too large for an f64 parse as infinity rather than failing
+            TokenType::ConstFloat => match token.lexeme.parse::<f64>() {
+                Ok(float) if float.is_finite() => Ok(Literal::new_float(float)),
+                _ => Err(format!(
+                    "Parser Error: Float literal `{}` is out of range",
+                    token.lexeme
+                )),
+            },
             TokenType::ConstChar => {
                 // strip quotes
                 let mut chars = token.lexeme.chars();
@@ -402,12 +521,478 @@ pub mod par {
                     Ok(Literal::new_bool(false))
                 }
             }
-            _ => {
-                return Err(format!(
-                    "Parser Error: Unexpected token type {:?} for Literal",
-                    token.token_type
-                ))
+            _ => Err(format!(
+                "Parser Error: Unexpected token type {:?} for Literal",
+                token.token_type
+            )),
+        }
+    }
+}
+
+pub mod par_cst {
+    // A concrete syntax tree: the syntax tree together with every token as spelled in the source
+    // and the comments and whitespace around them, so that it writes back out to the exact source.
+    // Tools that rewrite source work on the CST; the evaluator works on the AST it lowers to.
+
+    use crate::lexer::lex_token::{Token, TokenType, TriviaToken};
+    use crate::parser::par::parse_literal;
+    use crate::parser::par_ast::*;
+    use std::fmt::{Display, Formatter, Result as FmtResult};
+    use std::iter::Peekable;
+    use std::vec;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CstCode {
+        pub execs: Vec<CstExec>,
+        pub end: TriviaToken, // holds the trivia after the last exec
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CstExec {
+        pub op: CstOp,
+        pub sigil: TriviaToken,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum CstOp {
+        Atom(TriviaToken), // a literal or an instruction
+        List(CstList),
+        Block(CstBlock),
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CstList {
+        pub open: TriviaToken,
+        pub items: Vec<CstItem>,
+        pub close: TriviaToken,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum CstItem {
+        Atom(TriviaToken),
+        List(CstList),
+        Comma(TriviaToken),
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CstBlock {
+        pub open: TriviaToken,
+        pub effect: Option<TriviaToken>, // a stack effect annotation
+        pub execs: Vec<CstExec>,
+        pub close: TriviaToken,
+    }
+
+    // a lexer, parser or literal error, with the token it was found at
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CstError {
+        pub message: String,
+        pub token: Token,
+    }
+
+    impl CstError {
+        fn new(message: String, token: &Token) -> CstError {
+            CstError {
+                message,
+                token: token.clone(),
             }
         }
     }
+
+    impl Display for CstError {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{}", self.message)
+        }
+    }
+
+    impl From<CstError> for String {
+        fn from(error: CstError) -> String {
+            error.message
+        }
+    }
+
+    type CstResult<T> = Result<T, CstError>;
+
+    impl CstCode {
+        pub fn to_ast(&self) -> CstResult<Code> {
+            let mut code = self
+                .execs
+                .iter()
+                .map(CstExec::to_ast)
+                .collect::<CstResult<Code>>()?;
+            set_paths(&mut code, &[]);
+            Ok(code)
+        }
+
+        // the exec at a path of indices into the code and then into nested blocks,
+        // which are the same in the AST
+        pub fn exec_at(&self, path: &[usize]) -> Option<&CstExec> {
+            let (first, rest) = path.split_first()?;
+            let mut exec = self.execs.get(*first)?;
+            for i in rest {
+                exec = match &exec.op {
+                    CstOp::Block(block) => block.execs.get(*i)?,
+                    _ => return None,
+                };
+            }
+            Some(exec)
+        }
+    }
+
+    impl CstExec {
+        pub fn is_left(&self) -> bool {
+            self.sigil.token.token_type == TokenType::Bang
+        }
+
+        pub fn to_ast(&self) -> CstResult<Exec> {
+            let op = self.op.to_ast()?;
+            if self.is_left() {
+                Ok(Exec::new_left(op))
+            } else {
+                Ok(Exec::new_right(op))
+            }
+        }
+    }
+
+    impl CstOp {
+        // the first token of the op, which holds the trivia before it
+        pub fn first_token(&self) -> &TriviaToken {
+            match self {
+                CstOp::Atom(token) => token,
+                CstOp::List(list) => &list.open,
+                CstOp::Block(block) => &block.open,
+            }
+        }
+
+        pub fn to_ast(&self) -> CstResult<Op> {
+            match self {
+                CstOp::Atom(token) if token.token.token_type == TokenType::Instr => {
+                    Ok(Op::new_instruction(token.token.lexeme.clone()))
+                }
+                CstOp::Atom(token) => Ok(Op::new_literal(lower_literal(token)?)),
+                CstOp::List(list) => Ok(Op::new_literal(list.to_ast()?)),
+                CstOp::Block(block) => {
+                    let execs: CstResult<Code> = block.execs.iter().map(CstExec::to_ast).collect();
+                    match &block.effect {
+                        Some(token) => {
+                            let effect = StackEffect::parse(&token.token.lexeme)
+                                .map_err(|e| CstError::new(e, &token.token))?;
+                            Ok(Op::new_literal(Literal::new_annotated_block(
+                                execs?, effect,
+                            )))
+                        }
+                        None => Ok(Op::new_literal(Literal::new_block(execs?))),
+                    }
+                }
+            }
+        }
+    }
+
+    impl CstList {
+        pub fn to_ast(&self) -> CstResult<Literal> {
+            let mut list = vec![];
+            for item in self.items.iter() {
+                match item {
+                    CstItem::Atom(token) => list.push(lower_literal(token)?),
+                    CstItem::List(nested) => list.push(nested.to_ast()?),
+                    CstItem::Comma(_) => {}
+                }
+            }
+            Ok(Literal::new_list(list))
+        }
+    }
+
+    fn lower_literal(token: &TriviaToken) -> CstResult<Literal> {
+        parse_literal(&token.token).map_err(|e| CstError::new(e, &token.token))
+    }
+
+    // writing a CST out gives back its source
+    impl Display for CstCode {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            for exec in self.execs.iter() {
+                write!(f, "{}", exec)?;
+            }
+            write!(f, "{}", self.end)
+        }
+    }
+
+    impl Display for CstExec {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{}{}", self.op, self.sigil)
+        }
+    }
+
+    impl Display for CstOp {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            match self {
+                CstOp::Atom(token) => write!(f, "{}", token),
+                CstOp::List(list) => write!(f, "{}", list),
+                CstOp::Block(block) => write!(f, "{}", block),
+            }
+        }
+    }
+
+    impl Display for CstList {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{}", self.open)?;
+            for item in self.items.iter() {
+                match item {
+                    CstItem::Atom(token) | CstItem::Comma(token) => write!(f, "{}", token)?,
+                    CstItem::List(list) => write!(f, "{}", list)?,
+                }
+            }
+            write!(f, "{}", self.close)
+        }
+    }
+
+    impl Display for CstBlock {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "{}", self.open)?;
+            if let Some(effect) = &self.effect {
+                write!(f, "{}", effect)?;
+            }
+            for exec in self.execs.iter() {
+                write!(f, "{}", exec)?;
+            }
+            write!(f, "{}", self.close)
+        }
+    }
+
+    pub type Tokens = Peekable<vec::IntoIter<TriviaToken>>;
+
+    // parses the tokens from tokenize_with_trivia into a CST
+    // reports the first lexer error too
+    pub fn parse_cst(tokens: Vec<TriviaToken>) -> CstResult<CstCode> {
+        let (code, mut errors) = parse_cst_recovering(tokens);
+        if errors.is_empty() {
+            Ok(code)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    // like parse_cst, but carries on after an error to find the rest of them
+    // an exec with an error is left out of the CST, which goes on from its sigil
+    pub fn parse_cst_recovering(tokens: Vec<TriviaToken>) -> (CstCode, Vec<CstError>) {
+        let mut tokens = tokens.into_iter().peekable();
+        let mut errors = vec![];
+        let mut execs = vec![];
+        loop {
+            execs.extend(parse_cst_execs(&mut tokens, &mut errors));
+            match next_token(&mut tokens) {
+                Ok(end) if end.token.token_type == TokenType::End => {
+                    return (CstCode { execs, end }, errors)
+                }
+                // a closing curly without a block, and the sigil after it
+                Ok(token) => {
+                    errors.push(CstError::new(
+                        format!(
+                            "Parse Error: Unexpected token type {:?} for Op on line {}",
+                            token.token.token_type,
+                            token.token.line + 1
+                        ),
+                        &token.token,
+                    ));
+                    tokens.next_if(|token| is_sigil(&token.token.token_type));
+                }
+                // the tokens ran out without an End
+                Err(error) => {
+                    let end = TriviaToken {
+                        leading: vec![],
+                        token: error.token.clone(),
+                        text: String::new(),
+                    };
+                    errors.push(error);
+                    return (CstCode { execs, end }, errors);
+                }
+            }
+        }
+    }
+
+    // parses execs up to the end of the code or of the current block
+    pub fn parse_cst_execs(tokens: &mut Tokens, errors: &mut Vec<CstError>) -> Vec<CstExec> {
+        let mut execs = vec![];
+        while let Some(token) = tokens.peek() {
+            match token.token.token_type {
+                TokenType::End | TokenType::RightCurly => break,
+                _ => match parse_cst_exec(tokens, errors) {
+                    Ok(Some(exec)) => execs.push(exec),
+                    Ok(None) => {}
+                    // a sigil where an op should be is the end of the exec already
+                    Err(error) if is_sigil(&error.token.token_type) => errors.push(error),
+                    Err(error) => {
+                        errors.push(error);
+                        skip_exec(tokens);
+                    }
+                },
+            }
+        }
+        execs
+    }
+
+    fn is_sigil(tt: &TokenType) -> bool {
+        matches!(tt, TokenType::Bang | TokenType::Tilde)
+    }
+
+    // skips the rest of an exec with an error, up to and including its sigil,
+    // but stops before the end of the block or the code
+    fn skip_exec(tokens: &mut Tokens) {
+        while let Some(token) = tokens.next_if(|token| {
+            !matches!(
+                token.token.token_type,
+                TokenType::End | TokenType::RightCurly
+            )
+        }) {
+            if is_sigil(&token.token.token_type) {
+                return;
+            }
+        }
+    }
+
+    // an exec missing its sigil is an error, but the exec after it can still be parsed,
+    // so the error is kept and the exec left out
+    pub fn parse_cst_exec(
+        tokens: &mut Tokens,
+        errors: &mut Vec<CstError>,
+    ) -> CstResult<Option<CstExec>> {
+        let op = parse_cst_op(tokens, errors)?;
+        let sigil = match tokens.peek() {
+            Some(sigil) => sigil,
+            None => return Err(next_token(tokens).unwrap_err()),
+        };
+        let message = match sigil.token.token_type {
+            TokenType::Bang | TokenType::Tilde | TokenType::Error => {
+                let sigil = next_token(tokens)?;
+                return Ok(Some(CstExec { op, sigil }));
+            }
+            TokenType::End => {
+                "Parser Error: Expected sigil Bang or Tilde, but got None".to_string()
+            }
+            tt => format!(
+                "Parser Error: Expected sigil Bang or Tilde, instead got {:?} on line {}",
+                tt,
+                sigil.token.line + 1
+            ),
+        };
+        errors.push(CstError::new(message, &sigil.token));
+        Ok(None)
+    }
+
+    pub fn parse_cst_op(tokens: &mut Tokens, errors: &mut Vec<CstError>) -> CstResult<CstOp> {
+        let token = next_token(tokens)?;
+        match token.token.token_type {
+            TokenType::ConstInt
+            | TokenType::ConstFloat
+            | TokenType::ConstChar
+            | TokenType::ConstString
+            | TokenType::ConstBool
+            | TokenType::Instr => Ok(CstOp::Atom(token)),
+            TokenType::LeftSquare => Ok(CstOp::List(parse_cst_list(token, tokens)?)),
+            TokenType::Effect => Err(CstError::new(
+                format!(
+                    "Parse Error: Stack effect {} can only start a block, on line {}",
+                    token.token.lexeme,
+                    token.token.line + 1
+                ),
+                &token.token,
+            )),
+            TokenType::LeftCurly => {
+                // a stack effect annotation can only come first
+                let effect = match tokens.peek() {
+                    Some(next) if next.token.token_type == TokenType::Effect => {
+                        Some(next_token(tokens)?)
+                    }
+                    _ => None,
+                };
+                let execs = parse_cst_execs(tokens, errors);
+                let close = next_token(tokens)?;
+                if close.token.token_type != TokenType::RightCurly {
+                    return Err(CstError::new(
+                        format!(
+                            "Parser Error: Unclosed block opened on line {}",
+                            token.token.line + 1
+                        ),
+                        &token.token,
+                    ));
+                }
+                Ok(CstOp::Block(CstBlock {
+                    open: token,
+                    effect,
+                    execs,
+                    close,
+                }))
+            }
+            tt => Err(CstError::new(
+                format!(
+                    "Parse Error: Unexpected token type {:?} for Op on line {}",
+                    tt,
+                    token.token.line + 1
+                ),
+                &token.token,
+            )),
+        }
+    }
+
+    pub fn parse_cst_list(open: TriviaToken, tokens: &mut Tokens) -> CstResult<CstList> {
+        let mut items = vec![];
+        loop {
+            let token = next_token(tokens)?;
+            match token.token.token_type {
+                TokenType::ConstInt
+                | TokenType::ConstFloat
+                | TokenType::ConstChar
+                | TokenType::ConstString
+                | TokenType::ConstBool => items.push(CstItem::Atom(token)),
+                TokenType::Comma => items.push(CstItem::Comma(token)),
+                TokenType::LeftSquare => items.push(CstItem::List(parse_cst_list(token, tokens)?)),
+                TokenType::RightSquare => {
+                    return Ok(CstList {
+                        open,
+                        items,
+                        close: token,
+                    })
+                }
+                TokenType::End => {
+                    return Err(CstError::new(
+                        format!(
+                            "Parsing Error: Unclosed list opened on line {}",
+                            open.token.line + 1
+                        ),
+                        &open.token,
+                    ))
+                }
+                tt => {
+                    return Err(CstError::new(
+                        format!(
+                            "Parser Error: Unexpected token type {:?} for Literal on line {}",
+                            tt,
+                            token.token.line + 1
+                        ),
+                        &token.token,
+                    ))
+                }
+            }
+        }
+    }
+
+    // the next token, failing on lexer errors
+    fn next_token(tokens: &mut Tokens) -> CstResult<TriviaToken> {
+        match tokens.next() {
+            Some(token) if token.token.token_type == TokenType::Error => Err(CstError::new(
+                format!("line {}: {}", token.token.line + 1, token.token.error_msg),
+                &token.token,
+            )),
+            Some(token) => Ok(token),
+            None => Err(CstError {
+                message: "Parser Error: Unexpected end of tokens".to_string(),
+                token: Token {
+                    token_type: TokenType::End,
+                    lexeme: String::new(),
+                    error_msg: String::new(),
+                    start: 0,
+                    end: 0,
+                    line: 0,
+                },
+            }),
+        }
+    }
 }
@@ -0,0 +1,151 @@
+pub mod profile {
+    // an observer that counts and times execs by instruction and by the block they are written in
+    // blocks are told apart by where they are written, so a block's time is the time of the execs
+    // written inside it, wherever it was run from
+
+    use crate::evaluator::eval_trace::{Observer, TraceEvent};
+    use crate::lexer::lex::tokenize_with_trivia;
+    use crate::parser::par_ast::{Exec, Op};
+    use crate::parser::par_cst::parse_cst;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    // what literals are counted as, in place of an instruction name
+    pub const LITERAL: &str = "<literal>";
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct Stat {
+        pub count: u64,
+        pub time: Duration,
+    }
+
+    impl Stat {
+        fn add(&mut self, count: u64, time: Duration) {
+            self.count += count;
+            self.time += time;
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct Profile {
+        // by instruction name, counting each exec
+        pub instrs: HashMap<String, Stat>,
+        // by the path of the block literal, empty for the top level,
+        // counting each time the block starts, and the time of every exec written inside it
+        pub blocks: HashMap<Vec<usize>, Stat>,
+        // by the paths of the blocks an exec is written in, and its instruction name
+        stacks: HashMap<(Vec<usize>, String), Duration>,
+    }
+
+    impl Profile {
+        pub fn new() -> Profile {
+            Profile::default()
+        }
+
+        // a table of the instructions and of the blocks, the slowest first
+        // source is the code that was run, to find where the blocks are
+        pub fn report(&self, source: &str) -> Result<String, String> {
+            let names = self.block_names(source)?;
+            let mut lines = vec![format!("{:>10}  {:>12}  instruction", "count", "time")];
+            for (name, stat) in sorted(&self.instrs) {
+                lines.push(row(stat, name));
+            }
+            lines.push(String::new());
+            lines.push(format!("{:>10}  {:>12}  block", "count", "time"));
+            for (path, stat) in sorted(&self.blocks) {
+                lines.push(row(stat, &names[path]));
+            }
+            Ok(lines.join("\n"))
+        }
+
+        // one line for each stack of blocks and instruction, with its time in nanoseconds,
+        // in the folded format that flamegraph tools read
+        pub fn folded(&self, source: &str) -> Result<String, String> {
+            let names = self.block_names(source)?;
+            let mut lines: Vec<String> = self
+                .stacks
+                .iter()
+                .map(|((block, name), time)| {
+                    let mut frames: Vec<&str> = (0..=block.len())
+                        .map(|end| names[&block[..end]].as_str())
+                        .collect();
+                    frames.push(name);
+                    format!("{} {}", frames.join(";"), time.as_nanos())
+                })
+                .collect();
+            lines.sort();
+            Ok(lines.join("\n"))
+        }
+
+        // where each block literal is written, like block@3:5 for line 3, column 5
+        fn block_names(&self, source: &str) -> Result<HashMap<Vec<usize>, String>, String> {
+            let cst = parse_cst(tokenize_with_trivia(source))?;
+            let chars: Vec<char> = source.chars().collect();
+            let mut names = HashMap::new();
+            for path in self.blocks.keys() {
+                let name = match cst.exec_at(path) {
+                    _ if path.is_empty() => "top".to_string(),
+                    Some(exec) => {
+                        let start = exec.op.first_token().token.start;
+                        let line_start = chars[..start]
+                            .iter()
+                            .rposition(|c| *c == '\n')
+                            .map_or(0, |newline| newline + 1);
+                        let line = chars[..start].iter().filter(|c| **c == '\n').count();
+                        format!("block@{}:{}", line + 1, start - line_start + 1)
+                    }
+                    None => format!("block@{:?}", path),
+                };
+                names.insert(path.clone(), name);
+            }
+            Ok(names)
+        }
+    }
+
+    impl Observer for Profile {
+        fn on_block_enter(&mut self, path: &[usize], _depth: usize) {
+            self.blocks.entry(path.to_vec()).or_default().count += 1;
+        }
+
+        fn after_exec(&mut self, event: &TraceEvent) {
+            let name = match &event.exec {
+                Exec::Left(Op::Instruction(name)) | Exec::Right(Op::Instruction(name)) => {
+                    name.clone()
+                }
+                _ => LITERAL.to_string(),
+            };
+            self.instrs
+                .entry(name.clone())
+                .or_default()
+                .add(1, event.elapsed);
+            // the block an exec is written in is its path without the last index,
+            // and that block and every block around it take the time
+            let block = &event.path[..event.path.len().saturating_sub(1)];
+            for end in 0..=block.len() {
+                self.blocks.entry(block[..end].to_vec()).or_default().time += event.elapsed;
+            }
+            *self.stacks.entry((block.to_vec(), name)).or_default() += event.elapsed;
+        }
+    }
+
+    fn row(stat: &Stat, name: &str) -> String {
+        format!(
+            "{:>10}  {:>12}  {}",
+            stat.count,
+            format!("{:.3}ms", stat.time.as_secs_f64() * 1000.0),
+            name
+        )
+    }
+
+    // the stats, the longest time first, then the most runs
+    fn sorted<K: Ord>(stats: &HashMap<K, Stat>) -> Vec<(&K, &Stat)> {
+        let mut stats: Vec<(&K, &Stat)> = stats.iter().collect();
+        stats.sort_by(|(a_key, a), (b_key, b)| {
+            b.time
+                .cmp(&a.time)
+                .then(b.count.cmp(&a.count))
+                .then(a_key.cmp(b_key))
+        });
+        stats
+    }
+}
@@ -1,3 +1,8 @@
+pub mod test_check;
+pub mod test_debug;
 pub mod test_eval;
+pub mod test_fmt;
 pub mod test_lex;
+pub mod test_lint;
 pub mod test_par;
+pub mod test_profile;
@@ -0,0 +1,220 @@
+#[cfg(test)]
+mod tests {
+    use crate::checker::check::*;
+    use crate::parser::par::*;
+    use crate::parser::par_ast::Code;
+
+    fn parse(code: &str) -> Code {
+        parse_source(code).unwrap()
+    }
+
+    fn warnings(code: &str) -> Vec<String> {
+        check_code(&parse(code))
+            .warnings
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    // the effect of the code, written out
+    fn effect(code: &str) -> String {
+        match infer_effect(&parse(code)) {
+            Some(effect) => effect.to_string(),
+            None => "?".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_check_underflow() {
+        assert_eq!(
+            warnings("+~"),
+            vec!["`+~` needs 2 values, but the deque has 0"]
+        );
+        assert_eq!(
+            warnings("1~ 2~ +~ +!"),
+            vec!["`+!` needs 2 values, but the deque has 1"]
+        );
+        // values on the other side are popped too
+        assert!(warnings("1~ 2! +~").is_empty());
+        // swap and rot do nothing to an empty deque, but swap fails with one value
+        assert!(warnings("swap~ rot~").is_empty());
+        assert_eq!(
+            warnings("1~ swap~"),
+            vec!["`swap~` needs 2 values, but the deque has 1"]
+        );
+        // one missing value is reported, not everything after it
+        assert_eq!(warnings("pop~ pop~ pop~").len(), 1);
+        // blocks that are run are checked on the deque they run on
+        assert_eq!(
+            warnings("1~ {+~}~ exec~"),
+            vec!["`+~` needs 2 values, but the deque has 1"]
+        );
+        assert_eq!(warnings("{+~}~ [1, 2]~ map~").len(), 1);
+        assert_eq!(
+            warnings("\"%d and %d\"~ printf~"),
+            vec!["`printf~` pops from an empty deque"]
+        );
+        assert!(warnings("2~ 1~ \"%d and %d\"~ printf~").is_empty());
+    }
+
+    #[test]
+    fn test_check_types() {
+        assert_eq!(
+            warnings("'a'~ 1~ +~"),
+            vec!["`+~` expects a number, got Char"]
+        );
+        assert_eq!(
+            warnings("1~ 'a'~ <~"),
+            vec!["`<~` can't compare Char with Int"]
+        );
+        assert_eq!(
+            warnings("1~ 2~ 3~ range~"),
+            vec!["`range~` needs 4 values, but the deque has 3"]
+        );
+        assert_eq!(warnings("1~ 2~ ite~ ite~").len(), 1);
+        assert_eq!(
+            warnings("'c'~ \"%d\"~ printf~"),
+            vec!["`printf~` expects an Int, got Char"]
+        );
+        // a block is checked even if it never runs
+        assert_eq!(
+            warnings("{true~ 1.5~ -~}~"),
+            vec!["`-~` expects a number, got Bool"]
+        );
+        // numbers mix, and values of unknown type are never wrong
+        assert!(warnings("1~ 2.5~ *~ 3~ /~ il~ +~").is_empty());
+        assert!(warnings("'a'~ 'b'~ <~ ol~").is_empty());
+    }
+
+    #[test]
+    fn test_check_control_flow() {
+        // a balanced loop keeps the deque known
+        assert!(warnings("0~ {+~}~ 10~ 0~ 1~ range~ ol~").is_empty());
+        assert_eq!(
+            warnings("0~ {+~}~ 10~ 0~ 1~ range~ ol~ ol~"),
+            vec!["`ol~` needs 1 value, but the deque has 0"]
+        );
+        // an unbalanced one doesn't
+        assert!(warnings("{}~ 10~ 0~ 1~ range~ +~ +~").is_empty());
+        // the branches of ite are merged when they agree
+        assert_eq!(
+            warnings("{1~}~ {2~}~ {true~}~ ite~ pop~ pop~"),
+            vec!["`pop~` needs 1 value, but the deque has 0"]
+        );
+        assert!(warnings("{1~}~ {}~ {true~}~ ite~ pop~ pop~").is_empty());
+        // the condition of while runs once more than its body
+        assert!(warnings("5! {1! -!}! {dup! 0! >!}! while! ol!").is_empty());
+        // nothing after loop or exit runs
+        assert!(warnings("{}~ loop~ +~").is_empty());
+        assert!(warnings("0~ exit~ +~").is_empty());
+        // exec does nothing with a value that isn't a block
+        assert_eq!(
+            warnings("1~ exec~ pop~ pop~"),
+            vec!["`pop~` needs 1 value, but the deque has 0"]
+        );
+        // a block that runs itself isn't followed forever
+        assert!(warnings("{dup~ exec~}~ dup~ exec~").is_empty());
+    }
+
+    #[test]
+    fn test_check_effects() {
+        // binary instructions push their result to the front, whichever side they are called on
+        assert_eq!(effect("+~"), "( | Num Num -- Num | )");
+        assert_eq!(effect("+!"), "( Num Num | -- Num | )");
+        assert_eq!(effect("dup~ *~"), "( | Num -- Num | )");
+        assert_eq!(effect("1~ 2.0~"), "( -- Int Float )");
+        assert_eq!(effect(""), "( -- )");
+        assert_eq!(effect("ll~ toStr~ 'a'~ swap~"), "( List -- Char List )");
+        // the front of the deque is written before a |
+        assert_eq!(effect("pop!"), "( Any | -- | )");
+        assert_eq!(effect("rot~"), "( | Any -- Any | )");
+        // a block pops its inputs before any values on the other side
+        assert_eq!(effect("1~ 2! <~"), "( | Any -- Int Bool | )");
+        // branches that leave different numbers of values have no effect
+        assert_eq!(effect("{1~}~ {2.0~}~ {true~}~ ite~"), "( -- Num )");
+        assert_eq!(effect("{1~}~ {}~ {true~}~ ite~"), "?");
+        assert_eq!(effect("clear~"), "?");
+        assert_eq!(effect("exec~"), "?");
+        assert_eq!(effect("{2~ *~}~ exec~"), "( | Num -- Num | )");
+        // so do the results of the list functions
+        assert_eq!(effect("{1~ +~}~ swap~ map~"), "( | List -- List | )");
+        assert_eq!(effect("{true~}~ swap~ filter~"), "( | List -- List | )");
+        assert_eq!(effect("5~ {+!}~ 0~ [1, 2]~ reduce~"), "( | -- Any | Int )");
+        assert_eq!(effect("3~ lb~"), "( Any Any Any -- List )");
+        assert_eq!(effect("[1, 'a']~ ld~"), "( -- Int Char )");
+    }
+
+    #[test]
+    fn test_check_block_effects() {
+        let report = check_code(&parse("{+~}~ {{1~}~ exec~ pop~}~ ol~"));
+        let effects: Vec<(Vec<usize>, Option<String>)> = report
+            .effects
+            .iter()
+            .map(|block| {
+                (
+                    block.path.clone(),
+                    block.effect.as_ref().map(|e| e.to_string()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            effects,
+            vec![
+                (vec![0], Some("( | Num Num -- Num | )".to_string())),
+                (vec![1], Some("( -- )".to_string())),
+                (vec![1, 0], Some("( -- Int )".to_string())),
+            ]
+        );
+        // warnings have the path of the exec they are about
+        let report = check_code(&parse("1~ {'a'~ +~}~ exec~"));
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].path, vec![1, 1]);
+    }
+
+    #[test]
+    fn test_check_unused() {
+        let report = check_code(&parse("1~ pop~ 2~ 3~ ol~ {4~ 5~ clear~}~ exec~"));
+        let unused: Vec<(Vec<usize>, Option<Vec<usize>>)> = report
+            .unused
+            .into_iter()
+            .map(|unused| (unused.path, unused.dropped_by))
+            .collect();
+        assert_eq!(
+            unused,
+            vec![
+                (vec![0], Some(vec![1])),
+                (vec![2], Some(vec![5, 2])),
+                (vec![5, 0], Some(vec![5, 2])),
+                (vec![5, 1], Some(vec![5, 2])),
+            ]
+        );
+        // nothing is left over when the end of the program can't be followed
+        assert!(check_code(&parse("1~ {}~ loop~")).unused.is_empty());
+    }
+
+    #[test]
+    fn test_check_annotations() {
+        assert!(warnings("{( a b -- c ) +~}~").is_empty());
+        // the values a block leaves are counted, whichever side they are on
+        assert!(warnings("{( | a b -- c | ) +~}~").is_empty());
+        assert_eq!(
+            warnings("{( a -- b ) +~}~"),
+            vec!["block is annotated ( a -- b ), but its effect is ( | Num Num -- Num | )"]
+        );
+        assert_eq!(
+            warnings("{( a b -- c d ) +~}~"),
+            vec!["block is annotated ( a b -- c d ), but its effect is ( | Num Num -- Num | )"]
+        );
+        // the annotation is used where the effect can't be worked out
+        assert_eq!(effect("{( a -- b ) {}~ loop~}~ exec~"), "( Any -- Any )");
+        assert_eq!(
+            warnings("{( a -- b ) {}~ loop~}~ 1~ swap~ exec~ +~"),
+            vec!["`+~` needs 2 values, but the deque has 1"]
+        );
+        // and its names are read as types when they are types
+        assert_eq!(
+            effect("{( a -- Int ) {}~ loop~}~ exec~ 1~ +~"),
+            "( | Any -- Int | )"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use crate::debugger::debug::*;
+    use crate::evaluator::eval::Interpreter;
+    use crate::evaluator::eval_error::EvalError;
+    use crate::evaluator::eval_io::SharedBuffer;
+
+    fn start(code: &str) -> Debugger {
+        let output = SharedBuffer::new();
+        Debugger::new(code, Interpreter::new().with_output(Box::new(output))).unwrap()
+    }
+
+    // the first line of what a command prints, which says where the program stopped
+    fn stop(debugger: &mut Debugger, command: &str) -> String {
+        debugger
+            .command(command)
+            .lines()
+            .next()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_debug_step() {
+        let mut debugger = start("1~ {2~\n  3~}~ exec~\n4~");
+        assert_eq!(
+            debugger.show(),
+            "line 1, column 1: 1~\n1 | 1~ {2~\n  | ^^\ndeque: []"
+        );
+        assert_eq!(stop(&mut debugger, "s"), "line 1, column 4: {2~");
+        assert_eq!(stop(&mut debugger, "s"), "line 2, column 8: exec~");
+        assert_eq!(stop(&mut debugger, "step"), "line 1, column 5: 2~");
+        assert_eq!(stop(&mut debugger, "s"), "line 2, column 3: 3~");
+        assert_eq!(stop(&mut debugger, "s"), "line 3, column 1: 4~");
+        assert_eq!(
+            debugger.command("s"),
+            "the program finished\ndeque: [1, 2, 3, 4]"
+        );
+        assert_eq!(debugger.command("s"), "the program has finished");
+        assert_eq!(debugger.outcome(), Some(&Ok(())));
+
+        // next runs a block without stopping in it
+        let mut debugger = start("{1~ 2~}~ exec~ 3~");
+        debugger.command("s");
+        assert_eq!(stop(&mut debugger, "next"), "line 1, column 16: 3~");
+        assert!(debugger.command("p").contains("deque: [1, 2]"));
+    }
+
+    #[test]
+    fn test_debug_breakpoints() {
+        let mut debugger = start("1~\n{ol~}~ 1~ 3~ 1~ range~\n2~ ol~");
+        assert_eq!(debugger.command("b 3"), "breakpoint 1: line 3");
+        assert_eq!(debugger.command("b ol~"), "breakpoint 2: instruction ol");
+        assert_eq!(stop(&mut debugger, "c"), "line 2, column 2: ol~");
+        assert!(debugger.command("p").contains("deque: [1, 1]"));
+        assert_eq!(stop(&mut debugger, "c"), "line 2, column 2: ol~");
+        assert_eq!(
+            debugger.command("d 2"),
+            "deleted the breakpoint on instruction ol"
+        );
+        assert_eq!(stop(&mut debugger, "continue"), "line 3, column 1: 2~");
+        assert_eq!(stop(&mut debugger, "c"), "line 3, column 4: ol~");
+        assert_eq!(stop(&mut debugger, "c"), "the program finished");
+        assert_eq!(debugger.command("d 5"), "no breakpoint 5");
+        assert_eq!(
+            debugger.command("b"),
+            "break needs a line number or an instruction"
+        );
+    }
+
+    #[test]
+    fn test_debug_watches() {
+        let mut debugger = start("1~ 2~ 3~");
+        assert_eq!(debugger.command("w len~"), "watch 1: len~ = 0");
+        assert_eq!(
+            debugger.command("watch dup~"),
+            "watch 2: dup~ = error: tried to dup empty deque"
+        );
+        assert!(debugger.command("w 1~ {").starts_with("error: "));
+        debugger.command("s");
+        let shown = debugger.command("s");
+        assert!(
+            shown.ends_with("watch 1: len~ = 2\nwatch 2: dup~ = 2"),
+            "{}",
+            shown
+        );
+        // watches run on a copy, so the program's deque isn't changed
+        assert!(shown.contains("deque: [1, 2]"));
+        // and they can't print
+        assert!(debugger.command("w 1~ ol~").contains("= error: "));
+        assert_eq!(debugger.command("unwatch 1"), "deleted the watch on len~");
+        assert_eq!(debugger.command("i"), "watch 1: dup~\nwatch 2: 1~ ol~");
+    }
+
+    #[test]
+    fn test_debug_errors() {
+        let mut debugger = start("1~ pop~ pop~ 2~");
+        debugger.command("c");
+        assert!(debugger.command("p").starts_with("the program failed: "));
+        assert!(matches!(debugger.outcome(), Some(Err(EvalError::Instr(_)))));
+
+        let mut debugger = start("3~ exit~");
+        assert_eq!(stop(&mut debugger, "c"), "the program exited with status 3");
+        assert_eq!(debugger.outcome(), Some(&Err(EvalError::Exit(3))));
+
+        assert_eq!(start("").outcome(), Some(&Ok(())));
+        assert!(Debugger::new("1~ {", Interpreter::new()).is_err());
+        assert_eq!(
+            start("1~").command("frob"),
+            "unknown command: frob, type help for the commands"
+        );
+    }
+}
@@ -1,11 +1,24 @@
 #[cfg(test)]
+// the original tests keep their form; new tests are written clippy-clean
+#[allow(clippy::useless_vec)]
 mod tests {
     use std::collections::VecDeque;
 
     use crate::evaluator::eval::*;
-    use crate::evaluator::eval_value::Value;
+    use crate::evaluator::eval_convert::*;
+    use crate::evaluator::eval_docs::*;
+    use crate::evaluator::eval_error::{EvalError, EvalResult};
+    use crate::evaluator::eval_io::SharedBuffer;
+    use crate::evaluator::eval_trace::{Observer, TraceEvent};
+    use crate::evaluator::eval_value::{Deque, Value};
     use crate::lexer::lex::*;
     use crate::parser::par::*;
+    use crate::parser::par_ast::{Exec, Op, StackEffect};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::rc::Rc;
+    use std::time::Duration;
     #[test]
     fn test_eval_literals() -> Result<(), String> {
         let input_str = String::from("[1.2, 'a', [true, 3], -4]~ {ol~}~");
@@ -284,8 +297,1185 @@ mod tests {
         Ok(())
     }
 
+    fn str_value(s: &str) -> Value {
+        Value::List(s.chars().map(Value::Char).collect())
+    }
+
+    #[test]
+    fn test_eval_type() -> Result<(), String> {
+        let inputs = [
+            String::from("1~ type~"),
+            String::from("1.5~ type~"),
+            String::from("true~ type~"),
+            String::from("'a'~ type~"),
+            String::from("[1, 2]~ type~"),
+            String::from("\"hi\"~ type~"),
+            String::from("{1~}~ type~"),
+        ];
+
+        let expected = ["Int", "Float", "Bool", "Char", "List", "List", "Block"];
+
+        for (input, expected) in inputs.iter().zip(expected.iter()) {
+            let tokens = tokenize_code(input);
+            let ast = parse_tokens(&mut tokens.into_iter())?;
+            let deque = run_ast(Option::None, ast)?;
+            assert_eq!(deque, VecDeque::from(vec![str_value(expected)]));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_type_predicates() -> Result<(), String> {
+        let inputs = [
+            String::from("1~ isInt~ 1~ isFloat~"),
+            String::from("1.5~ isFloat~ 'a'~ isInt~"),
+            String::from("true~ isBool~ 0~ isBool~"),
+            String::from("[1]~ isList~ {1~}~ isList~"),
+            String::from("{1~}~ isBlock~ []~ isBlock~"),
+            String::from("\"hi\"~ isStr~ [1, 'a']~ isStr~"),
+            String::from("[]~ isStr~ 'a'~ isStr~"),
+        ];
+
+        for input in inputs.iter() {
+            let tokens = tokenize_code(input);
+            let ast = parse_tokens(&mut tokens.into_iter())?;
+            let deque = run_ast(Option::None, ast)?;
+            assert_eq!(
+                deque,
+                VecDeque::from(vec![Value::Bool(true), Value::Bool(false)]),
+                "{}",
+                input
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_to_str() -> Result<(), String> {
+        let inputs = [
+            String::from("-12~ toStr~"),
+            String::from("'a'~ toStr~"),
+            String::from("\"hi\"~ toStr~"),
+            String::from("true~ toStr~"),
+        ];
+
+        let expected = ["-12", "'a'", "\"hi\"", "true"];
+
+        for (input, expected) in inputs.iter().zip(expected.iter()) {
+            let tokens = tokenize_code(input);
+            let ast = parse_tokens(&mut tokens.into_iter())?;
+            let deque = run_ast(Option::None, ast)?;
+            assert_eq!(deque, VecDeque::from(vec![str_value(expected)]));
+        }
+
+        // toStr output can be concatenated into a message
+        let tokens = tokenize_code(&String::from("5~ toStr~ \"n = \"~ l+~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let deque = run_ast(Option::None, ast)?;
+        assert_eq!(deque, VecDeque::from(vec![str_value("n = 5")]));
+        Ok(())
+    }
+
+    // a block that counts n down to 0 by executing a copy of itself
+    // the recursive call is the last exec of the true branch, which is the last exec of the block
+    const COUNTDOWN: &str =
+        "{swap! 1! swap! -! swap! {}! {swap! dup! exec!}! {swap! dup! 0! swap! >!}! ite!}";
+
+    #[test]
+    fn test_eval_deep_tail_recursion() -> Result<(), String> {
+        let input_str = format!("100000! {}! dup! exec!", COUNTDOWN);
+        let tokens = tokenize_code(&input_str);
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let deque = run_ast(Option::None, ast)?;
+        assert_eq!(deque.len(), 2);
+        assert_eq!(deque[0], Value::Int(0));
+        assert!(matches!(deque[1], Value::Block(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_deep_recursion() -> Result<(), String> {
+        // the recursive call is followed by another exec, so every level keeps a frame
+        let input_str = String::from(
+            "100000! {swap! 1! swap! -! swap! {}! {swap! dup! exec! 0! pop!}! {swap! dup! 0! swap! >!}! ite!}! dup! exec!",
+        );
+        let tokens = tokenize_code(&input_str);
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let deque = run_ast(Option::None, ast)?;
+        assert_eq!(deque.len(), 2);
+        assert_eq!(deque[0], Value::Int(0));
+        Ok(())
+    }
+
+    fn run_limited(input: &str, limits: Limits) -> Result<(), EvalError> {
+        let tokens = tokenize_code(&String::from(input));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        Interpreter::new().with_limits(limits).run(ast)
+    }
+
+    #[test]
+    fn test_eval_limits() -> Result<(), String> {
+        let limits = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run_limited("{1~ pop~}~ loop~", limits),
+            Err(EvalError::StepLimit(1000))
+        );
+
+        let limits = Limits {
+            max_deque_len: Some(100),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run_limited("{1~}~ loop~", limits),
+            Err(EvalError::DequeLimit(100))
+        );
+
+        let limits = Limits {
+            max_total_size: Some(100),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run_limited("[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]~ {dup~}~ loop~", limits),
+            Err(EvalError::SizeLimit(100))
+        );
+
+        let limits = Limits {
+            max_depth: Some(100),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run_limited("{dup~ exec~ 1~}~ dup~ exec~", limits),
+            Err(EvalError::DepthLimit(100))
+        );
+
+        let limits = Limits {
+            timeout: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run_limited("{}~ loop~", limits),
+            Err(EvalError::Timeout(Duration::from_millis(50)))
+        );
+
+        // limits also apply inside the list functions
+        let limits = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run_limited("{{}~ loop~}~ [1]~ map~", limits),
+            Err(EvalError::StepLimit(1000))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_limit_counts() -> Result<(), String> {
+        // three blocks nest here, but the list functions put twice as many frames on the stack
+        let code = "{{1~}~ [1]~ map~ pop~}~ [1]~ map~ pop~";
+        let depth = |max| Limits {
+            max_depth: Some(max),
+            ..Limits::default()
+        };
+        assert_eq!(run_limited(code, depth(3)), Ok(()));
+        assert_eq!(run_limited(code, depth(2)), Err(EvalError::DepthLimit(2)));
+
+        // the total size follows values in and out of the deque, however they get there
+        let mut deque = Deque::from(VecDeque::from(vec![Value::Int(1)]));
+        deque.push_front(Value::List(vec![Value::Int(2), Value::Int(3)]));
+        deque.push_back(Value::List(vec![]));
+        assert_eq!(deque.total_size(), 5);
+        deque.rotate_left(1);
+        deque.pop_back();
+        assert_eq!(deque.total_size(), 2);
+        deque.modify(|values| values.push_back(Value::List(vec![Value::Int(4)])));
+        assert_eq!(deque.total_size(), 4);
+        deque.clear();
+        assert_eq!(deque.total_size(), 0);
+
+        let size = |max| Limits {
+            max_total_size: Some(max),
+            ..Limits::default()
+        };
+        let mut interp = Interpreter::new().with_limits(size(10)).with_host_fn(
+            "grow",
+            HostFn::new_raw(|deque, _place| {
+                deque.push_back(Value::List(vec![Value::Int(0); 10]));
+                Ok(())
+            }),
+        );
+        assert_eq!(
+            interp.run(parse_source("grow~")?),
+            Err(EvalError::SizeLimit(10))
+        );
+        // a list function swaps deques, and the one it puts back keeps its size
+        let mut interp = Interpreter::new().with_limits(size(20));
+        interp.run(parse_source("[1, 2, 3]~ {1~ +~}~ [1, 2, 3]~ map~")?)?;
+        assert_eq!(interp.deque.total_size(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_within_limits() -> Result<(), String> {
+        let limits = Limits {
+            max_steps: Some(1000),
+            max_deque_len: Some(10),
+            max_total_size: Some(10),
+            max_depth: Some(10),
+            timeout: Some(Duration::from_secs(10)),
+        };
+        let tokens = tokenize_code(&String::from("5~ 1~+~1~{*~}~1~rot!2~range~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let mut interp = Interpreter::new().with_limits(limits);
+        interp.run(ast)?;
+        assert_eq!(interp.deque, VecDeque::from(vec![Value::Int(120)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_capabilities() -> Result<(), String> {
+        let tokens = tokenize_code(&String::from("1~ ol~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let mut interp = Interpreter::new().with_capabilities(Capabilities::none());
+        assert_eq!(
+            interp.run(ast),
+            Err(EvalError::Permission(
+                String::from("ol"),
+                Capability::Stdout
+            ))
+        );
+        // the instruction was refused before it popped anything
+        assert_eq!(interp.deque, VecDeque::from(vec![Value::Int(1)]));
+
+        let tokens = tokenize_code(&String::from("il~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let capabilities = Capabilities {
+            stdin: false,
+            ..Capabilities::all()
+        };
+        let mut interp = Interpreter::new().with_capabilities(capabilities);
+        assert_eq!(
+            interp.run(ast),
+            Err(EvalError::Permission(String::from("il"), Capability::Stdin))
+        );
+
+        // instructions without side effects need no capabilities
+        let tokens = tokenize_code(&String::from("1~ 2~ +~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let mut interp = Interpreter::new().with_capabilities(Capabilities::none());
+        interp.run(ast)?;
+        assert_eq!(interp.deque, VecDeque::from(vec![Value::Int(3)]));
+        Ok(())
+    }
+
+    fn run_with_files(input: &str) -> Result<VecDeque<Value>, EvalError> {
+        let tokens = tokenize_code(&String::from(input));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let capabilities = Capabilities {
+            files: true,
+            ..Capabilities::default()
+        };
+        let mut interp = Interpreter::new().with_capabilities(capabilities);
+        interp.run(ast)?;
+        Ok(interp.deque.into())
+    }
+
+    #[test]
+    fn test_eval_files() -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("rusty_deque_files_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join("out.txt").to_string_lossy().into_owned();
+        let dir_path = dir.to_string_lossy().into_owned();
+
+        let code = format!(
+            "\"a\"~ \"{0}\"~ fw~ \"b\"~ \"{0}\"~ fa~ \"{0}\"~ fr~ \"{0}\"~ frl~ \"{1}\"~ fls~",
+            path, dir_path
+        );
+        assert_eq!(
+            run_with_files(&code)?,
+            VecDeque::from(vec![
+                str_value("ab"),
+                Value::List(vec![str_value("ab")]),
+                Value::List(vec![str_value("out.txt")]),
+            ])
+        );
+
+        let code = format!("\"{0}\"~ fe~ \"{0}\"~ frm~ \"{0}\"~ fe~", path);
+        assert_eq!(
+            run_with_files(&code)?,
+            VecDeque::from(vec![Value::Bool(true), Value::Bool(false)])
+        );
+
+        let code = format!("[0, 255]~ \"{0}\"~ fwb~ \"{0}\"~ frb~ \"{0}\"~ frm~", path);
+        assert_eq!(
+            run_with_files(&code)?,
+            VecDeque::from(vec![Value::List(vec![Value::Int(0), Value::Int(255)])])
+        );
+
+        // missing files are an error, not a panic
+        let code = format!("\"{0}\"~ fr~", path);
+        assert_eq!(
+            run_with_files(&code),
+            Err(EvalError::Io(
+                String::from("fr"),
+                path.clone(),
+                std::io::ErrorKind::NotFound
+            ))
+        );
+
+        // files are off by default
+        let tokens = tokenize_code(&format!("\"{0}\"~ fe~", path));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        assert_eq!(
+            Interpreter::new().run(ast),
+            Err(EvalError::Permission(String::from("fe"), Capability::Files))
+        );
+
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_process() -> Result<(), String> {
+        let tokens = tokenize_code(&String::from("args~ 7~ exit~ 1~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let mut interp = Interpreter::new().with_args(vec![String::from("a"), String::from("bc")]);
+        // exit stops the code with its status instead of running the rest
+        assert_eq!(interp.run(ast), Err(EvalError::Exit(7)));
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![Value::List(vec![str_value("a"), str_value("bc")])])
+        );
+
+        std::env::set_var("RUSTY_DEQUE_TEST_ENV", "set");
+        let tokens = tokenize_code(&String::from(
+            "\"RUSTY_DEQUE_TEST_ENV\"~ env~ \"RUSTY_DEQUE_TEST_UNSET\"~ env~",
+        ));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let mut interp = Interpreter::new().with_capabilities(Capabilities::all());
+        interp.run(ast)?;
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![str_value("set"), Value::Bool(false)])
+        );
+
+        let tokens = tokenize_code(&String::from("envl~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        assert_eq!(
+            Interpreter::new().run(ast),
+            Err(EvalError::Permission(String::from("envl"), Capability::Env))
+        );
+        Ok(())
+    }
+
+    fn run_with_io(input: &str, stdin: &str) -> Result<(Interpreter, String), String> {
+        let tokens = tokenize_code(&String::from(input));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let output = SharedBuffer::new();
+        let mut interp = Interpreter::new()
+            .with_input(Box::new(Cursor::new(stdin.to_string())))
+            .with_output(Box::new(output.clone()));
+        interp.run(ast)?;
+        Ok((interp, output.text()))
+    }
+
+    #[test]
+    fn test_eval_output() -> Result<(), String> {
+        let (_, output) = run_with_io("\"Hello, World!\"~ ol~", "")?;
+        assert_eq!(output, "Hello, World!\n");
+
+        let (_, output) = run_with_io("1~ ow~ \" \"~ ow~ [1, 'a']~ ow~ 'c'~ ol~", "")?;
+        assert_eq!(output, "1 [1, 'a', ]'c'\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_input() -> Result<(), String> {
+        let (interp, output) = run_with_io("il~ il~ ol~", "first\nsecond\n")?;
+        assert_eq!(interp.deque, VecDeque::from(vec![str_value("first")]));
+        assert_eq!(output, "second\n");
+
+        let (interp, _) = run_with_io("il~ ia~", "first\nsecond\nthird\n")?;
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![str_value("first"), str_value("second\nthird")])
+        );
+
+        // only the line ending is stripped, and the end of the input pushes false
+        let (interp, _) = run_with_io("il~ il~ eof~ il~ eof~", "  a b \r\n\t\n")?;
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![
+                str_value("  a b "),
+                str_value("\t"),
+                Value::Bool(true),
+                Value::Bool(false),
+                Value::Bool(true),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_lines() -> Result<(), String> {
+        let (interp, output) = run_with_io("0~ {ow~ \"|\"~ ow~ 1~ +~}~ lines~", "a\n\n b")?;
+        assert_eq!(output, "a|| b|");
+        assert_eq!(interp.deque, VecDeque::from(vec![Value::Int(3)]));
+
+        // nothing runs for empty input
+        let (interp, output) = run_with_io("{ol~}~ lines~", "")?;
+        assert_eq!(output, "");
+        assert!(interp.deque.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_instr_docs() -> Result<(), String> {
+        // every documented instruction is one the interpreter knows
+        for doc in INSTR_DOCS.iter() {
+            let ast = vec![Exec::Right(Op::Instruction(doc.name.to_string()))];
+            let mut interp = Interpreter::new()
+                .with_capabilities(Capabilities::all())
+                .with_input(Box::new(Cursor::new(Vec::new())))
+                .with_output(Box::new(SharedBuffer::new()));
+            if let Err(EvalError::Instr(e)) = interp.run(ast) {
+                assert!(!e.contains("Unknown instruction"), "{}", e);
+            }
+        }
+        assert!(instr_doc("dup").is_some());
+        assert!(instr_doc("frob").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_error_output() -> Result<(), String> {
+        let tokens = tokenize_code(&String::from("\"out\"~ ol~ \"err\"~ el~ 1~ ew~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let output = SharedBuffer::new();
+        let error = SharedBuffer::new();
+        let mut interp = Interpreter::new()
+            .with_output(Box::new(output.clone()))
+            .with_error(Box::new(error.clone()));
+        interp.run(ast)?;
+        assert_eq!(output.text(), "out\n");
+        assert_eq!(error.text(), "err\n1");
+
+        let tokens = tokenize_code(&String::from("1~ el~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let capabilities = Capabilities {
+            stderr: false,
+            ..Capabilities::default()
+        };
+        let mut interp = Interpreter::new().with_capabilities(capabilities);
+        assert_eq!(
+            interp.run(ast),
+            Err(EvalError::Permission(
+                String::from("el"),
+                Capability::Stderr
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_format() -> Result<(), String> {
+        // arguments are popped after the format string, so the first one is pushed last
+        let (_, output) = run_with_io(
+            "'!'~ 2.5~ 2.5~ 3~ \"ab\"~ \"%s %d %f %.2f%c 100%%\\n\"~ printf~",
+            "",
+        )?;
+        assert_eq!(output, "ab 3 2.5 2.50! 100%\n");
+
+        let (interp, _) = run_with_io("1~ \"<%d>\"~ format~", "")?;
+        assert_eq!(interp.deque, VecDeque::from(vec![str_value("<1>")]));
+
+        assert_eq!(
+            run_with_io("'a'~ \"%d\"~ format~", "").err(),
+            Some(String::from("format: %d expected Int, got Char"))
+        );
+        assert_eq!(
+            run_with_io("1~ \"%x\"~ printf~", "").err(),
+            Some(String::from("printf: unknown directive %x"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_debug_output() -> Result<(), String> {
+        let (_, output) = run_with_io(
+            "\"a\\\"b\\n\"~ od~ '\\''~ od~ [1, \"x\"]~ od~ []~ od~ \"a\\\"b\\n\"~ ol~",
+            "",
+        )?;
+        assert_eq!(output, "\"a\\\"b\\n\"\n'\\''\n[1, \"x\"]\n[]\na\"b\n\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_list_functions_push_front() -> Result<(), String> {
+        // like the other instructions of two or three values, the result goes to the front
+        let inputs = [
+            "5~ {1! +!}~ [1, 2]~ map~",
+            "5~ {2! <!}~ [1, 2, 3]~ filter~",
+            "5~ {+!}~ 0~ [1, 2]~ reduce~",
+        ];
+        let expected = vec![
+            vec![
+                Value::List(vec![Value::Int(2), Value::Int(3)]),
+                Value::Int(5),
+            ],
+            vec![Value::List(vec![Value::Int(3)]), Value::Int(5)],
+            vec![Value::Int(3), Value::Int(5)],
+        ];
+        for (input, expected) in inputs.iter().zip(expected) {
+            let deque = run_ast(Option::None, parse_source(input)?)?;
+            assert_eq!(deque, VecDeque::from(expected), "{}", input);
+        }
+        Ok(())
+    }
+
+    fn bytes_value(bytes: &[u8]) -> Value {
+        Value::List(bytes.iter().map(|b| Value::Int(*b as i64)).collect())
+    }
+
+    #[test]
+    fn test_eval_bytes() -> Result<(), String> {
+        let tokens = tokenize_code(&String::from("2~ ib~ iab~ [104, 105]~ ob~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let output = SharedBuffer::new();
+        let mut interp = Interpreter::new()
+            .with_input(Box::new(Cursor::new(vec![0xff, b' ', b'\n'])))
+            .with_output(Box::new(output.clone()));
+        interp.run(ast)?;
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![bytes_value(&[0xff, b' ']), bytes_value(b"\n")])
+        );
+        assert_eq!(output.contents(), b"hi".to_vec());
+
+        let (interp, _) = run_with_io(
+            "\"hi\"~ \"utf8\"~ enc~ [255]~ \"latin1\"~ dec~ \"00ff\"~ \"hex\"~ enc~ [1, 171]~ \"hex\"~ dec~",
+            "",
+        )?;
+        assert_eq!(
+            interp.deque,
+            // binary results go to the front
+            VecDeque::from(vec![
+                str_value("01ab"),
+                bytes_value(&[0, 255]),
+                str_value("\u{ff}"),
+                bytes_value(b"hi"),
+            ])
+        );
+
+        assert_eq!(
+            run_with_io("[256]~ ob~", "").err(),
+            Some(String::from("ob: expected byte, got 256"))
+        );
+        assert_eq!(
+            run_with_io("[255]~ \"utf8\"~ dec~", "").err(),
+            Some(String::from("dec: invalid utf8"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_binary_result_side() -> Result<(), String> {
+        // binary results are pushed onto the front, whichever side the instruction was called on
+        let tokens = tokenize_code(&String::from("0~ 1~ 2~ +~ 3! 4! +!"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let deque = run_ast(Option::None, ast)?;
+        assert_eq!(
+            deque,
+            VecDeque::from(vec![Value::Int(7), Value::Int(3), Value::Int(0)])
+        );
+        // unary results go back to the side they came from
+        let tokens = tokenize_code(&String::from("1~ 2~ 0~ toBool~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        let deque = run_ast(Option::None, ast)?;
+        assert_eq!(deque.back(), Some(&Value::Bool(false)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_host_fns() -> Result<(), String> {
+        let mut interp = Interpreter::new()
+            .with_host_fn("answer", HostFn::new_nilary(|| Ok(Value::Int(42))))
+            .with_host_fn(
+                "double",
+                HostFn::new_unary(|v| match v {
+                    Value::Int(i) => Ok(Value::Int(i * 2)),
+                    _ => Err("double: expected int".to_string()),
+                }),
+            )
+            .with_host_fn(
+                "pair",
+                HostFn::new_binary(|a, b| Ok(Value::List(vec![a, b]))),
+            )
+            .with_host_fn(
+                "rev",
+                HostFn::new_raw(|deque, _place| {
+                    deque.make_contiguous().reverse();
+                    Ok(())
+                }),
+            );
+
+        let tokens = tokenize_code(&String::from("0~ answer~ double~ 1! 2! pair! rev~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        interp.run(ast)?;
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![
+                Value::Int(84),
+                Value::Int(0),
+                Value::List(vec![Value::Int(2), Value::Int(1)]),
+            ])
+        );
+
+        // a binary host function called on the back still pushes to the front
+        let tokens = tokenize_code(&String::from("1~ 2~ pair~ 3~ answer~"));
+        interp.deque.clear();
+        interp.run(parse_tokens(&mut tokens.into_iter())?)?;
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![
+                Value::List(vec![Value::Int(2), Value::Int(1)]),
+                Value::Int(3),
+                Value::Int(42),
+            ])
+        );
+
+        // errors from host functions stop the interpreter like builtin errors
+        let tokens = tokenize_code(&String::from("'a'~ double~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        assert_eq!(
+            interp.run(ast),
+            Err(EvalError::Instr(String::from("double: expected int")))
+        );
+
+        // host functions shadow builtins
+        interp.register("+", HostFn::new_binary(|_, _| Ok(Value::Int(0))));
+        let tokens = tokenize_code(&String::from("1~ 2~ +~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        interp.deque.clear();
+        interp.run(ast)?;
+        assert_eq!(interp.deque, VecDeque::from(vec![Value::Int(0)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_conversions() {
+        assert_eq!(5i64.into_value(), Value::Int(5));
+        assert_eq!("ab".into_value(), String::from("ab").into_value());
+        assert_eq!(
+            vec![Some('a'), None].into_value(),
+            Value::List(vec![
+                Value::List(vec![Value::Char('a')]),
+                Value::List(vec![]),
+            ])
+        );
+        assert_eq!(
+            (1i64, true).into_value(),
+            Value::List(vec![Value::Int(1), Value::Bool(true)])
+        );
+        assert_eq!(().into_value(), Value::None);
+
+        assert_eq!(f64::from_value(Value::Int(2)), Ok(2.0));
+        assert_eq!(
+            String::from_value("hi".into_value()),
+            Ok(String::from("hi"))
+        );
+        assert_eq!(
+            <(i64, char)>::from_value(Value::List(vec![Value::Int(1), Value::Char('x')])),
+            Ok((1, 'x'))
+        );
+        let map: HashMap<String, i64> = vec![(String::from("a"), 1)].into_iter().collect();
+        assert_eq!(HashMap::from_value(map.clone().into_value()), Ok(map));
+
+        assert_eq!(
+            i64::from_value(Value::Bool(true)),
+            Err(String::from("expected Int, got Bool"))
+        );
+        assert!(String::from_value(Value::List(vec![Value::Int(1)])).is_err());
+        assert!(
+            Option::<i64>::from_value(Value::List(vec![Value::Int(1), Value::Int(2)])).is_err()
+        );
+    }
+
+    #[test]
+    fn test_eval_typed_host_fns() -> Result<(), String> {
+        let mut interp = Interpreter::new()
+            .with_host_fn(
+                "repeat",
+                HostFn::typed_binary(|s: String, n: i64| Ok(s.repeat(n as usize))),
+            )
+            .with_host_fn(
+                "halve",
+                HostFn::typed_unary(|i: i64| Ok(Some(i / 2).filter(|_| i % 2 == 0))),
+            );
+
+        let tokens = tokenize_code(&String::from("2~ \"ab\"~ repeat~ 4~ halve~ 3~ halve~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        interp.run(ast)?;
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![
+                String::from("abab").into_value(),
+                Value::List(vec![Value::Int(2)]),
+                Value::List(vec![]),
+            ])
+        );
+
+        // arguments of the wrong type are an error
+        let tokens = tokenize_code(&String::from("1~ 2~ repeat~"));
+        let ast = parse_tokens(&mut tokens.into_iter())?;
+        assert_eq!(
+            interp.run(ast),
+            Err(EvalError::Instr(String::from("expected List, got Int")))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_effect_checks() -> Result<(), String> {
+        let run = |code: &str, check: bool| {
+            let tokens = tokenize_code(&String::from(code));
+            let ast = parse_tokens(&mut tokens.into_iter()).unwrap();
+            let mut interp = Interpreter::new().with_effect_checks(check);
+            interp.run(ast).map(|_| VecDeque::from(interp.deque))
+        };
+        let effect = |text: &str| StackEffect::parse(text).unwrap();
+
+        assert_eq!(
+            run("2~ 3~ {( a b -- c ) *~}~ exec~", true),
+            Ok(VecDeque::from(vec![Value::Int(6)]))
+        );
+        // values below the block's inputs are left alone
+        assert!(run("1~ 2~ 3~ {( a b -- c ) *~}~ exec~", true).is_ok());
+        assert_eq!(
+            run("2~ {( a b -- c ) *~}~ exec~", true),
+            Err(EvalError::Effect(
+                effect("( a b -- c )"),
+                String::from("the block needs 2 values, but the deque has 1")
+            ))
+        );
+        assert_eq!(
+            run("2~ {( a -- b ) dup~}~ exec~", true),
+            Err(EvalError::Effect(
+                effect("( a -- b )"),
+                String::from("the deque should have 1 values after the block, but it has 2")
+            ))
+        );
+        // blocks run by other instructions are checked too
+        assert!(run("{( n -- m ) pop~}~ [1, 2]~ map~", true).is_err());
+        // annotations are only checked when asked to
+        assert!(run("2~ {( a -- b ) dup~}~ exec~", false).is_ok());
+
+        // an annotated block that calls itself in tail position doesn't grow the stack
+        let ast = parse_source("{( b -- ) dup~ exec~}~ dup~ exec~")?;
+        let mut interp = Interpreter::new()
+            .with_effect_checks(true)
+            .with_limits(Limits {
+                max_steps: Some(10_000),
+                ..Limits::default()
+            });
+        assert_eq!(interp.run(ast), Err(EvalError::StepLimit(10_000)));
+        assert!(interp.frames().len() < 10);
+        // and a tail call whose effect disagrees with its caller's is still caught
+        assert!(run("1~ {( a -- ) {( -- b ) 2~}~ exec~}~ exec~", true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_step() -> Result<(), String> {
+        let start = |code: &str| {
+            let tokens = tokenize_code(&String::from(code));
+            let mut interp = Interpreter::new();
+            interp.start(parse_tokens(&mut tokens.into_iter()).unwrap());
+            interp
+        };
+        // the path of the next exec, and how many blocks are running
+        let next = |interp: &mut Interpreter| {
+            let path = interp.next_exec().unwrap().map(|(_, path)| path);
+            (path, interp.depth())
+        };
+
+        let mut interp = start("1~ {2~ 3~}~ exec~ 4~");
+        assert_eq!(next(&mut interp), (Some(vec![0]), 1));
+        interp.step()?;
+        interp.step()?;
+        assert_eq!(next(&mut interp), (Some(vec![2]), 1));
+        // stepping into a block stops before its first exec
+        interp.step()?;
+        assert_eq!(next(&mut interp), (Some(vec![1, 0]), 2));
+        interp.step()?;
+        interp.step()?;
+        assert_eq!(next(&mut interp), (Some(vec![3]), 1));
+        interp.step()?;
+        assert_eq!(next(&mut interp), (None, 0));
+        assert_eq!(
+            interp.deque,
+            VecDeque::from(vec![
+                Value::Int(1),
+                Value::Int(2),
+                Value::Int(3),
+                Value::Int(4)
+            ])
+        );
+
+        // stepping over runs the whole block, even when it is the last exec of its own block
+        let mut interp = start("{{1~}~ exec~}~ exec~ 2~");
+        interp.step()?;
+        interp.step()?;
+        interp.step()?;
+        assert_eq!(next(&mut interp), (Some(vec![0, 1]), 2));
+        interp.step_over()?;
+        assert_eq!(next(&mut interp), (Some(vec![2]), 1));
+
+        // each turn of a loop stops at the start of the body again
+        let mut interp = start("{ol~}~ 1~ 3~ 1~ range~ 0~");
+        for _ in 0..5 {
+            interp.step()?;
+        }
+        assert_eq!(next(&mut interp), (Some(vec![0, 0]), 2));
+        interp.step()?;
+        assert_eq!(next(&mut interp), (Some(vec![0, 0]), 2));
+        interp.step()?;
+        assert_eq!(next(&mut interp), (Some(vec![5]), 1));
+
+        let mut interp = start("1~ pop~ pop~");
+        interp.step()?;
+        interp.step()?;
+        assert!(interp.step().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_trace() -> Result<(), String> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        let mut interp = Interpreter::new()
+            .with_tracer(move |event: &TraceEvent| recorded.borrow_mut().push(event.clone()));
+        let tokens = tokenize_code(&String::from("1~ {2! +~}~ exec~ {pop~}~ exec~"));
+        interp.run(parse_tokens(&mut tokens.into_iter())?)?;
+
+        let events = events.borrow();
+        let execs: Vec<(String, Vec<usize>, usize)> = events
+            .iter()
+            .map(|event| (event.exec_text(), event.path.clone(), event.depth))
+            .collect();
+        assert_eq!(
+            execs,
+            vec![
+                ("1~".to_string(), vec![0], 1),
+                ("{2! +~ }~".to_string(), vec![1], 1),
+                ("exec~".to_string(), vec![2], 1),
+                ("2!".to_string(), vec![1, 0], 2),
+                ("+~".to_string(), vec![1, 1], 2),
+                ("{pop~ }~".to_string(), vec![3], 1),
+                ("exec~".to_string(), vec![4], 1),
+                // a block run in tail position replaces the block that ran it
+                ("pop~".to_string(), vec![3, 0], 1),
+            ]
+        );
+        assert_eq!(
+            events[4].before,
+            VecDeque::from(vec![Value::Int(2), Value::Int(1)])
+        );
+        assert_eq!(events[4].after, VecDeque::from(vec![Value::Int(3)]));
+        assert_eq!(
+            events[4].to_json(),
+            serde_json::json!({
+                "step": 5,
+                "exec": "+~",
+                "instruction": "+",
+                "side": "right",
+                "depth": 2,
+                "path": [1, 1],
+                "before": ["2", "1"],
+                "after": ["3"],
+            })
+        );
+
+        // a failing exec is traced with its error
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        let mut interp = Interpreter::new()
+            .with_tracer(move |event: &TraceEvent| recorded.borrow_mut().push(event.to_json()));
+        let tokens = tokenize_code(&String::from("'a'! pop~ pop~"));
+        assert!(interp.run(parse_tokens(&mut tokens.into_iter())?).is_err());
+        let events = events.borrow();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["after"], serde_json::json!(["'a'"]));
+        assert_eq!(events[0]["side"], "left");
+        assert_eq!(events[2]["error"], "tried to pop empty deque");
+        Ok(())
+    }
+
+    // writes down what it is told, one line for each call
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl Observer for Log {
+        fn before_exec(
+            &mut self,
+            exec: &Exec,
+            path: &[usize],
+            depth: usize,
+            deque: &VecDeque<Value>,
+        ) {
+            self.0.push(format!(
+                "before {} {:?} {} {}",
+                exec,
+                path,
+                depth,
+                deque.len()
+            ));
+        }
+
+        fn after_exec(&mut self, event: &TraceEvent) {
+            self.0
+                .push(format!("after {} {}", event.exec_text(), event.after.len()));
+        }
+
+        fn on_error(&mut self, error: &EvalError) {
+            self.0.push(format!("error {}", error));
+        }
+
+        fn on_block_enter(&mut self, path: &[usize], depth: usize) {
+            self.0.push(format!("enter {:?} {}", path, depth));
+        }
+
+        fn on_block_exit(&mut self, path: &[usize], depth: usize) {
+            self.0.push(format!("exit {:?} {}", path, depth));
+        }
+
+        fn on_io(&mut self, instr: &str, capability: Capability) {
+            self.0.push(format!("io {} {:?}", instr, capability));
+        }
+    }
+
+    #[test]
+    fn test_eval_observer() -> Result<(), String> {
+        let run = |code: &str| {
+            let log = Rc::new(RefCell::new(Log::default()));
+            let mut interp = Interpreter::new()
+                .with_output(Box::new(SharedBuffer::new()))
+                .with_observer(log.clone());
+            let tokens = tokenize_code(&String::from(code));
+            let result = interp.run(parse_tokens(&mut tokens.into_iter()).unwrap());
+            let lines = log.borrow().0.clone();
+            (result, lines)
+        };
+
+        let (result, log) = run("{}~ exec~ {1~ ol~}~ exec~ 2~");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            log,
+            vec![
+                "enter [] 1",
+                "before {} [0] 1 0",
+                "after {}~ 1",
+                "before exec [1] 1 1",
+                "after exec~ 0",
+                "enter [0] 2",
+                "exit [0] 2",
+                "before {1~ ol~ } [2] 1 0",
+                "after {1~ ol~ }~ 1",
+                "before exec [3] 1 1",
+                "after exec~ 0",
+                "enter [2] 2",
+                "before 1 [2, 0] 2 0",
+                "after 1~ 1",
+                "before ol [2, 1] 2 1",
+                "io ol Stdout",
+                "after ol~ 0",
+                "exit [2] 2",
+                "before 2 [4] 1 0",
+                "after 2~ 1",
+                "exit [] 1",
+            ]
+        );
+
+        // a block run by map is run like any other, and an error in it is told once
+        let (result, log) = run("{pop~ pop~}~ [1]~ map~");
+        assert!(result.is_err());
+        let errors: Vec<&String> = log
+            .iter()
+            .filter(|line| line.starts_with("error"))
+            .collect();
+        assert_eq!(errors, vec!["error tried to pop empty deque"]);
+        assert!(!log.iter().any(|line| line.starts_with("exit [0]")));
+        assert_eq!(
+            log[log.len() - 8..],
+            [
+                "after map~ 0",
+                "exit [] 1",
+                "enter [0] 1",
+                "before pop [0, 0] 1 1",
+                "after pop~ 0",
+                "before pop [0, 1] 1 0",
+                "after pop~ 0",
+                "error tried to pop empty deque",
+            ]
+        );
+
+        // so is an error from a limit, which no exec fails with
+        let log = Rc::new(RefCell::new(Log::default()));
+        let mut interp = Interpreter::new()
+            .with_limits(Limits {
+                max_steps: Some(5),
+                ..Limits::default()
+            })
+            .with_observer(log.clone());
+        let tokens = tokenize_code(&String::from("{}~ loop~"));
+        assert!(interp.run(parse_tokens(&mut tokens.into_iter())?).is_err());
+        assert_eq!(
+            log.borrow().0.last().unwrap(),
+            "error step limit exceeded: ran more than 5 instructions"
+        );
+        Ok(())
+    }
+
+    // the same body, with each of the ways of running a block, followed by an exec
+    // so that none of them is in tail position
+    // lines runs on one line of input, and loop runs until it hits a limit
+    const RUNNERS: [(&str, &str); 9] = [
+        ("exec", "BODY~ exec~ 0~ pop~"),
+        ("ite", "{}~ BODY~ {true~}~ ite~ 0~ pop~"),
+        ("loop", "BODY~ loop~ 0~ pop~"),
+        ("range", "BODY~ 1~ 1~ 0~ range~ 0~ pop~"),
+        ("while", "false~ true~ BODY~ {}~ while~ 0~ pop~"),
+        ("lines", "BODY~ lines~ 0~ pop~"),
+        ("map", "BODY~ [1]~ map~ 0~ pop~"),
+        ("filter", "BODY~ [1]~ filter~ 0~ pop~"),
+        ("reduce", "BODY~ 0~ [1]~ reduce~ 0~ pop~"),
+    ];
+
+    fn run_runner(runner: &str, body: &str, limits: Limits) -> (Interpreter, EvalResult<()>) {
+        let code = runner.replace("BODY", body);
+        let mut interp = Interpreter::new()
+            .with_input(Box::new(Cursor::new("line\n")))
+            .with_limits(limits)
+            .with_effect_checks(true);
+        let tokens = tokenize_code(&code);
+        let result = interp.run(parse_tokens(&mut tokens.into_iter()).unwrap());
+        (interp, result)
+    }
+
+    #[test]
+    fn test_eval_control_flow() -> Result<(), String> {
+        let steps = |max| Limits {
+            max_steps: Some(max),
+            ..Limits::default()
+        };
+        for (name, runner) in RUNNERS.iter() {
+            // the execs of the body are traced like any others, one block deeper
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let recorded = events.clone();
+            let code = runner.replace("BODY", "{1~ pop~}");
+            let mut interp = Interpreter::new()
+                .with_input(Box::new(Cursor::new("line\n")))
+                .with_limits(steps(100))
+                .with_tracer(move |event: &TraceEvent| {
+                    // the conditions of ite and while are left out
+                    if event.depth > 1 && event.exec_text() != "true~" {
+                        let path_len = event.path.len();
+                        recorded
+                            .borrow_mut()
+                            .push((event.exec_text(), path_len, event.depth));
+                    }
+                });
+            let tokens = tokenize_code(&code);
+            let _ = interp.run(parse_tokens(&mut tokens.into_iter())?);
+            assert_eq!(
+                events.borrow()[..2],
+                [("1~".to_string(), 2, 2), ("pop~".to_string(), 2, 2)],
+                "{}",
+                name
+            );
+
+            // every limit applies inside the body
+            let (_, result) = run_runner(runner, "{{}~ loop~}", steps(1000));
+            assert_eq!(result, Err(EvalError::StepLimit(1000)), "{}", name);
+            let limits = Limits {
+                max_deque_len: Some(10),
+                ..Limits::default()
+            };
+            let (_, result) = run_runner(runner, "{{1~}~ loop~}", limits);
+            assert_eq!(result, Err(EvalError::DequeLimit(10)), "{}", name);
+            let limits = Limits {
+                max_depth: Some(50),
+                ..Limits::default()
+            };
+            let (_, result) = run_runner(runner, "{{dup~ exec~ 1~}~ dup~ exec~}", limits);
+            assert_eq!(result, Err(EvalError::DepthLimit(50)), "{}", name);
+
+            // and so do effect checks
+            let (_, result) = run_runner(runner, "{( -- ) 1~}", steps(100));
+            assert!(matches!(result, Err(EvalError::Effect(..))), "{}", name);
+
+            // a body that recurses in tail position doesn't grow the stack
+            let body = "{{dup~ exec~}~ dup~ exec~}";
+            let (interp, result) = run_runner(runner, body, steps(10_000));
+            assert_eq!(result, Err(EvalError::StepLimit(10_000)), "{}", name);
+            assert!(interp.depth() < 10, "{}", name);
+
+            // a failed body leaves the deque the program was run on, not one of its own
+            let code = format!("7! {}", runner);
+            let (interp, result) = run_runner(&code, "{1~ exit~}", steps(100));
+            assert_eq!(result, Err(EvalError::Exit(1)), "{}", name);
+            assert_eq!(interp.deque.front(), Some(&Value::Int(7)), "{}", name);
+
+            // stepping stops inside the body
+            let code = runner.replace("BODY", "{1~ pop~}");
+            let mut interp = Interpreter::new().with_input(Box::new(Cursor::new("line\n")));
+            interp.start(parse_tokens(&mut tokenize_code(&code).into_iter())?);
+            let mut inside = false;
+            for _ in 0..10 {
+                interp.step()?;
+                if let Some((exec, path)) = interp.next_exec()? {
+                    if path.len() == 2 && exec.to_string() == "1" {
+                        inside = true;
+                        break;
+                    }
+                }
+            }
+            assert!(inside, "{}", name);
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_eval_for_loop() -> Result<(), String> {
-        unimplemented!()
+        let run = |code: &str| {
+            let tokens = tokenize_code(&String::from(code));
+            let ast = parse_tokens(&mut tokens.into_iter()).unwrap();
+            run_ast(None, ast)
+        };
+        // range pushes each i from the start, up to but not including the end
+        assert_eq!(
+            run("0~ {+~}~ 1~ 11~ 1~ range~")?,
+            VecDeque::from(vec![Value::Int(55)])
+        );
+        assert_eq!(
+            run("0~ {+~}~ 2~ 10~ 1~ range~")?,
+            VecDeque::from(vec![Value::Int(25)])
+        );
+        assert_eq!(
+            run("{}~ 1~ 3~ 0~ range~")?,
+            VecDeque::from(vec![Value::Int(0), Value::Int(1), Value::Int(2)])
+        );
+        // on the left, i is pushed to the front
+        assert_eq!(
+            run("{}! 1! 3! 0! range!")?,
+            VecDeque::from(vec![Value::Int(2), Value::Int(1), Value::Int(0)])
+        );
+        // an empty range runs nothing
+        assert_eq!(run("{ol~}~ 1~ 0~ 5~ range~")?, VecDeque::new());
+        assert!(run("{}~ 0~ 3~ 0~ range~").is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use crate::formatter::fmt::*;
+    use crate::parser::par::*;
+    use crate::parser::par_ast::Code;
+
+    fn parse(code: &str) -> Result<Code, String> {
+        parse_source(code)
+    }
+
+    fn format_default(code: &str) -> Result<String, String> {
+        format_code(code, &FormatOptions::default())
+    }
+
+    // the scripts in examples/
+    fn examples() -> Vec<(String, String)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut examples = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "deque") {
+                let code = std::fs::read_to_string(&path).unwrap();
+                examples.push((path.display().to_string(), code));
+            }
+        }
+        assert!(!examples.is_empty());
+        examples
+    }
+
+    #[test]
+    fn test_fmt_spacing() -> Result<(), String> {
+        assert_eq!(format_default("1~2~   +~ ol!")?, "1~ 2~ +~ ol!\n");
+        assert_eq!(format_default("{ 1~  2~ }~ { }~")?, "{1~ 2~}~ {}~\n");
+        assert_eq!(
+            format_default("[1,2 ,[ 'a' ,\"b\" ]]~")?,
+            "[1, 2, ['a', \"b\"]]~\n"
+        );
+        assert_eq!(format_default("")?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fmt_lines() -> Result<(), String> {
+        // line breaks are kept, but not runs of blank lines
+        assert_eq!(format_default("1~\n\n\n\n2~\n3~")?, "1~\n\n2~\n3~\n");
+        assert_eq!(format_default("\n\n1~\n\n")?, "1~\n");
+        // a block written over several lines keeps its contents on their own lines
+        assert_eq!(
+            format_default("{1~\n{2~ 3~}~ 4~}~ 5~")?,
+            "{\n    1~\n    {2~ 3~}~ 4~\n}~ 5~\n"
+        );
+        assert_eq!(
+            format_default("{{\n1~}~}~")?,
+            "{\n    {\n        1~\n    }~\n}~\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fmt_comments() -> Result<(), String> {
+        assert_eq!(
+            format_default("# top  \n1~ #one\n2~")?,
+            "# top\n1~ #one\n2~\n"
+        );
+        assert_eq!(
+            format_default("{ # body\n1~ # one\n}~")?,
+            "{ # body\n    1~ # one\n}~\n"
+        );
+        assert_eq!(
+            format_default("[1, # one\n2]~")?,
+            "[\n    1, # one\n    2,\n]~\n"
+        );
+        // a shebang is a comment
+        assert_eq!(
+            format_default("#!/usr/bin/env rusty_deque\n1~  ol~")?,
+            "#!/usr/bin/env rusty_deque\n1~ ol~\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fmt_annotations() -> Result<(), String> {
+        assert_eq!(
+            format_default("{(a  b --   c)+~}~")?,
+            "{( a b -- c ) +~}~\n"
+        );
+        // the annotation stays on the line of the brace
+        assert_eq!(
+            format_default("{ (a|--|b) # push\n1!}~")?,
+            "{( a | -- | b ) # push\n    1!\n}~\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fmt_width() -> Result<(), String> {
+        let options = FormatOptions {
+            max_width: 10,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_code("1~ 2~ 3~ 4~ 5~", &options)?,
+            "1~ 2~ 3~\n4~ 5~\n"
+        );
+        assert_eq!(
+            format_code("{1~ 2~ 3~ 4~}~", &options)?,
+            "{\n    1~ 2~\n    3~ 4~\n}~\n"
+        );
+        assert_eq!(
+            format_code("[10, 20, 30]~", &options)?,
+            "[\n    10,\n    20,\n    30,\n]~\n"
+        );
+        // an atom that can't fit goes past the width
+        assert_eq!(
+            format_code("\"a long string\"~", &options)?,
+            "\"a long string\"~\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fmt_errors() {
+        assert!(format_default("1~ 2").is_err());
+        assert!(format_default("1.2.3~").is_err());
+        assert!(format_default("[1, 2~").is_err());
+    }
+
+    #[test]
+    fn test_fmt_examples_idempotent() -> Result<(), String> {
+        for max_width in [80, 40, 20].iter() {
+            let options = FormatOptions {
+                max_width: *max_width,
+                ..FormatOptions::default()
+            };
+            for (path, code) in examples() {
+                let formatted = format_code(&code, &options)?;
+                let reformatted = format_code(&formatted, &options)?;
+                assert_eq!(formatted, reformatted, "{} at width {}", path, max_width);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fmt_examples_same_ast() -> Result<(), String> {
+        for (path, code) in examples() {
+            let formatted = format_default(&code)?;
+            assert_eq!(parse(code.trim())?, parse(formatted.trim())?, "{}", path);
+        }
+        Ok(())
+    }
+}
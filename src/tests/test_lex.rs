@@ -1,4 +1,6 @@
 #[cfg(test)]
+// the original tests keep their form; new tests are written clippy-clean
+#[allow(clippy::print_literal)]
 mod tests {
     use crate::lexer::lex::*;
     use crate::lexer::lex_token::*;
@@ -767,6 +769,12 @@ mod tests {
         assert_eq!(expected, tokenize_code(&input_str));
     }
 
+    #[test]
+    fn test_lex_empty() {
+        let expected: Vec<Token> = Vec::new();
+        assert_eq!(expected, tokenize_code(""));
+    }
+
     #[test]
     fn test_lex_comments_and_code() {
         let input_str =
@@ -777,7 +785,7 @@ mod tests {
                 lexeme: String::from("this"),
                 error_msg: String::new(),
                 line: 1,
-                start: 19,
+                start: 20,
                 end: 24,
             },
             Token {
@@ -802,9 +810,115 @@ mod tests {
                 error_msg: String::new(),
                 line: 1,
                 start: 33,
-                end: 38,
+                end: 37,
             },
         ];
         assert_eq!(expected, tokenize_code(&input_str));
     }
+
+    #[test]
+    fn test_lex_non_ascii() {
+        let input_str = String::from("'é'~ \"日本\"~");
+        let tokens = tokenize_code(&input_str);
+        let lexemes: Vec<&str> = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(lexemes, vec!["'é'", "~", "\"日本\"", "~"]);
+        assert_eq!(tokens[0].token_type, TokenType::ConstChar);
+        assert_eq!((tokens[2].start, tokens[2].end), (5, 9));
+    }
+
+    #[test]
+    fn test_lex_spans_around_comments_and_newlines() {
+        // a token after a comment starts just after the comment's newline,
+        // not one char early for the # that began it
+        let tokens = tokenize_code(&String::from("#c\nab~"));
+        assert_eq!((tokens[0].start, tokens[0].end), (3, 5));
+        // an instruction ended by a newline ends before it, like one ended by a space
+        let tokens = tokenize_code(&String::from("ab\ncd~"));
+        assert_eq!((tokens[0].start, tokens[0].end), (0, 2));
+        assert_eq!((tokens[1].start, tokens[1].end, tokens[1].line), (3, 5, 1));
+    }
+
+    #[test]
+    fn test_lex_lines() {
+        let input_str = String::from("a\nb~ \"x\ny\"~\n# c\nd~");
+        let lines: Vec<(String, usize)> = tokenize_code(&input_str)
+            .into_iter()
+            .map(|t| (t.lexeme, t.line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("a".to_string(), 0),
+                ("b".to_string(), 1),
+                ("~".to_string(), 1),
+                ("\"x\ny\"".to_string(), 1),
+                ("~".to_string(), 2),
+                ("d".to_string(), 4),
+                ("~".to_string(), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_trivia() {
+        let input_str = "  1~ # one\r\n\n\"a\"~\t#end";
+        let tokens = tokenize_with_trivia(input_str);
+        let source: String = tokens.iter().map(|t| t.to_string()).collect();
+        assert_eq!(source, input_str);
+
+        let kinds: Vec<TriviaKind> = tokens[2].leading.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TriviaKind::Whitespace,
+                TriviaKind::Comment,
+                TriviaKind::Newline,
+                TriviaKind::Newline,
+            ]
+        );
+        assert_eq!(tokens[2].leading[1].text, "# one");
+        assert_eq!(tokens[2].leading[2].text, "\r\n");
+        assert_eq!(tokens[2].text, "\"a\"");
+
+        let end = tokens.last().unwrap();
+        assert_eq!(end.token.token_type, TokenType::End);
+        assert_eq!(end.token.line, 2);
+        assert_eq!(end.leading.last().unwrap().text, "#end");
+    }
+
+    #[test]
+    fn test_lex_trivia_round_trip() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let code = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            let source: String = tokenize_with_trivia(&code)
+                .iter()
+                .map(|t| t.to_string())
+                .collect();
+            assert_eq!(source, code);
+        }
+        for code in ["", "\n\n", "1.2.3~", "\"unterminated", "a\r\nb\r"].iter() {
+            let source: String = tokenize_with_trivia(code)
+                .iter()
+                .map(|t| t.to_string())
+                .collect();
+            assert_eq!(&source, code);
+        }
+    }
+
+    #[test]
+    fn test_lex_effect() {
+        let input_str = String::from("{( a b\n -- c ) +~}~ (a");
+        let tokens = tokenize_code(&input_str);
+        assert_eq!(tokens[1].token_type, TokenType::Effect);
+        assert_eq!(tokens[1].lexeme, "( a b\n -- c )");
+        // the newline inside counts towards the next token's line
+        assert_eq!(tokens[2].line, 1);
+        let last = tokens.last().unwrap();
+        assert_eq!(last.token_type, TokenType::Error);
+        assert_eq!(
+            last.error_msg,
+            "Invalid stack effect: Unterminated stack effect"
+        );
+    }
 }
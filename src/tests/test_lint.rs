@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use crate::linter::lint::*;
+
+    // the rule, line, column and message of each lint
+    fn lints(code: &str) -> Vec<(Rule, usize, usize, String)> {
+        lint_code(code, &LintConfig::default())
+            .unwrap()
+            .into_iter()
+            .map(|lint| (lint.rule, lint.line, lint.column, lint.message))
+            .collect()
+    }
+
+    // the code with every fix applied
+    fn fixed(code: &str) -> String {
+        let lints = lint_code(code, &LintConfig::default()).unwrap();
+        apply_fixes(code, lints).0
+    }
+
+    #[test]
+    fn test_lint_wrong_side() {
+        assert_eq!(
+            lints("1~ 2~ +!"),
+            vec![(
+                Rule::WrongSide,
+                1,
+                7,
+                "`2~` is pushed onto the back, but `+!` pops from the front".to_string()
+            )]
+        );
+        assert_eq!(fixed("1~ 2~ +!"), "1~ 2~ +~");
+        // rot and over move values between the ends on purpose
+        assert!(lints("1~ 2~ rot! ol~ ol~").is_empty());
+        assert!(lints("1~ {ol~}~ 3! over~ ol~ ol~ ol~").is_empty());
+        // instructions that pop nothing don't care
+        assert!(lints("1~ args! ol~ ol~").is_empty());
+    }
+
+    #[test]
+    fn test_lint_unused_literal() {
+        assert_eq!(
+            lints("1~ pop~ 2~ ol~"),
+            vec![(
+                Rule::UnusedLiteral,
+                1,
+                1,
+                "`1~` is pushed, then dropped by `pop~`".to_string()
+            )]
+        );
+        assert_eq!(fixed("1~ pop~ 2~ ol~"), "2~ ol~");
+        assert_eq!(
+            lints("\"a\"~ ol~\n[1, 2]~"),
+            vec![(
+                Rule::UnusedLiteral,
+                2,
+                1,
+                "`[...]~` is left on the deque, and never used".to_string()
+            )]
+        );
+        assert_eq!(fixed("1~ ol~ 2~\n3~ ol~"), "1~ ol~\n3~ ol~");
+        // a value that is used somewhere isn't unused, even if a copy is dropped
+        assert!(lints("1~ dup~ pop~ ol~").is_empty());
+        assert!(lints("{2~ ol~}~ exec~").is_empty());
+        // a pop in a block that runs elsewhere is left alone by the fix
+        let lints = lint_code("1~ {pop~}~ exec~", &LintConfig::default()).unwrap();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].fix, None);
+    }
+
+    #[test]
+    fn test_lint_range_step() {
+        assert_eq!(
+            lints("{ol~}~ 0~ 10~ 0~ range~"),
+            vec![(
+                Rule::RangeStep,
+                1,
+                8,
+                "`range~` has a step of 0, which fails when it runs".to_string()
+            )]
+        );
+        assert_eq!(fixed("{ol~}~ -2~ 10~ 0~ range~"), "{ol~}~ 2~ 10~ 0~ range~");
+        assert!(lints("{ol~}~ 1~ 10~ 0~ range~").is_empty());
+        // a step that isn't pushed right before the range is left to the checker
+        assert!(lints("{ol~}~ 0~ 10! 0~ range~").is_empty());
+    }
+
+    #[test]
+    fn test_lint_unreachable() {
+        assert_eq!(
+            lints("{1~ ol~}~ loop~\n2~ ol~"),
+            vec![(
+                Rule::Unreachable,
+                2,
+                1,
+                "code after `loop~` never runs".to_string()
+            )]
+        );
+        assert_eq!(
+            fixed("1~ ol~ {0~ exit~ 1~ ol~}~ exec~"),
+            "1~ ol~ {0~ exit~}~ exec~"
+        );
+        assert!(lints("{}~ loop~").is_empty());
+    }
+
+    #[test]
+    fn test_lint_config() -> Result<(), String> {
+        let config = LintConfig::parse("# quiet\nunused-literal = off\n\nrange-step=off # too\n")?;
+        assert!(!config.enabled(Rule::UnusedLiteral));
+        assert!(!config.enabled(Rule::RangeStep));
+        assert!(config.enabled(Rule::WrongSide));
+        assert!(lint_code("1~ pop~", &config)?.is_empty());
+        // a later line wins
+        assert!(
+            LintConfig::parse("unreachable = off\nunreachable = on")?.enabled(Rule::Unreachable)
+        );
+        for text in ["frob = off", "unreachable", "unreachable = maybe"].iter() {
+            assert!(LintConfig::parse(text).is_err(), "{}", text);
+        }
+        assert!(lint_code("1~ 2", &LintConfig::default()).is_err());
+        Ok(())
+    }
+}
@@ -1,9 +1,17 @@
 #[cfg(test)]
+// the original tests keep their form; new tests are written clippy-clean
+#[allow(
+    clippy::useless_vec,
+    clippy::redundant_closure,
+    clippy::needless_borrow
+)]
 mod tests {
 
     use crate::lexer::lex::*;
+    use crate::lexer::lex_token::TokenType;
     use crate::parser::par::*;
     use crate::parser::par_ast::*;
+    use crate::parser::par_cst::*;
 
     #[test]
     fn test_par_hello_world() -> Result<(), String> {
@@ -56,6 +64,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_par_out_of_range_literals() {
+        let token = get_next_token(&String::from("99999999999999999999"), 0, 0).0;
+        assert_eq!(
+            parse_literal(&token),
+            Err(String::from(
+                "Parser Error: Integer literal `99999999999999999999` is out of range"
+            ))
+        );
+        let float = format!("{}.0", "9".repeat(400));
+        let token = get_next_token(&float, 0, 0).0;
+        assert!(parse_literal(&token).is_err());
+        // the extremes still parse
+        let token = get_next_token(&String::from("-9223372036854775808"), 0, 0).0;
+        assert_eq!(parse_literal(&token), Ok(Literal::Int(i64::MIN)));
+
+        // lowering the CST, as the tools do, fails the same way
+        for input in ["99999999999999999999~", "[1, 99999999999999999999]~"].iter() {
+            let cst = parse_cst(tokenize_with_trivia(input)).unwrap();
+            assert!(cst.to_ast().is_err(), "{}", input);
+            assert!(parse_ast(input).is_err(), "{}", input);
+        }
+    }
+
     #[test]
     fn test_par_ints() {
         let ints = vec![
@@ -582,7 +614,7 @@ mod tests {
         let block = parse_exec(&mut tokens.into_iter()).unwrap();
         println!("{:?}\n", block);
 
-        let expected = Exec::Left(Op::Literal(Literal::Block(vec![
+        let expected = Exec::Left(Op::Literal(Literal::new_block(vec![
             Exec::Right(Op::Literal(Literal::Int(1))),
             Exec::Left(Op::Literal(Literal::Int(2))),
             Exec::Right(Op::Literal(Literal::Int(3))),
@@ -599,9 +631,9 @@ mod tests {
         let block = parse_exec(&mut tokens.into_iter()).unwrap();
         println!("{:?}\n", block);
 
-        let expected = Exec::Left(Op::Literal(Literal::Block(vec![
+        let expected = Exec::Left(Op::Literal(Literal::new_block(vec![
             Exec::Right(Op::Literal(Literal::Int(1))),
-            Exec::Right(Op::Literal(Literal::Block(vec![
+            Exec::Right(Op::Literal(Literal::new_block(vec![
                 Exec::Right(Op::Instruction(String::from("dup"))),
                 Exec::Left(Op::Literal(Literal::Int(2))),
                 Exec::Right(Op::Instruction(String::from("rot"))),
@@ -612,4 +644,155 @@ mod tests {
 
         assert_eq!(expected, block);
     }
+
+    #[test]
+    fn test_par_token_parsers_leave_the_rest() -> Result<(), String> {
+        let mut tokens = tokenize_code(&String::from("1~ {2~ [3]!}~ 4!")).into_iter();
+        assert_eq!(
+            parse_exec(&mut tokens)?,
+            Exec::Right(Op::Literal(Literal::Int(1)))
+        );
+        tokens.next();
+        let block = parse_block(&mut tokens)?;
+        assert_eq!(
+            block,
+            Literal::new_block(vec![
+                Exec::Right(Op::Literal(Literal::Int(2))),
+                Exec::Left(Op::Literal(Literal::List(vec![Literal::Int(3)]))),
+            ])
+        );
+        assert_eq!(tokens.next().map(|t| t.token_type), Some(TokenType::Tilde));
+        assert_eq!(parse_tokens(&mut tokens)?.len(), 1);
+        assert_eq!(tokens.len(), 0);
+        Ok(())
+    }
+
+    fn parse_ast(code: &str) -> Result<Code, String> {
+        parse_tokens(&mut tokenize_code(code).into_iter())
+    }
+
+    #[test]
+    fn test_par_cst_round_trip() -> Result<(), String> {
+        let inputs = [
+            "",
+            "1~ # one\n  [ 1 ,[2,'a'] , ]!\n\n{ dup~\n  {ol~}~ }~ # end",
+            "\"a\\\"b\"~\r\nol~\r\n",
+            "{ # sum\n  (a  b -- c)  +~ }~",
+        ];
+        for input in inputs.iter() {
+            let cst = parse_cst(tokenize_with_trivia(input))?;
+            assert_eq!(&cst.to_string(), input);
+            assert_eq!(cst.to_ast()?, parse_ast(input)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_par_cst_examples() -> Result<(), String> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let code = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            let cst = parse_cst(tokenize_with_trivia(&code))?;
+            assert_eq!(cst.to_string(), code);
+            assert_eq!(cst.to_ast()?, parse_ast(code.trim())?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_par_cst_spelling() -> Result<(), String> {
+        // the two spellings are the same value, but the CST keeps each one
+        let string = parse_cst(tokenize_with_trivia("\"hi\"~"))?;
+        let chars = parse_cst(tokenize_with_trivia("['h','i']~"))?;
+        assert_eq!(string.to_ast()?, chars.to_ast()?);
+        assert_eq!(string.to_string(), "\"hi\"~");
+        assert_eq!(chars.to_string(), "['h','i']~");
+        match &chars.execs[0].op {
+            CstOp::List(list) => assert_eq!(list.items.len(), 3),
+            op => panic!("expected a list, got {:?}", op),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_par_cst_errors() {
+        let inputs = [
+            "1~ 2",
+            "{1~",
+            "[1, 2~",
+            "}~",
+            "1.2.3~",
+            "[ol]~",
+            "(a -- b)~",
+            "{1~ (a -- b)}~",
+        ];
+        for input in inputs.iter() {
+            assert!(parse_cst(tokenize_with_trivia(input)).is_err(), "{}", input);
+            assert!(parse_ast(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_par_cst_recovering() {
+        // parsing carries on after each error, leaving out the execs they are in
+        let (cst, errors) = parse_cst_recovering(tokenize_with_trivia("1 2~ {[ol]~ 3~}~ }~ 4~"));
+        let lines: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Parser Error: Expected sigil Bang or Tilde, instead got ConstInt on line 1",
+                "Parser Error: Unexpected token type Instr for Literal on line 1",
+                "Parse Error: Unexpected token type RightCurly for Op on line 1",
+            ]
+        );
+        assert_eq!(cst.to_string(), " 2~ { 3~}~ 4~");
+        // the first error is the one parse_cst reports
+        assert_eq!(
+            parse_cst(tokenize_with_trivia("1 2~ }~")).map_err(String::from),
+            Err(lines[0].clone())
+        );
+    }
+
+    #[test]
+    fn test_par_effect() -> Result<(), String> {
+        let effect = StackEffect::parse("( n -- n! )")?;
+        assert_eq!(effect.right_in, vec!["n"]);
+        assert_eq!(effect.right_out, vec!["n!"]);
+        assert_eq!((effect.inputs(), effect.outputs()), (1, 1));
+        let effect = StackEffect::parse("(a|b c -- | d)")?;
+        assert_eq!(effect.left_in, vec!["a"]);
+        assert_eq!(effect.right_in, vec!["b", "c"]);
+        assert!(effect.left_out.is_empty());
+        assert_eq!(effect.to_string(), "( a | b c -- | d )");
+        assert_eq!(StackEffect::parse("(--)")?.to_string(), "( -- )");
+        for text in [
+            "( a b )",
+            "( a -- b -- c )",
+            "( a | b -- c )",
+            "( a | b | c -- | )",
+        ]
+        .iter()
+        {
+            assert!(StackEffect::parse(text).is_err(), "{}", text);
+        }
+
+        // the annotation is part of the block literal
+        let ast = parse_ast("{( a b -- c ) +~}~")?;
+        match &ast[0] {
+            Exec::Right(Op::Literal(Literal::Block(block))) => {
+                assert_eq!(block.effect, Some(StackEffect::parse("( a b -- c )")?));
+                assert_eq!(block.execs.len(), 1);
+            }
+            exec => panic!("expected a block, got {:?}", exec),
+        }
+        // a malformed annotation is found when the tree is lowered, like a bad char escape
+        assert!(parse_ast("{(a b)}~").is_err());
+        let cst = parse_cst(tokenize_with_trivia("{(a b)}~"))?;
+        assert!(cst.to_ast().is_err());
+        assert_eq!(
+            Literal::new_annotated_block(vec![], StackEffect::parse("(--)")?).to_string(),
+            "{( -- ) }"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::eval::Interpreter;
+    use crate::evaluator::eval_trace::{Observer, TraceEvent};
+    use crate::parser::par::parse_source;
+    use crate::parser::par_ast::{Exec, Literal, Op};
+    use crate::profiler::profile::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    // an exec that took ms milliseconds
+    fn event(exec: Exec, path: Vec<usize>, ms: u64) -> TraceEvent {
+        TraceEvent {
+            step: 0,
+            exec,
+            path,
+            depth: 0,
+            before: VecDeque::new(),
+            after: VecDeque::new(),
+            error: None,
+            elapsed: Duration::from_millis(ms),
+        }
+    }
+
+    fn instr(name: &str) -> Exec {
+        Exec::Right(Op::Instruction(name.to_string()))
+    }
+
+    fn stat(count: u64, ms: u64) -> Stat {
+        Stat {
+            count,
+            time: Duration::from_millis(ms),
+        }
+    }
+
+    #[test]
+    fn test_profile_record() -> Result<(), String> {
+        // {1~ dup~}~ exec~, with the block run twice
+        let source = "{1~ dup~}~\nexec~";
+        let one = Exec::Right(Op::Literal(Literal::Int(1)));
+        let mut profile = Profile::new();
+        profile.on_block_enter(&[], 1);
+        profile.after_exec(&event(one.clone(), vec![0], 1));
+        profile.after_exec(&event(instr("exec"), vec![1], 2));
+        for _ in 0..2 {
+            profile.on_block_enter(&[0], 2);
+            profile.after_exec(&event(one.clone(), vec![0, 0], 3));
+            profile.after_exec(&event(instr("dup"), vec![0, 1], 4));
+        }
+
+        assert_eq!(profile.instrs[LITERAL], stat(3, 7));
+        assert_eq!(profile.instrs["dup"], stat(2, 8));
+        assert_eq!(profile.instrs["exec"], stat(1, 2));
+        // the top level holds every exec, and starts once
+        assert_eq!(profile.blocks[&vec![]], stat(1, 17));
+        assert_eq!(profile.blocks[&vec![0]], stat(2, 14));
+
+        assert_eq!(
+            profile.report(source)?,
+            "     count          time  instruction
+         2       8.000ms  dup
+         3       7.000ms  <literal>
+         1       2.000ms  exec
+
+     count          time  block
+         1      17.000ms  top
+         2      14.000ms  block@1:1"
+        );
+        assert_eq!(
+            profile.folded(source)?,
+            "top;<literal> 1000000
+top;block@1:1;<literal> 6000000
+top;block@1:1;dup 8000000
+top;exec 2000000"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_run() -> Result<(), String> {
+        let source = "0~ {+~}~ 1~ 4~ 1~ range~";
+        let profile = Rc::new(RefCell::new(Profile::new()));
+        let mut interp = Interpreter::new().with_observer(profile.clone());
+        interp.run(parse_source(source)?)?;
+
+        let profile = profile.borrow();
+        assert_eq!(profile.instrs["+"].count, 3);
+        assert_eq!(profile.instrs["range"].count, 1);
+        assert_eq!(profile.instrs[LITERAL].count, 5);
+        assert_eq!(profile.blocks[&vec![1]].count, 3);
+        // the block starts once for each turn of the range
+        assert!(profile.report(source)?.contains("         3  "));
+        assert!(profile.folded(source)?.contains("top;block@1:4;+ "));
+        Ok(())
+    }
+}
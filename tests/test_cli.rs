@@ -0,0 +1,317 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn rusty_deque(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rusty_deque"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// a fresh directory for the files a test needs
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rusty_deque_cli_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_cli_version_and_help() {
+    let output = rusty_deque(&["--version"], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("rusty_deque "));
+
+    let output = rusty_deque(&["--help"], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("dump-ast"));
+}
+
+#[test]
+fn test_cli_run() {
+    let output = rusty_deque(&["run", "-c", "args~ ol~", "--", "a", "b"], "");
+    assert_eq!(stdout(&output), "[\"a\", \"b\", ]\n");
+    assert_eq!(output.status.code(), Some(0));
+
+    // a script path without a command runs it, and a shebang is skipped
+    let dir = scratch_dir("run");
+    let script = dir.join("script.deque");
+    std::fs::write(&script, "#!/usr/bin/env rusty_deque\nargs~ ol~ 3~ exit~\n").unwrap();
+    let output = rusty_deque(&[script.to_str().unwrap(), "x"], "");
+    assert_eq!(stdout(&output), "[\"x\", ]\n");
+    assert_eq!(output.status.code(), Some(3));
+
+    // errors in a script with a shebang are reported on the line they are on
+    let script = dir.join("error.deque");
+    std::fs::write(&script, "#!/usr/bin/env rusty_deque\n1~ ol~\n\"abc\n").unwrap();
+    let expected = "line 3: Invalid string literal: Unterminated string constant\n";
+    let output = rusty_deque(&["run", script.to_str().unwrap()], "");
+    assert_eq!(stderr(&output), format!("error: {}", expected));
+    let output = rusty_deque(&["check", script.to_str().unwrap()], "");
+    assert_eq!(
+        stderr(&output),
+        format!("{}: {}", script.to_str().unwrap(), expected)
+    );
+
+    let output = rusty_deque(&["-"], "\"from stdin\"~ ol~");
+    assert_eq!(stdout(&output), "from stdin\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_exit_codes() {
+    let output = rusty_deque(&["-c", "1~ foo~"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "error: Unknown instruction: foo\n");
+    assert_eq!(stdout(&output), "");
+
+    assert_eq!(rusty_deque(&["--bogus"], "").status.code(), Some(2));
+    assert_eq!(rusty_deque(&["-c", "1.~"], "").status.code(), Some(3));
+    assert_eq!(rusty_deque(&["missing.deque"], "").status.code(), Some(4));
+    assert_eq!(
+        rusty_deque(&["--max-steps", "10", "-c", "{}~ loop~"], "")
+            .status
+            .code(),
+        Some(5)
+    );
+    assert_eq!(
+        rusty_deque(&["--deny-io", "-c", "1~ ol~"], "")
+            .status
+            .code(),
+        Some(6)
+    );
+
+    // annotations are only checked when asked to
+    let code = "1~ {( a -- b ) dup~}~ exec~";
+    assert_eq!(rusty_deque(&["-c", code], "").status.code(), Some(0));
+    let output = rusty_deque(&["--check-effects", "-c", code], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("error: stack effect ( a -- b ): "));
+}
+
+#[test]
+fn test_cli_check() {
+    let dir = scratch_dir("check");
+    let bad = dir.join("bad.deque");
+    std::fs::write(&bad, "'ab'~\n1.~\n").unwrap();
+    let output = rusty_deque(&["check", bad.to_str().unwrap(), "-c", "1~ ol~"], "");
+    assert_eq!(output.status.code(), Some(3));
+    // every lexer error is reported, not just the first
+    assert_eq!(stderr(&output).lines().count(), 2);
+    assert!(stderr(&output).contains("line 2"));
+    // and so is every parser error, each once
+    let code = "1~ 2\n{[ol]~ 3~}~\n}~ 99999999999999999999~ 4~";
+    let output = rusty_deque(&["check", "-c", code], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        stderr(&output),
+        "<expr>: Parser Error: Expected sigil Bang or Tilde, instead got LeftCurly on line 2\n\
+         <expr>: Parser Error: Unexpected token type Instr for Literal on line 2\n\
+         <expr>: Parse Error: Unexpected token type RightCurly for Op on line 3\n\
+         <expr>: Parser Error: Integer literal `99999999999999999999` is out of range on line 3\n"
+    );
+
+    let output = rusty_deque(&["check", "-c", "1~ ol~"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "");
+
+    // the checker's warnings don't change the exit status
+    let output = rusty_deque(&["check", "--effects", "-c", "{+~}~\n'a'~ 1~ +~"], "");
+    assert!(output.status.success());
+    assert_eq!(
+        stderr(&output),
+        "<expr>: line 2: warning: `+~` expects a number, got Char\n"
+    );
+    assert_eq!(stdout(&output), "<expr>: line 1: ( | Num Num -- Num | )\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_debug() {
+    let output = rusty_deque(
+        &["debug", "-c", "1~ {2~ ol~}~ exec~"],
+        "break ol\ncontinue\n\nquit\n",
+    );
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with("line 1, column 1: 1~\n1 | 1~ {2~ ol~}~ exec~\n  | ^^\ndeque: []\n"));
+    assert!(stdout.contains("breakpoint 1: instruction ol\n"));
+    assert!(stdout.contains("line 1, column 8: ol~\n"));
+    // an empty line continues again, so the block prints and the program finishes
+    assert!(stdout.contains("2\nthe program finished\ndeque: [1]\n"));
+
+    let output = rusty_deque(&["debug", "-c", "4~ exit~"], "c\n");
+    assert_eq!(output.status.code(), Some(4));
+    let output = rusty_deque(&["debug", "-c", "1~ {"], "");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_cli_trace() {
+    let dir = scratch_dir("trace");
+    let trace = dir.join("trace.jsonl");
+    let trace = trace.to_str().unwrap();
+    let output = rusty_deque(&["run", "--trace", trace, "-c", "1~ 2~ +~ ol~"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "3\n");
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(trace)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[2]["exec"], "+~");
+    assert_eq!(lines[2]["before"], serde_json::json!(["1", "2"]));
+    assert_eq!(lines[2]["after"], serde_json::json!(["3"]));
+
+    // - traces to stderr
+    let output = rusty_deque(&["--trace", "-", "-c", "pop~"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("\"error\":\"tried to pop empty deque\""));
+
+    let missing = dir.join("missing").join("trace.jsonl");
+    let output = rusty_deque(&["--trace", missing.to_str().unwrap(), "-c", "1~"], "");
+    assert_eq!(output.status.code(), Some(4));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_profile() {
+    let output = rusty_deque(&["--profile", "-c", "0~ {+~}~ 1~ 4~ 1~ range~ ol~"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "6\n");
+    let report = stderr(&output);
+    assert!(report.starts_with("     count          time  instruction\n"));
+    assert!(report.contains("     count          time  block\n"));
+    assert!(report.contains("  block@1:4\n"));
+
+    let dir = scratch_dir("profile");
+    let folded = dir.join("folded.txt");
+    let folded = folded.to_str().unwrap();
+    let output = rusty_deque(&["--profile-folded", folded, "-c", "{1~}~ exec~"], "");
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "");
+    let stacks: Vec<String> = std::fs::read_to_string(folded)
+        .unwrap()
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+        .collect();
+    assert_eq!(
+        stacks,
+        vec!["top;<literal>", "top;block@1:1;<literal>", "top;exec"]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_lint() {
+    let output = rusty_deque(&["lint", "-c", "1~ ol~"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "");
+
+    let dir = scratch_dir("lint");
+    let script = dir.join("script.deque");
+    let script = script.to_str().unwrap();
+    std::fs::write(
+        script,
+        "1~ 2~ +!
+{ol~}~ 0~ 3~ 0~ range~
+",
+    )
+    .unwrap();
+    let output = rusty_deque(&["lint", script], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{0}: line 1, column 7: wrong-side: `2~` is pushed onto the back, but `+!` pops from the front\n    fix: pop from the back with `+~`\n{0}: line 2, column 8: range-step: `range~` has a step of 0, which fails when it runs\n    fix: use a step of 1\n",
+            script
+        )
+    );
+
+    // rules can be turned off, and fixes applied
+    let config = dir.join("lint.conf");
+    std::fs::write(&config, "range-step = off\n").unwrap();
+    let config = config.to_str().unwrap();
+    let output = rusty_deque(&["lint", "--config", config, "--write", script], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        std::fs::read_to_string(script).unwrap(),
+        "1~ 2~ +~\n{ol~}~ 0~ 3~ 0~ range~\n"
+    );
+
+    std::fs::write(config, "bogus = off\n").unwrap();
+    let output = rusty_deque(&["lint", "--config", config, script], "");
+    assert_eq!(output.status.code(), Some(2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_test() {
+    let dir = scratch_dir("test");
+    std::fs::write(dir.join("echo.deque"), "{ol~}~ lines~").unwrap();
+    std::fs::write(dir.join("echo.in"), "a\nb\n").unwrap();
+    std::fs::write(dir.join("echo.expected"), "a\nb\n").unwrap();
+    std::fs::write(dir.join("runs.deque"), "1~ 2~ +~").unwrap();
+    let output = rusty_deque(&["test", dir.to_str().unwrap()], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with("2 passed, 0 failed\n"));
+
+    std::fs::write(dir.join("wrong.deque"), "\"c\"~ ol~").unwrap();
+    std::fs::write(dir.join("wrong.expected"), "d\n").unwrap();
+    let output = rusty_deque(&["test", dir.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("FAIL"));
+    assert!(stdout(&output).ends_with("2 passed, 1 failed\n"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_fmt() {
+    let output = rusty_deque(&["fmt", "-c", "1~2~ +~"], "");
+    assert_eq!(stdout(&output), "1~ 2~ +~\n");
+    assert_eq!(output.status.code(), Some(0));
+
+    let dir = scratch_dir("fmt");
+    let script = dir.join("script.deque");
+    let script = script.to_str().unwrap();
+    std::fs::write(script, "{1~\n2~}~ ol~").unwrap();
+    let output = rusty_deque(&["fmt", "--check", script], "");
+    assert_eq!(stdout(&output), format!("{}\n", script));
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = rusty_deque(&["fmt", "--write", script], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        std::fs::read_to_string(script).unwrap(),
+        "{\n    1~\n    2~\n}~ ol~\n"
+    );
+    let output = rusty_deque(&["fmt", "--check", script], "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = rusty_deque(&["fmt", "-c", "1~ 2"], "");
+    assert_eq!(output.status.code(), Some(3));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
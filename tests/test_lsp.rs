@@ -0,0 +1,222 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{ChildStdout, Command, Stdio};
+
+use serde_json::{json, Value};
+
+fn send(input: &mut impl Write, message: Value) {
+    let body = message.to_string();
+    write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+}
+
+fn receive(output: &mut BufReader<ChildStdout>) -> Value {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        output.read_line(&mut header).unwrap();
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    output.read_exact(&mut body).unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn request(id: i64, method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+#[test]
+fn test_lsp_session() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    let mut output = BufReader::new(child.stdout.take().unwrap());
+    let uri = "file:///test.deque";
+    let doc = json!({"uri": uri});
+
+    send(&mut input, request(1, "initialize", json!({})));
+    let reply = receive(&mut output);
+    assert_eq!(reply["id"], 1);
+    assert_eq!(reply["result"]["capabilities"]["hoverProvider"], true);
+
+    // a parse error is reported at the token it was found at
+    let text = "1~ 2~ +~\n{dup~ 3";
+    send(
+        &mut input,
+        notification(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": uri, "languageId": "deque", "version": 1, "text": text}}),
+        ),
+    );
+    let diagnostics = receive(&mut output);
+    assert_eq!(diagnostics["method"], "textDocument/publishDiagnostics");
+    let diagnostic = &diagnostics["params"]["diagnostics"][0];
+    assert_eq!(diagnostic["severity"], 1);
+    assert_eq!(
+        diagnostic["range"]["start"],
+        json!({"line": 1, "character": 7})
+    );
+
+    // a literal too large for its type is a parse error, not a crash
+    let text = "1~ 99999999999999999999~";
+    send(
+        &mut input,
+        notification(
+            "textDocument/didChange",
+            json!({"textDocument": {"uri": uri, "version": 2}, "contentChanges": [{"text": text}]}),
+        ),
+    );
+    let diagnostics = receive(&mut output);
+    let diagnostic = &diagnostics["params"]["diagnostics"][0];
+    assert_eq!(diagnostic["severity"], 1);
+    assert_eq!(
+        diagnostic["range"]["start"],
+        json!({"line": 0, "character": 3})
+    );
+
+    // unknown instructions and the checker's findings are warnings
+    let text = "{dup~  frob~}~ ol~ 'a'~ 1~ +~";
+    send(
+        &mut input,
+        notification(
+            "textDocument/didChange",
+            json!({"textDocument": {"uri": uri, "version": 2}, "contentChanges": [{"text": text}]}),
+        ),
+    );
+    let diagnostics = receive(&mut output);
+    let diagnostic = &diagnostics["params"]["diagnostics"][0];
+    assert_eq!(diagnostic["severity"], 2);
+    assert_eq!(diagnostic["message"], "Unknown instruction: frob");
+    let diagnostic = &diagnostics["params"]["diagnostics"][1];
+    assert_eq!(diagnostic["severity"], 2);
+    assert_eq!(diagnostic["message"], "`+~` expects a number, got Char");
+    assert_eq!(
+        diagnostic["range"],
+        json!({"start": {"line": 0, "character": 27}, "end": {"line": 0, "character": 29}})
+    );
+
+    send(
+        &mut input,
+        request(
+            2,
+            "textDocument/hover",
+            json!({"textDocument": doc, "position": {"line": 0, "character": 2}}),
+        ),
+    );
+    let hover = receive(&mut output);
+    assert!(hover["result"]["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .starts_with("`dup` `( a -- a a )`"));
+
+    send(
+        &mut input,
+        request(3, "textDocument/completion", json!({"textDocument": doc})),
+    );
+    let completion = receive(&mut output);
+    let labels: Vec<&str> = completion["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    assert!(labels.contains(&"range") && labels.contains(&"printf"));
+
+    send(
+        &mut input,
+        request(
+            4,
+            "textDocument/documentHighlight",
+            json!({"textDocument": doc, "position": {"line": 0, "character": 0}}),
+        ),
+    );
+    let highlight = receive(&mut output);
+    assert_eq!(
+        highlight["result"][1]["range"]["start"],
+        json!({"line": 0, "character": 12})
+    );
+
+    send(
+        &mut input,
+        request(
+            5,
+            "textDocument/formatting",
+            json!({"textDocument": doc, "options": {"tabSize": 4, "insertSpaces": true}}),
+        ),
+    );
+    let formatting = receive(&mut output);
+    assert_eq!(
+        formatting["result"][0]["newText"],
+        "{dup~ frob~}~ ol~ 'a'~ 1~ +~\n"
+    );
+
+    send(&mut input, request(6, "textDocument/definition", json!({})));
+    let unsupported = receive(&mut output);
+    assert_eq!(unsupported["error"]["code"], -32601);
+
+    send(&mut input, request(7, "shutdown", Value::Null));
+    assert_eq!(receive(&mut output)["id"], 7);
+    send(&mut input, notification("exit", Value::Null));
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn test_lsp_host_fns() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    let mut output = BufReader::new(child.stdout.take().unwrap());
+    let uri = "file:///host.deque";
+
+    // the client names the host functions its interpreter registers
+    let options = json!({"initializationOptions": {"instructions": ["frob"]}});
+    send(&mut input, request(1, "initialize", options));
+    assert_eq!(receive(&mut output)["id"], 1);
+
+    send(
+        &mut input,
+        notification(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": uri, "languageId": "deque", "version": 1, "text": "1~ frob~ blah~"}}),
+        ),
+    );
+    let diagnostics = receive(&mut output);
+    let diagnostics = diagnostics["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["message"], "Unknown instruction: blah");
+
+    send(
+        &mut input,
+        request(
+            2,
+            "textDocument/completion",
+            json!({"textDocument": {"uri": uri}}),
+        ),
+    );
+    let completion = receive(&mut output);
+    assert!(completion["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|item| item["label"] == "frob"));
+
+    send(&mut input, request(3, "shutdown", Value::Null));
+    assert_eq!(receive(&mut output)["id"], 3);
+    send(&mut input, notification("exit", Value::Null));
+    assert!(child.wait().unwrap().success());
+}